@@ -62,9 +62,7 @@ impl HttpClient {
             received.extend_from_slice(&buffer[..bytes_read]);
         }
 
-        match core::str::from_utf8(&received) {
-            Ok(response) => HttpResponse::new(response.to_string()),
-            Err(e) => return Err(Error::Network(format!("Invalid received response: {}", e))),
-        }
+        // The body is decoded by the page, as it may be in an encoding other than UTF-8.
+        HttpResponse::from_bytes(received)
     }
 }
@@ -0,0 +1,601 @@
+//! The decoders of the Encoding Standard. Each decoder handles one byte at a time, so input
+//! may be split into chunks anywhere.
+//! https://encoding.spec.whatwg.org/#decoders
+
+use crate::encoding::index_jis0208;
+use crate::encoding::index_jis0212;
+use crate::encoding::windows_1252_code_point;
+use crate::encoding::Encoding;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Iso2022JpState {
+    Ascii,
+    Roman,
+    Katakana,
+    LeadByte,
+    TrailByte,
+    EscapeStart,
+    Escape,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoder {
+    encoding: Encoding,
+    /// True until the first code point is decoded. A leading U+FEFF is the byte order mark and
+    /// is dropped.
+    at_start: bool,
+    // UTF-8
+    code_point: u32,
+    bytes_seen: u8,
+    bytes_needed: u8,
+    lower_boundary: u8,
+    upper_boundary: u8,
+    // UTF-16
+    lead_byte: Option<u8>,
+    lead_surrogate: Option<u16>,
+    // Shift_JIS, EUC-JP and ISO-2022-JP
+    lead: u8,
+    // EUC-JP
+    jis0212: bool,
+    // ISO-2022-JP
+    state: Iso2022JpState,
+    output_state: Iso2022JpState,
+    output: bool,
+}
+
+impl Decoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            at_start: true,
+            code_point: 0,
+            bytes_seen: 0,
+            bytes_needed: 0,
+            lower_boundary: 0x80,
+            upper_boundary: 0xBF,
+            lead_byte: None,
+            lead_surrogate: None,
+            lead: 0,
+            jis0212: false,
+            state: Iso2022JpState::Ascii,
+            output_state: Iso2022JpState::Ascii,
+            output: false,
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Decodes a chunk of bytes. A character split across chunks is returned with the chunk
+    /// that completes it.
+    pub fn decode(&mut self, chunk: &[u8]) -> String {
+        let mut out = String::new();
+        let mut queue: VecDeque<u8> = chunk.iter().copied().collect();
+        while let Some(byte) = queue.pop_front() {
+            self.handle(Some(byte), &mut queue, &mut out);
+        }
+        self.strip_bom(out)
+    }
+
+    /// Marks the end of the input, and returns what the bytes held for an incomplete character
+    /// decode to.
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        let mut queue = VecDeque::new();
+        loop {
+            self.handle(None, &mut queue, &mut out);
+            if queue.is_empty() {
+                break;
+            }
+            // The end of the input is handled again after the bytes put back are processed.
+            while let Some(byte) = queue.pop_front() {
+                self.handle(Some(byte), &mut queue, &mut out);
+            }
+        }
+        self.strip_bom(out)
+    }
+
+    fn strip_bom(&mut self, out: String) -> String {
+        if !self.at_start || out.is_empty() {
+            return out;
+        }
+        self.at_start = false;
+        match out.strip_prefix('\u{FEFF}') {
+            Some(rest)
+                if matches!(
+                    self.encoding,
+                    Encoding::Utf8 | Encoding::Utf16Be | Encoding::Utf16Le
+                ) =>
+            {
+                String::from(rest)
+            }
+            _ => out,
+        }
+    }
+
+    /// Handles a byte, or the end of the input if `byte` is None. Bytes that need to be
+    /// processed again are pushed to the front of `queue`.
+    fn handle(&mut self, byte: Option<u8>, queue: &mut VecDeque<u8>, out: &mut String) {
+        match self.encoding {
+            Encoding::Utf8 => self.handle_utf8(byte, queue, out),
+            Encoding::Utf16Be => self.handle_utf16(byte, true, queue, out),
+            Encoding::Utf16Le => self.handle_utf16(byte, false, queue, out),
+            Encoding::ShiftJis => self.handle_shift_jis(byte, queue, out),
+            Encoding::EucJp => self.handle_euc_jp(byte, queue, out),
+            Encoding::Iso2022Jp => self.handle_iso_2022_jp(byte, queue, out),
+            Encoding::Windows1252 => {
+                if let Some(b) = byte {
+                    out.push(windows_1252_code_point(b));
+                }
+            }
+        }
+    }
+
+    /// https://encoding.spec.whatwg.org/#utf-8-decoder
+    fn handle_utf8(&mut self, byte: Option<u8>, queue: &mut VecDeque<u8>, out: &mut String) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.bytes_needed != 0 {
+                    self.bytes_needed = 0;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+                return;
+            }
+        };
+
+        if self.bytes_needed == 0 {
+            match b {
+                0x00..=0x7F => out.push(b as char),
+                0xC2..=0xDF => {
+                    self.bytes_needed = 1;
+                    self.code_point = (b & 0x1F) as u32;
+                }
+                0xE0..=0xEF => {
+                    if b == 0xE0 {
+                        self.lower_boundary = 0xA0;
+                    }
+                    if b == 0xED {
+                        self.upper_boundary = 0x9F;
+                    }
+                    self.bytes_needed = 2;
+                    self.code_point = (b & 0x0F) as u32;
+                }
+                0xF0..=0xF4 => {
+                    if b == 0xF0 {
+                        self.lower_boundary = 0x90;
+                    }
+                    if b == 0xF4 {
+                        self.upper_boundary = 0x8F;
+                    }
+                    self.bytes_needed = 3;
+                    self.code_point = (b & 0x07) as u32;
+                }
+                _ => out.push(char::REPLACEMENT_CHARACTER),
+            }
+            return;
+        }
+
+        if b < self.lower_boundary || b > self.upper_boundary {
+            self.code_point = 0;
+            self.bytes_needed = 0;
+            self.bytes_seen = 0;
+            self.lower_boundary = 0x80;
+            self.upper_boundary = 0xBF;
+            queue.push_front(b);
+            out.push(char::REPLACEMENT_CHARACTER);
+            return;
+        }
+
+        self.lower_boundary = 0x80;
+        self.upper_boundary = 0xBF;
+        self.code_point = (self.code_point << 6) | (b & 0x3F) as u32;
+        self.bytes_seen += 1;
+        if self.bytes_seen == self.bytes_needed {
+            out.push(char::from_u32(self.code_point).unwrap_or(char::REPLACEMENT_CHARACTER));
+            self.code_point = 0;
+            self.bytes_needed = 0;
+            self.bytes_seen = 0;
+        }
+    }
+
+    /// https://encoding.spec.whatwg.org/#shared-utf-16-decoder
+    fn handle_utf16(
+        &mut self,
+        byte: Option<u8>,
+        big_endian: bool,
+        queue: &mut VecDeque<u8>,
+        out: &mut String,
+    ) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.lead_byte.is_some() || self.lead_surrogate.is_some() {
+                    self.lead_byte = None;
+                    self.lead_surrogate = None;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+                return;
+            }
+        };
+
+        let lead_byte = match self.lead_byte.take() {
+            Some(lead_byte) => lead_byte,
+            None => {
+                self.lead_byte = Some(b);
+                return;
+            }
+        };
+        let code_unit = if big_endian {
+            ((lead_byte as u16) << 8) | b as u16
+        } else {
+            ((b as u16) << 8) | lead_byte as u16
+        };
+
+        if let Some(lead_surrogate) = self.lead_surrogate.take() {
+            if (0xDC00..=0xDFFF).contains(&code_unit) {
+                let c = 0x10000
+                    + ((lead_surrogate as u32 - 0xD800) << 10)
+                    + (code_unit as u32 - 0xDC00);
+                out.push(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+            } else {
+                // The code unit is processed again as the start of a new character.
+                let [high, low] = code_unit.to_be_bytes();
+                if big_endian {
+                    queue.push_front(low);
+                    queue.push_front(high);
+                } else {
+                    queue.push_front(high);
+                    queue.push_front(low);
+                }
+                out.push(char::REPLACEMENT_CHARACTER);
+            }
+            return;
+        }
+
+        match code_unit {
+            0xD800..=0xDBFF => self.lead_surrogate = Some(code_unit),
+            0xDC00..=0xDFFF => out.push(char::REPLACEMENT_CHARACTER),
+            _ => out.push(char::from_u32(code_unit as u32).unwrap_or(char::REPLACEMENT_CHARACTER)),
+        }
+    }
+
+    /// https://encoding.spec.whatwg.org/#shift_jis-decoder
+    fn handle_shift_jis(&mut self, byte: Option<u8>, queue: &mut VecDeque<u8>, out: &mut String) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.lead != 0 {
+                    self.lead = 0;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+                return;
+            }
+        };
+
+        if self.lead != 0 {
+            let lead = self.lead as usize;
+            self.lead = 0;
+            let offset = if b < 0x7F { 0x40 } else { 0x41 };
+            let lead_offset = if lead < 0xA0 { 0x81 } else { 0xC1 };
+            if matches!(b, 0x40..=0x7E | 0x80..=0xFC) {
+                let pointer = (lead - lead_offset) * 188 + b as usize - offset;
+                // The user-defined area is mapped to the Private Use Area.
+                if (8836..=10715).contains(&pointer) {
+                    out.push(char::from_u32(0xE000 - 8836 + pointer as u32).unwrap_or_default());
+                    return;
+                }
+                if let Some(c) = index_jis0208::code_point(pointer) {
+                    out.push(c);
+                    return;
+                }
+            }
+            if b.is_ascii() {
+                queue.push_front(b);
+            }
+            out.push(char::REPLACEMENT_CHARACTER);
+            return;
+        }
+
+        match b {
+            0x00..=0x80 => out.push(b as char),
+            0xA1..=0xDF => out.push(halfwidth_katakana(b - 0xA1)),
+            0x81..=0x9F | 0xE0..=0xFC => self.lead = b,
+            _ => out.push(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    /// https://encoding.spec.whatwg.org/#euc-jp-decoder
+    fn handle_euc_jp(&mut self, byte: Option<u8>, queue: &mut VecDeque<u8>, out: &mut String) {
+        let b = match byte {
+            Some(b) => b,
+            None => {
+                if self.lead != 0 {
+                    self.lead = 0;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+                return;
+            }
+        };
+
+        if self.lead == 0x8E && (0xA1..=0xDF).contains(&b) {
+            self.lead = 0;
+            out.push(halfwidth_katakana(b - 0xA1));
+            return;
+        }
+        if self.lead == 0x8F && (0xA1..=0xFE).contains(&b) {
+            self.jis0212 = true;
+            self.lead = b;
+            return;
+        }
+        if self.lead != 0 {
+            let lead = self.lead;
+            self.lead = 0;
+            let mut c = None;
+            if (0xA1..=0xFE).contains(&lead) && (0xA1..=0xFE).contains(&b) {
+                let pointer = (lead as usize - 0xA1) * 94 + b as usize - 0xA1;
+                c = if self.jis0212 {
+                    index_jis0212::code_point(pointer)
+                } else {
+                    index_jis0208::code_point(pointer)
+                };
+            }
+            self.jis0212 = false;
+            match c {
+                Some(c) => out.push(c),
+                None => {
+                    if b.is_ascii() {
+                        queue.push_front(b);
+                    }
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            }
+            return;
+        }
+
+        match b {
+            0x00..=0x7F => out.push(b as char),
+            0x8E | 0x8F | 0xA1..=0xFE => self.lead = b,
+            _ => out.push(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    /// https://encoding.spec.whatwg.org/#iso-2022-jp-decoder
+    fn handle_iso_2022_jp(&mut self, byte: Option<u8>, queue: &mut VecDeque<u8>, out: &mut String) {
+        match self.state {
+            Iso2022JpState::Ascii => match byte {
+                Some(0x1B) => self.state = Iso2022JpState::EscapeStart,
+                Some(b @ 0x00..=0x7F) if !matches!(b, 0x0E | 0x0F) => {
+                    self.output = false;
+                    out.push(b as char);
+                }
+                None => {}
+                Some(_) => {
+                    self.output = false;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            Iso2022JpState::Roman => match byte {
+                Some(0x1B) => self.state = Iso2022JpState::EscapeStart,
+                Some(0x5C) => {
+                    self.output = false;
+                    out.push('\u{A5}');
+                }
+                Some(0x7E) => {
+                    self.output = false;
+                    out.push('\u{203E}');
+                }
+                Some(b @ 0x00..=0x7F) if !matches!(b, 0x0E | 0x0F) => {
+                    self.output = false;
+                    out.push(b as char);
+                }
+                None => {}
+                Some(_) => {
+                    self.output = false;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            Iso2022JpState::Katakana => match byte {
+                Some(0x1B) => self.state = Iso2022JpState::EscapeStart,
+                Some(b @ 0x21..=0x5F) => {
+                    self.output = false;
+                    out.push(halfwidth_katakana(b - 0x21));
+                }
+                None => {}
+                Some(_) => {
+                    self.output = false;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            Iso2022JpState::LeadByte => match byte {
+                Some(0x1B) => self.state = Iso2022JpState::EscapeStart,
+                Some(b @ 0x21..=0x7E) => {
+                    self.output = false;
+                    self.lead = b;
+                    self.state = Iso2022JpState::TrailByte;
+                }
+                None => {}
+                Some(_) => {
+                    self.output = false;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            Iso2022JpState::TrailByte => match byte {
+                Some(0x1B) => {
+                    self.state = Iso2022JpState::EscapeStart;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+                Some(b @ 0x21..=0x7E) => {
+                    self.state = Iso2022JpState::LeadByte;
+                    let pointer = (self.lead as usize - 0x21) * 94 + b as usize - 0x21;
+                    out.push(
+                        index_jis0208::code_point(pointer).unwrap_or(char::REPLACEMENT_CHARACTER),
+                    );
+                }
+                _ => {
+                    self.state = Iso2022JpState::LeadByte;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            Iso2022JpState::EscapeStart => match byte {
+                Some(b @ (0x24 | 0x28)) => {
+                    self.lead = b;
+                    self.state = Iso2022JpState::Escape;
+                }
+                _ => {
+                    if let Some(b) = byte {
+                        queue.push_front(b);
+                    }
+                    self.output = false;
+                    self.state = self.output_state;
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            Iso2022JpState::Escape => {
+                let lead = self.lead;
+                self.lead = 0;
+                let state = match (lead, byte) {
+                    (0x28, Some(0x42)) => Some(Iso2022JpState::Ascii),
+                    (0x28, Some(0x4A)) => Some(Iso2022JpState::Roman),
+                    (0x28, Some(0x49)) => Some(Iso2022JpState::Katakana),
+                    (0x24, Some(0x40 | 0x42)) => Some(Iso2022JpState::LeadByte),
+                    _ => None,
+                };
+                match state {
+                    Some(state) => {
+                        self.state = state;
+                        self.output_state = state;
+                        // An escape sequence right after another one is an error.
+                        let output = self.output;
+                        self.output = true;
+                        if output {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                        }
+                    }
+                    None => {
+                        if let Some(b) = byte {
+                            queue.push_front(b);
+                        }
+                        queue.push_front(lead);
+                        self.output = false;
+                        self.state = self.output_state;
+                        out.push(char::REPLACEMENT_CHARACTER);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the character at `offset` from U+FF61 HALFWIDTH IDEOGRAPHIC FULL STOP.
+fn halfwidth_katakana(offset: u8) -> char {
+    char::from_u32(0xFF61 + offset as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes `bytes` split at every position, and checks that the results match decoding
+    /// at once.
+    fn decode_in_chunks(encoding: Encoding, bytes: &[u8]) -> String {
+        let expected = encoding.decode(bytes);
+        for i in 0..=bytes.len() {
+            let mut decoder = Decoder::new(encoding);
+            let mut s = decoder.decode(&bytes[..i]);
+            s.push_str(&decoder.decode(&bytes[i..]));
+            s.push_str(&decoder.finish());
+            assert_eq!(expected, s, "split at {}", i);
+        }
+        expected
+    }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(
+            "a\u{E9}\u{3042}\u{1F600}",
+            decode_in_chunks(
+                Encoding::Utf8,
+                "\u{FEFF}a\u{E9}\u{3042}\u{1F600}".as_bytes()
+            )
+        );
+        assert_eq!(
+            "\u{FFFD}a\u{FFFD}\u{FFFD}\u{FFFD}",
+            decode_in_chunks(Encoding::Utf8, b"\xC3a\xED\xA0\x80")
+        );
+        assert_eq!("a\u{FFFD}", decode_in_chunks(Encoding::Utf8, b"a\xE3\x81"));
+    }
+
+    #[test]
+    fn test_utf16() {
+        assert_eq!(
+            "a\u{3042}\u{1F600}",
+            decode_in_chunks(Encoding::Utf16Le, b"\xFF\xFEa\x00\x42\x30\x3D\xD8\x00\xDE")
+        );
+        assert_eq!(
+            "a\u{3042}\u{1F600}",
+            decode_in_chunks(Encoding::Utf16Be, b"\xFE\xFF\x00a\x30\x42\xD8\x3D\xDE\x00")
+        );
+        // A lead surrogate followed by another character, and an odd byte at the end.
+        assert_eq!(
+            "\u{FFFD}a\u{FFFD}",
+            decode_in_chunks(Encoding::Utf16Be, b"\xD8\x3D\x00a\x00")
+        );
+    }
+
+    #[test]
+    fn test_shift_jis() {
+        // "日本語" and half-width katakana "ｱ".
+        assert_eq!(
+            "a\u{65E5}\u{672C}\u{8A9E}\u{FF71}",
+            decode_in_chunks(Encoding::ShiftJis, b"a\x93\xFA\x96\x7B\x8C\xEA\xB1")
+        );
+        assert_eq!(
+            "\u{E000}",
+            decode_in_chunks(Encoding::ShiftJis, b"\xF0\x40")
+        );
+        // An invalid trail byte in ASCII is decoded again.
+        assert_eq!("\u{FFFD}<", decode_in_chunks(Encoding::ShiftJis, b"\x93<"));
+        assert_eq!("\u{FFFD}", decode_in_chunks(Encoding::ShiftJis, b"\x93"));
+    }
+
+    #[test]
+    fn test_euc_jp() {
+        assert_eq!(
+            "a\u{65E5}\u{672C}\u{8A9E}\u{FF71}",
+            decode_in_chunks(Encoding::EucJp, b"a\xC6\xFC\xCB\xDC\xB8\xEC\x8E\xB1")
+        );
+        // JIS X 0212.
+        assert_eq!(
+            "\u{4E02}",
+            decode_in_chunks(Encoding::EucJp, b"\x8F\xB0\xA1")
+        );
+        assert_eq!("\u{FFFD}<", decode_in_chunks(Encoding::EucJp, b"\xC6<"));
+    }
+
+    #[test]
+    fn test_iso_2022_jp() {
+        assert_eq!(
+            "a\u{65E5}\u{672C}\u{8A9E}b",
+            decode_in_chunks(Encoding::Iso2022Jp, b"a\x1B$BF|K\\8l\x1B(Bb")
+        );
+        // Roman and katakana.
+        assert_eq!(
+            "\u{A5}\u{FF71}",
+            decode_in_chunks(Encoding::Iso2022Jp, b"\x1B(J\\\x1B(I1\x1B(B")
+        );
+        // An unknown escape sequence is decoded in the current state.
+        assert_eq!(
+            "\u{FFFD}$A",
+            decode_in_chunks(Encoding::Iso2022Jp, b"\x1B$A")
+        );
+        // Two escape sequences in a row.
+        assert_eq!(
+            "\u{FFFD}a",
+            decode_in_chunks(Encoding::Iso2022Jp, b"\x1B$B\x1B(Ba")
+        );
+    }
+}
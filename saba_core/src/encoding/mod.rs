@@ -0,0 +1,133 @@
+//! https://encoding.spec.whatwg.org/
+
+pub mod decoder;
+mod index_jis0208;
+mod index_jis0212;
+
+use crate::encoding::decoder::Decoder;
+use alloc::string::String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Be,
+    Utf16Le,
+    ShiftJis,
+    EucJp,
+    Iso2022Jp,
+    Windows1252,
+}
+
+impl Encoding {
+    /// Returns the encoding for a label such as "utf8" or "Shift_JIS", or None if the label is
+    /// unknown or names an encoding that isn't supported.
+    /// https://encoding.spec.whatwg.org/#concept-encoding-get
+    pub fn for_label(label: &str) -> Option<Self> {
+        let label = label
+            .trim_matches(|c| matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' '))
+            .to_ascii_lowercase();
+        let encoding = match label.as_str() {
+            "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8"
+            | "x-unicode20utf8" => Encoding::Utf8,
+            "unicodefffe" | "utf-16be" => Encoding::Utf16Be,
+            "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff" | "utf-16"
+            | "utf-16le" => Encoding::Utf16Le,
+            "csshiftjis" | "ms932" | "ms_kanji" | "shift-jis" | "shift_jis" | "sjis"
+            | "windows-31j" | "x-sjis" => Encoding::ShiftJis,
+            "cseucpkdfmtjapanese" | "euc-jp" | "x-euc-jp" => Encoding::EucJp,
+            "csiso2022jp" | "iso-2022-jp" => Encoding::Iso2022Jp,
+            "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819"
+            | "iso-8859-1" | "iso-ir-100" | "iso8859-1" | "iso88591" | "iso_8859-1"
+            | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii" | "windows-1252" | "x-cp1252" => {
+                Encoding::Windows1252
+            }
+            _ => return None,
+        };
+        Some(encoding)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::ShiftJis => "Shift_JIS",
+            Encoding::EucJp => "EUC-JP",
+            Encoding::Iso2022Jp => "ISO-2022-JP",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Decodes the whole `bytes` at once. Errors are replaced with U+FFFD.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let mut decoder = Decoder::new(*self);
+        let mut s = decoder.decode(bytes);
+        s.push_str(&decoder.finish());
+        s
+    }
+}
+
+/// Returns the code point for a byte in windows-1252. Bytes outside 0x80-0x9F are the same as
+/// in ISO-8859-1.
+/// https://encoding.spec.whatwg.org/index-windows-1252.txt
+pub fn windows_1252_code_point(byte: u8) -> char {
+    match byte {
+        0x80 => '\u{20AC}',
+        0x82 => '\u{201A}',
+        0x83 => '\u{0192}',
+        0x84 => '\u{201E}',
+        0x85 => '\u{2026}',
+        0x86 => '\u{2020}',
+        0x87 => '\u{2021}',
+        0x88 => '\u{02C6}',
+        0x89 => '\u{2030}',
+        0x8A => '\u{0160}',
+        0x8B => '\u{2039}',
+        0x8C => '\u{0152}',
+        0x8E => '\u{017D}',
+        0x91 => '\u{2018}',
+        0x92 => '\u{2019}',
+        0x93 => '\u{201C}',
+        0x94 => '\u{201D}',
+        0x95 => '\u{2022}',
+        0x96 => '\u{2013}',
+        0x97 => '\u{2014}',
+        0x98 => '\u{02DC}',
+        0x99 => '\u{2122}',
+        0x9A => '\u{0161}',
+        0x9B => '\u{203A}',
+        0x9C => '\u{0153}',
+        0x9E => '\u{017E}',
+        0x9F => '\u{0178}',
+        b => b as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_label() {
+        assert_eq!(Some(Encoding::Utf8), Encoding::for_label("utf-8"));
+        assert_eq!(
+            Some(Encoding::ShiftJis),
+            Encoding::for_label(" Shift_JIS\n")
+        );
+        assert_eq!(Some(Encoding::EucJp), Encoding::for_label("EUC-JP"));
+        assert_eq!(
+            Some(Encoding::Windows1252),
+            Encoding::for_label("iso-8859-1")
+        );
+        assert_eq!(Some(Encoding::Utf16Le), Encoding::for_label("utf-16"));
+        assert_eq!(None, Encoding::for_label("utf-7"));
+    }
+
+    #[test]
+    fn test_windows_1252() {
+        assert_eq!(
+            "a\u{20AC}\u{81}\u{FF}",
+            Encoding::Windows1252.decode(b"a\x80\x81\xFF")
+        );
+    }
+}
@@ -11,6 +11,7 @@ pub struct HttpResponse {
     reason: String,
     headers: Vec<Header>,
     body: String,
+    body_bytes: Vec<u8>,
 }
 
 #[derive(Debug, Clone)]
@@ -61,9 +62,34 @@ impl HttpResponse {
             reason: statuees[2].to_string(),
             headers,
             body: body.to_string(),
+            body_bytes: body.as_bytes().to_vec(),
         })
     }
 
+    /// Parses a response whose body may be in an encoding other than UTF-8. The status line
+    /// and headers are parsed as text, and the body is kept as bytes for the page to decode.
+    pub fn from_bytes(raw_response: Vec<u8>) -> Result<Self, Error> {
+        let body_start = raw_response
+            .windows(2)
+            .enumerate()
+            .find_map(|(i, w)| match w {
+                b"\n\n" => Some(i + 2),
+                b"\n\r" if raw_response.get(i + 2) == Some(&b'\n') => Some(i + 3),
+                _ => None,
+            });
+
+        let body_start = match body_start {
+            Some(body_start) => body_start,
+            None => return Self::new(String::from_utf8_lossy(&raw_response).into_owned()),
+        };
+
+        let mut response =
+            Self::new(String::from_utf8_lossy(&raw_response[..body_start]).into_owned())?;
+        response.body_bytes = raw_response[body_start..].to_vec();
+        response.body = String::from_utf8_lossy(&response.body_bytes).into_owned();
+        Ok(response)
+    }
+
     pub fn version(&self) -> String {
         self.version.clone()
     }
@@ -81,13 +107,18 @@ impl HttpResponse {
         self.headers.clone()
     }
 
+    /// Returns the body decoded as UTF-8. Use `body_bytes()` for a body in other encodings.
     pub fn body(&self) -> String {
         self.body.clone()
     }
 
+    pub fn body_bytes(&self) -> Vec<u8> {
+        self.body_bytes.clone()
+    }
+
     pub fn header_value(&self, name: &str) -> Result<String, String> {
         for header in &self.headers {
-            if header.name == name {
+            if header.name.eq_ignore_ascii_case(name) {
                 return Ok(header.value.clone());
             }
         }
@@ -149,6 +180,33 @@ mod tests {
         assert_eq!(res.body, "body message".to_string());
     }
 
+    #[test]
+    fn test_body_bytes() {
+        let mut raw =
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=Shift_JIS\r\n\r\n".to_vec();
+        raw.extend_from_slice(b"\x93\xFA\x96\x7B");
+
+        let res = HttpResponse::from_bytes(raw).expect("failed to parse http response");
+
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(
+            res.header_value("Content-Type"),
+            Ok("text/html; charset=Shift_JIS".to_string())
+        );
+        assert_eq!(res.body_bytes(), b"\x93\xFA\x96\x7B".to_vec());
+
+        let mut raw = b"HTTP/1.1 200 OK\ncontent-type: text/html; charset=Shift_JIS\n\n".to_vec();
+        raw.extend_from_slice(b"\x93\xFA\x96\x7B");
+
+        let res = HttpResponse::from_bytes(raw).expect("failed to parse http response");
+
+        assert_eq!(
+            res.header_value("Content-Type"),
+            Ok("text/html; charset=Shift_JIS".to_string())
+        );
+        assert_eq!(res.body_bytes(), b"\x93\xFA\x96\x7B".to_vec());
+    }
+
     #[test]
     fn test_invalid() {
         let raw = "HTTP/1.1 200 OK".to_string();
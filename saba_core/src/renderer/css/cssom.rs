@@ -1,6 +1,8 @@
 use crate::alloc::string::ToString;
+use crate::error::Error;
 use crate::renderer::css::token::CssToken;
 use crate::renderer::css::token::CssTokenizer;
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::iter::Peekable;
@@ -221,6 +223,100 @@ pub enum Selector {
     UnknownSelector,
 }
 
+/// https://www.w3.org/TR/selectors-4/#combinators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// Whitespace, e.g. "div p".
+    Descendant,
+    /// ">", e.g. "div > p".
+    Child,
+}
+
+/// Compound selectors joined by combinators, e.g. "div > p.note". A compound selector is a
+/// list of simple selectors that an element must match all of, and an empty one is the
+/// universal selector "*".
+/// https://www.w3.org/TR/selectors-4/#complex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplexSelector {
+    pub compounds: Vec<Vec<Selector>>,
+    /// The combinators between `compounds`, which has one less element than `compounds`.
+    pub combinators: Vec<Combinator>,
+}
+
+/// Parses a comma-separated list of selectors such as "h1, div > p.note". Only type, class, id
+/// and universal selectors with the descendant and child combinators are supported.
+/// https://www.w3.org/TR/selectors-4/#parse-a-selector
+pub fn parse_selector_list(selectors: &str) -> Result<Vec<ComplexSelector>, Error> {
+    let error = || Error::UnexpectedInput(format!("invalid selector: {}", selectors));
+
+    let mut list = Vec::new();
+    for selector in selectors.split(',') {
+        let mut complex = ComplexSelector {
+            compounds: Vec::new(),
+            combinators: Vec::new(),
+        };
+        let mut combinator = None;
+        // Surround ">" with spaces so that all parts are separated by whitespace.
+        for part in selector.replace('>', " > ").split_ascii_whitespace() {
+            if part == ">" {
+                if complex.compounds.is_empty() || combinator.is_some() {
+                    return Err(error());
+                }
+                combinator = Some(Combinator::Child);
+                continue;
+            }
+            if !complex.compounds.is_empty() {
+                complex
+                    .combinators
+                    .push(combinator.take().unwrap_or(Combinator::Descendant));
+            }
+            complex
+                .compounds
+                .push(parse_compound_selector(part).ok_or_else(error)?);
+        }
+        if complex.compounds.is_empty() || combinator.is_some() {
+            return Err(error());
+        }
+        list.push(complex);
+    }
+    Ok(list)
+}
+
+/// Parses a compound selector without whitespace, e.g. "p.note#first".
+fn parse_compound_selector(compound: &str) -> Option<Vec<Selector>> {
+    let (universal, rest) = match compound.strip_prefix('*') {
+        Some(rest) => (true, rest),
+        None => (false, compound),
+    };
+    // The tokenizer doesn't support other characters yet.
+    if !rest
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '#' | '.'))
+    {
+        return None;
+    }
+
+    let mut t = CssTokenizer::new(rest.to_string());
+    let mut selectors = Vec::new();
+    while let Some(token) = t.next() {
+        let selector = match token {
+            CssToken::Ident(name) if selectors.is_empty() && !universal => {
+                Selector::TypeSelector(name)
+            }
+            CssToken::HashToken(value) if value.len() > 1 => {
+                Selector::IdSelector(value[1..].to_string())
+            }
+            CssToken::Delim('.') => match t.next() {
+                Some(CssToken::Ident(name)) => Selector::ClassSelector(name),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        selectors.push(selector);
+    }
+    Some(selectors)
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
     pub property: String,
@@ -329,6 +425,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_selector_list() {
+        let expected = vec![
+            ComplexSelector {
+                compounds: vec![
+                    vec![Selector::TypeSelector("div".to_string())],
+                    vec![],
+                    vec![
+                        Selector::TypeSelector("p".to_string()),
+                        Selector::ClassSelector("a".to_string()),
+                        Selector::IdSelector("b".to_string()),
+                    ],
+                ],
+                combinators: vec![Combinator::Child, Combinator::Descendant],
+            },
+            ComplexSelector {
+                compounds: vec![vec![Selector::ClassSelector("c".to_string())]],
+                combinators: vec![],
+            },
+        ];
+        assert_eq!(Ok(expected), parse_selector_list("div>* p.a#b , .c"));
+
+        for invalid in [
+            "", "p,", "> p", "p >", "p > > a", "p.", "a[href]", "p:hover", "a*",
+        ] {
+            assert!(parse_selector_list(invalid).is_err(), "{}", invalid);
+        }
+    }
+
     #[test]
     fn test_multiple_rules() {
         let style = "p { content: \"Hey\"; } h1 { font-size: 40; color: blue; }".to_string();
@@ -88,7 +88,10 @@ impl CssTokenizer {
 
         loop {
             self.pos += 1;
-            let c = self.input[self.pos];
+            let c = match self.input.get(self.pos) {
+                Some(c) => *c,
+                None => break,
+            };
             match c {
                 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => s.push(c),
                 _ => break,
@@ -241,6 +244,15 @@ mod tests {
         assert!(t.next().is_none());
     }
 
+    #[test]
+    fn test_ident_at_end() {
+        let mut t = CssTokenizer::new("p.note".to_string());
+        assert_eq!(Some(CssToken::Ident("p".to_string())), t.next());
+        assert_eq!(Some(CssToken::Delim('.')), t.next());
+        assert_eq!(Some(CssToken::Ident("note".to_string())), t.next());
+        assert!(t.next().is_none());
+    }
+
     #[test]
     fn test_multiple_rules() {
         let style = "p { content: \"Hey\"; } h1 { font-size: 40; color: blue; }".to_string();
@@ -1,38 +1,66 @@
+use crate::renderer::css::cssom::parse_selector_list;
+use crate::renderer::css::cssom::Combinator;
+use crate::renderer::css::cssom::ComplexSelector;
+use crate::renderer::css::cssom::Selector;
+use crate::renderer::dom::mutation_observer::queue_attribute_record;
+use crate::renderer::dom::mutation_observer::queue_character_data_record;
+use crate::renderer::dom::mutation_observer::queue_child_list_record;
 use crate::renderer::dom::node::Element;
 use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::node::Namespace;
 use crate::renderer::dom::node::Node;
 use crate::renderer::dom::node::NodeKind;
+use crate::renderer::dom::node::QuirksMode;
+use crate::renderer::dom::traversal::ancestors;
+use crate::renderer::dom::traversal::descendants;
+use crate::renderer::dom::traversal::following_siblings;
+use crate::renderer::dom::traversal::inclusive_descendants;
+use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::attribute::AttributeNamespace;
 use alloc::rc::Rc;
+use alloc::rc::Weak;
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 
+/// An error thrown by a DOM operation.
+/// https://webidl.spec.whatwg.org/#idl-DOMException-error-names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomException {
+    /// The operation would make an invalid tree, e.g. a node inside itself or a document with
+    /// two elements.
+    HierarchyRequestError,
+    /// The reference node is not a child of the parent.
+    NotFoundError,
+    /// A selector string can't be parsed.
+    SyntaxError,
+    /// A name contains a character that isn't allowed, e.g. whitespace in an attribute name.
+    InvalidCharacterError,
+    /// The prefix of a qualified name doesn't match the namespace.
+    NamespaceError,
+    /// The operation is not supported by the node, e.g. setting an attribute of a text node.
+    InvalidNodeTypeError,
+    /// The object can't be used now, e.g. an event that is being dispatched.
+    InvalidStateError,
+    /// The arguments are invalid. This is a TypeError rather than a DOMException in
+    /// JavaScript.
+    TypeError,
+    /// An offset is out of range, e.g. splitting a text node after its end.
+    IndexSizeError,
+}
+
 pub fn get_target_element_node(
     node: Option<Rc<RefCell<Node>>>,
     element_kind: ElementKind,
 ) -> Option<Rc<RefCell<Node>>> {
-    match node {
-        Some(n) => {
-            if n.borrow().kind()
-                == NodeKind::Element(Element::new(&element_kind.to_string(), Vec::new()))
-            {
-                return Some(n.clone());
-            }
-            let result1 = get_target_element_node(n.borrow().first_child(), element_kind);
-            let result2 = get_target_element_node(n.borrow().next_sibling(), element_kind);
-            if result1.is_none() && result2.is_none() {
-                return None;
-            }
-
-            if result1.is_none() {
-                return result2;
-            }
-
-            result1
-        }
-        None => None,
-    }
+    let node = node?;
+    // Look for the element in `node` and its following siblings with their descendants.
+    let siblings = core::iter::once(node.clone()).chain(following_siblings(&node));
+    siblings
+        .flat_map(|n| inclusive_descendants(&n))
+        .find(|n| n.borrow().element_kind() == Some(element_kind))
 }
 
 pub fn get_style_content(root: Rc<RefCell<Node>>) -> String {
@@ -51,3 +79,1449 @@ pub fn get_style_content(root: Rc<RefCell<Node>>) -> String {
     };
     content
 }
+
+/// Appends `node` to the children of `parent` and returns it. See `insert_before()`.
+/// https://dom.spec.whatwg.org/#dom-node-appendchild
+pub fn append_child(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+) -> Result<Rc<RefCell<Node>>, DomException> {
+    insert_before(parent, node, None)
+}
+
+/// Inserts `node` into `parent` before `child`, or at the end if `child` is None, and returns
+/// it. `node` is removed from its current parent first. If `node` is a DocumentFragment, its
+/// children are moved instead.
+/// https://dom.spec.whatwg.org/#dom-node-insertbefore
+pub fn insert_before(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: Option<&Rc<RefCell<Node>>>,
+) -> Result<Rc<RefCell<Node>>, DomException> {
+    ensure_pre_insertion_validity(parent, node, child, false)?;
+    // Inserting a node before itself doesn't move it.
+    let reference = match child {
+        Some(child) if Rc::ptr_eq(child, node) => node.borrow().next_sibling(),
+        _ => child.cloned(),
+    };
+    insert_node_before(parent, node, reference.as_ref());
+    Ok(node.clone())
+}
+
+/// Removes `child` from `parent` and returns it.
+/// https://dom.spec.whatwg.org/#dom-node-removechild
+pub fn remove_child(
+    parent: &Rc<RefCell<Node>>,
+    child: &Rc<RefCell<Node>>,
+) -> Result<Rc<RefCell<Node>>, DomException> {
+    if !is_parent_of(parent, child) {
+        return Err(DomException::NotFoundError);
+    }
+    detach_node(child);
+    Ok(child.clone())
+}
+
+/// Replaces `child` of `parent` with `node` and returns `child`.
+/// https://dom.spec.whatwg.org/#dom-node-replacechild
+pub fn replace_child(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: &Rc<RefCell<Node>>,
+) -> Result<Rc<RefCell<Node>>, DomException> {
+    ensure_pre_insertion_validity(parent, node, Some(child), true)?;
+    let mut reference = child.borrow().next_sibling();
+    if reference.as_ref().is_some_and(|r| Rc::ptr_eq(r, node)) {
+        reference = node.borrow().next_sibling();
+    }
+    let mut previous = child.borrow().prev_sibling().upgrade();
+    if previous.as_ref().is_some_and(|p| Rc::ptr_eq(p, node)) {
+        previous = node.borrow().prev_sibling().upgrade();
+    }
+
+    // The removal and the insertion are reported as one change.
+    let mut removed = Vec::new();
+    if !Rc::ptr_eq(child, node) {
+        remove_node(child, /*suppress_observers*/ true);
+        removed.push(child.clone());
+    }
+    let added = insert_node(
+        parent,
+        node,
+        reference.as_ref(),
+        /*suppress_observers*/ true,
+    );
+    queue_child_list_record(parent, added, removed, previous, reference);
+    Ok(child.clone())
+}
+
+/// Returns a copy of `node` without a parent. The descendants, including the contents of a
+/// <template>, are copied too if `deep` is true.
+/// https://dom.spec.whatwg.org/#concept-node-clone
+pub fn clone_node(node: &Rc<RefCell<Node>>, deep: bool) -> Rc<RefCell<Node>> {
+    let clone = clone_single_node(node);
+    if deep {
+        // Copy the children of each copied node with a stack instead of recursion.
+        let mut pending = vec![(node.clone(), clone.clone())];
+        while let Some((from, to)) = pending.pop() {
+            let template_contents = |n: &Rc<RefCell<Node>>| {
+                n.borrow().get_element().and_then(|e| e.template_contents())
+            };
+            if let (Some(from), Some(to)) = (template_contents(&from), template_contents(&to)) {
+                pending.push((from, to));
+            }
+            for child in children(&from) {
+                let child_clone = clone_single_node(&child);
+                insert_node(&to, &child_clone, None, /*suppress_observers*/ true);
+                pending.push((child, child_clone));
+            }
+        }
+    }
+    if let Some(document) = node.borrow().owner_document().upgrade() {
+        adopt_node(&clone, &document);
+    }
+    clone
+}
+
+fn clone_single_node(node: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+    let kind = match node.borrow().kind {
+        NodeKind::Element(ref element) => {
+            // A new element gets its own template contents instead of sharing them.
+            NodeKind::Element(Element::new_with_namespace(
+                element.local_name(),
+                element.namespace(),
+                element.attributes().to_vec(),
+            ))
+        }
+        ref kind => kind.clone(),
+    };
+    let mut clone = Node::new(kind);
+    // A copy of a document has the same mode.
+    clone.set_quirks_mode(node.borrow().quirks_mode());
+    Rc::new(RefCell::new(clone))
+}
+
+/// Returns the document of `node`, which is `node` itself for a document.
+pub fn node_document(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    match node.borrow().kind {
+        NodeKind::Document => Some(node.clone()),
+        _ => node.borrow().owner_document().upgrade(),
+    }
+}
+
+/// Makes `node` and its descendants belong to `document`.
+/// https://dom.spec.whatwg.org/#concept-node-adopt
+fn adopt_node(node: &Rc<RefCell<Node>>, document: &Rc<RefCell<Node>>) {
+    let owner_document = node.borrow().owner_document().upgrade();
+    if owner_document.is_some_and(|d| Rc::ptr_eq(&d, document)) {
+        return;
+    }
+
+    let mut pending = vec![(node.clone(), document.clone())];
+    while let Some((root, document)) = pending.pop() {
+        let window = document.borrow().window().upgrade();
+        for n in inclusive_descendants(&root) {
+            n.borrow_mut().set_owner_document(Rc::downgrade(&document));
+            // The observers of a node from another document are notified of later changes.
+            if let Some(ref window) = window {
+                if !n.borrow().registered_observers().is_empty() {
+                    window.borrow_mut().set_has_mutation_observers();
+                }
+            }
+
+            // Template contents belong to a document without a browsing context.
+            let contents = n.borrow().get_element().and_then(|e| e.template_contents());
+            if let Some(contents) = contents {
+                let contents_document = match window {
+                    Some(ref window) => window.borrow().template_contents_owner_document(),
+                    None => document.clone(),
+                };
+                pending.push((contents, contents_document));
+            }
+        }
+    }
+}
+
+/// Returns true if `a` and `b` are the same node, not just equal nodes.
+/// https://dom.spec.whatwg.org/#dom-node-issamenode
+pub fn is_same_node(a: &Rc<RefCell<Node>>, b: &Rc<RefCell<Node>>) -> bool {
+    Rc::ptr_eq(a, b)
+}
+
+/// Returns true if the trees rooted at `a` and `b` have the same structure, names, attributes
+/// and text.
+/// https://dom.spec.whatwg.org/#dom-node-isequalnode
+pub fn is_equal_node(a: &Rc<RefCell<Node>>, b: &Rc<RefCell<Node>>) -> bool {
+    let mut pending = vec![(a.clone(), b.clone())];
+    while let Some((a, b)) = pending.pop() {
+        if *a.borrow() != *b.borrow() {
+            return false;
+        }
+        let children1 = children(&a);
+        let children2 = children(&b);
+        if children1.len() != children2.len() {
+            return false;
+        }
+        pending.extend(children1.into_iter().zip(children2));
+    }
+    true
+}
+
+/// Sets the attribute whose qualified name is `name` to `value`. The name is lowercased for
+/// HTML elements.
+/// https://dom.spec.whatwg.org/#dom-element-setattribute
+pub fn set_attribute(
+    element: &Rc<RefCell<Node>>,
+    name: &str,
+    value: &str,
+) -> Result<(), DomException> {
+    if !is_valid_attribute_name(name) {
+        return Err(DomException::InvalidCharacterError);
+    }
+    change_attribute(element, |e| {
+        let old_value = e.set_attribute(name, value);
+        e.get_attribute_node(name).cloned().map(|a| (a, old_value))
+    })
+}
+
+/// Sets the attribute in `namespace` named `qualified_name`, which may have a prefix such as
+/// "xlink:href".
+/// https://dom.spec.whatwg.org/#dom-element-setattributens
+pub fn set_attribute_ns(
+    element: &Rc<RefCell<Node>>,
+    namespace: Option<AttributeNamespace>,
+    qualified_name: &str,
+    value: &str,
+) -> Result<(), DomException> {
+    let (prefix, local_name) = match qualified_name.split_once(':') {
+        Some((prefix, local_name)) => (Some(prefix), local_name),
+        None => (None, qualified_name),
+    };
+    if prefix.is_some_and(|p| !is_valid_attribute_name(p)) || !is_valid_attribute_name(local_name) {
+        return Err(DomException::InvalidCharacterError);
+    }
+    // https://dom.spec.whatwg.org/#validate-and-extract
+    let is_xmlns = qualified_name == "xmlns" || prefix == Some("xmlns");
+    if (prefix.is_some() && namespace.is_none())
+        || (prefix == Some("xml") && namespace != Some(AttributeNamespace::Xml))
+        || is_xmlns != (namespace == Some(AttributeNamespace::Xmlns))
+    {
+        return Err(DomException::NamespaceError);
+    }
+
+    let mut attr = Attribute::with_value(local_name, value);
+    if let Some(namespace) = namespace {
+        attr.set_namespace(prefix, local_name, namespace);
+    }
+    change_attribute(element, |e| {
+        let old_value = e.set_attribute_ns(attr.clone());
+        Some((attr, old_value))
+    })
+}
+
+/// Removes the attribute whose qualified name is `name`. The name is lowercased for HTML
+/// elements.
+/// https://dom.spec.whatwg.org/#dom-element-removeattribute
+pub fn remove_attribute(element: &Rc<RefCell<Node>>, name: &str) -> Result<(), DomException> {
+    change_attribute(element, |e| {
+        e.remove_attribute(name).map(|a| {
+            let old_value = a.value();
+            (a, Some(old_value))
+        })
+    })
+}
+
+/// https://dom.spec.whatwg.org/#dom-element-removeattributens
+pub fn remove_attribute_ns(
+    element: &Rc<RefCell<Node>>,
+    namespace: Option<AttributeNamespace>,
+    local_name: &str,
+) -> Result<(), DomException> {
+    change_attribute(element, |e| {
+        e.remove_attribute_ns(namespace, local_name).map(|a| {
+            let old_value = a.value();
+            (a, Some(old_value))
+        })
+    })
+}
+
+/// Changes the attributes of `element` with `f`, which returns the changed attribute with its
+/// old value, and keeps the id index of its document up to date.
+fn change_attribute<F: FnOnce(&mut Element) -> Option<(Attribute, Option<String>)>>(
+    element: &Rc<RefCell<Node>>,
+    f: F,
+) -> Result<(), DomException> {
+    let (old_id, change) = match element.borrow_mut().kind {
+        NodeKind::Element(ref mut e) => (e.id(), f(e)),
+        _ => return Err(DomException::InvalidNodeTypeError),
+    };
+    if let Some((attr, old_value)) = change {
+        queue_attribute_record(element, &attr.local_name(), attr.namespace(), old_value);
+    }
+    let new_id = element.borrow().get_element().and_then(|e| e.id());
+    if old_id == new_id {
+        return Ok(());
+    }
+
+    if let Some(document) = connected_document(element) {
+        if let Some(id) = old_id {
+            document.borrow_mut().remove_id(&id, element);
+        }
+        if let Some(id) = new_id {
+            document.borrow_mut().add_id(&id, Rc::downgrade(element));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the data of a text or comment node.
+/// https://dom.spec.whatwg.org/#dom-characterdata-data
+pub fn set_data(node: &Rc<RefCell<Node>>, data: &str) -> Result<(), DomException> {
+    let old_value = match node.borrow_mut().kind {
+        NodeKind::Text(ref mut s) | NodeKind::Comment(ref mut s) => {
+            core::mem::replace(s, String::from(data))
+        }
+        _ => return Err(DomException::InvalidNodeTypeError),
+    };
+    queue_character_data_record(node, old_value);
+    Ok(())
+}
+
+/// Returns the text of `node`, which is the data of all the text nodes in it for an element or
+/// a DocumentFragment. Returns None for a document and a doctype.
+/// https://dom.spec.whatwg.org/#dom-node-textcontent
+pub fn text_content(node: &Rc<RefCell<Node>>) -> Option<String> {
+    match node.borrow().kind {
+        NodeKind::Document | NodeKind::DocumentType(_) => None,
+        NodeKind::Text(ref s) | NodeKind::Comment(ref s) => Some(s.clone()),
+        NodeKind::Element(_) | NodeKind::DocumentFragment => {
+            let mut text = String::new();
+            for n in descendants(node) {
+                if let NodeKind::Text(ref s) = n.borrow().kind {
+                    text.push_str(s);
+                }
+            }
+            Some(text)
+        }
+    }
+}
+
+/// Replaces the children of an element or a DocumentFragment with a text node of `text`, or
+/// the data of a text or comment node. This does nothing for a document and a doctype.
+/// https://dom.spec.whatwg.org/#dom-node-textcontent
+pub fn set_text_content(node: &Rc<RefCell<Node>>, text: &str) {
+    let kind = node.borrow().kind.clone();
+    match kind {
+        NodeKind::Document | NodeKind::DocumentType(_) => {}
+        NodeKind::Text(_) | NodeKind::Comment(_) => {
+            set_data(node, text).expect("node should be a text or a comment");
+        }
+        NodeKind::Element(_) | NodeKind::DocumentFragment => {
+            // https://dom.spec.whatwg.org/#string-replace-all
+            let removed = children(node);
+            for child in &removed {
+                remove_node(child, /*suppress_observers*/ true);
+            }
+            let mut added = Vec::new();
+            if !text.is_empty() {
+                let text = Rc::new(RefCell::new(Node::new(NodeKind::Text(String::from(text)))));
+                added = insert_node(node, &text, None, /*suppress_observers*/ true);
+            }
+            queue_child_list_record(node, added, removed, None, None);
+        }
+    }
+}
+
+/// Merges adjacent text nodes in the descendants of `node` and removes empty text nodes.
+/// https://dom.spec.whatwg.org/#dom-node-normalize
+pub fn normalize(node: &Rc<RefCell<Node>>) {
+    let texts: Vec<Rc<RefCell<Node>>> = descendants(node).filter(is_text).collect();
+    for text in texts {
+        // The text node was merged into a previous one.
+        if text.borrow().parent().upgrade().is_none() {
+            continue;
+        }
+
+        let mut data = match text.borrow().kind {
+            NodeKind::Text(ref s) => s.clone(),
+            _ => continue,
+        };
+        if data.is_empty() {
+            remove_node(&text, /*suppress_observers*/ false);
+            continue;
+        }
+
+        let mut merged = Vec::new();
+        let mut next = text.borrow().next_sibling();
+        while let Some(n) = next {
+            match n.borrow().kind {
+                NodeKind::Text(ref s) => data.push_str(s),
+                _ => break,
+            }
+            next = n.borrow().next_sibling();
+            merged.push(n);
+        }
+        if merged.is_empty() {
+            continue;
+        }
+        set_data(&text, &data).expect("node should be a text");
+        for n in &merged {
+            remove_node(n, /*suppress_observers*/ false);
+        }
+    }
+}
+
+/// Splits a text node at `offset`, which counts characters, and returns the new text node
+/// with the data after `offset`. The new node is inserted after `node` if it has a parent.
+/// https://dom.spec.whatwg.org/#dom-text-splittext
+pub fn split_text(
+    node: &Rc<RefCell<Node>>,
+    offset: usize,
+) -> Result<Rc<RefCell<Node>>, DomException> {
+    let data = match node.borrow().kind {
+        NodeKind::Text(ref s) => s.clone(),
+        _ => return Err(DomException::InvalidNodeTypeError),
+    };
+    if offset > data.chars().count() {
+        return Err(DomException::IndexSizeError);
+    }
+    let index = data
+        .char_indices()
+        .nth(offset)
+        .map(|(i, _)| i)
+        .unwrap_or(data.len());
+
+    let new_node = Rc::new(RefCell::new(Node::new(NodeKind::Text(String::from(
+        &data[index..],
+    )))));
+    if let Some(document) = node.borrow().owner_document().upgrade() {
+        adopt_node(&new_node, &document);
+    }
+    let parent = node.borrow().parent().upgrade();
+    if let Some(parent) = parent {
+        let next = node.borrow().next_sibling();
+        insert_node(
+            &parent,
+            &new_node,
+            next.as_ref(),
+            /*suppress_observers*/ false,
+        );
+    }
+    set_data(node, &data[..index])?;
+    Ok(new_node)
+}
+
+/// Returns true if `name` can be used as the name of an attribute.
+/// https://dom.spec.whatwg.org/#valid-attribute-local-name
+fn is_valid_attribute_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || matches!(c, '\0' | '/' | '>' | '='))
+}
+
+/// Returns the first element whose id is `id` among the descendants of `root` in tree order.
+/// A document looks up the element in its id index.
+/// https://dom.spec.whatwg.org/#dom-nonelementparentnode-getelementbyid
+pub fn get_element_by_id(root: &Rc<RefCell<Node>>, id: &str) -> Option<Rc<RefCell<Node>>> {
+    if id.is_empty() {
+        return None;
+    }
+    if matches!(root.borrow().kind, NodeKind::Document) {
+        let mut elements = root.borrow().elements_with_id(id);
+        match elements.len() {
+            0 => return None,
+            1 => return elements.pop(),
+            // Walk the tree to find the first one of the elements with the same id.
+            _ => {}
+        }
+    }
+
+    descendants(root).find(|n| match n.borrow().kind {
+        NodeKind::Element(ref element) => element.id().as_deref() == Some(id),
+        _ => false,
+    })
+}
+
+/// Returns the descendant elements of `root` named `name` in tree order, or all of them for
+/// "*". The name of HTML elements is matched case-insensitively.
+/// https://dom.spec.whatwg.org/#concept-getelementsbytagname
+pub fn get_elements_by_tag_name(root: &Rc<RefCell<Node>>, name: &str) -> Vec<Rc<RefCell<Node>>> {
+    let lower = name.to_ascii_lowercase();
+    descendants(root)
+        .filter(|n| match n.borrow().kind {
+            NodeKind::Element(ref element) => match element.namespace() {
+                _ if name == "*" => true,
+                Namespace::Html => element.local_name() == lower,
+                _ => element.local_name() == name,
+            },
+            _ => false,
+        })
+        .collect()
+}
+
+/// Returns the descendant elements of `root` that have all the classes in `class_names`, which
+/// are separated by whitespace. Classes are matched case-insensitively in quirks mode.
+/// https://dom.spec.whatwg.org/#concept-getelementsbyclassname
+pub fn get_elements_by_class_name(
+    root: &Rc<RefCell<Node>>,
+    class_names: &str,
+) -> Vec<Rc<RefCell<Node>>> {
+    let class_names: Vec<&str> = class_names.split_ascii_whitespace().collect();
+    if class_names.is_empty() {
+        return Vec::new();
+    }
+    let quirks = is_in_quirks_mode(root);
+    descendants(root)
+        .filter(|n| match n.borrow().kind {
+            NodeKind::Element(ref element) => class_names
+                .iter()
+                .all(|name| has_class(element, name, quirks)),
+            _ => false,
+        })
+        .collect()
+}
+
+/// Returns the first element that matches `selectors` among the descendants of `root` in tree
+/// order.
+/// https://dom.spec.whatwg.org/#dom-parentnode-queryselector
+pub fn query_selector(
+    root: &Rc<RefCell<Node>>,
+    selectors: &str,
+) -> Result<Option<Rc<RefCell<Node>>>, DomException> {
+    let selectors = parse_selectors(selectors)?;
+    Ok(descendants(root).find(|n| matches_selector_list(n, &selectors)))
+}
+
+/// Returns all the elements that match `selectors` among the descendants of `root` in tree
+/// order.
+/// https://dom.spec.whatwg.org/#dom-parentnode-queryselectorall
+pub fn query_selector_all(
+    root: &Rc<RefCell<Node>>,
+    selectors: &str,
+) -> Result<Vec<Rc<RefCell<Node>>>, DomException> {
+    let selectors = parse_selectors(selectors)?;
+    Ok(descendants(root)
+        .filter(|n| matches_selector_list(n, &selectors))
+        .collect())
+}
+
+/// Returns true if `element` matches `selectors`.
+/// https://dom.spec.whatwg.org/#dom-element-matches
+pub fn matches(element: &Rc<RefCell<Node>>, selectors: &str) -> Result<bool, DomException> {
+    let selectors = parse_selectors(selectors)?;
+    Ok(matches_selector_list(element, &selectors))
+}
+
+/// Returns the nearest inclusive ancestor of `element` that matches `selectors`.
+/// https://dom.spec.whatwg.org/#dom-element-closest
+pub fn closest(
+    element: &Rc<RefCell<Node>>,
+    selectors: &str,
+) -> Result<Option<Rc<RefCell<Node>>>, DomException> {
+    let selectors = parse_selectors(selectors)?;
+    let mut node = Some(element.clone());
+    while let Some(n) = node {
+        if matches_selector_list(&n, &selectors) {
+            return Ok(Some(n));
+        }
+        node = n.borrow().parent().upgrade();
+    }
+    Ok(None)
+}
+
+/// Returns true if `node` is an element that matches the simple selector `selector`.
+/// https://www.w3.org/TR/selectors-4/#simple
+pub fn matches_selector(node: &Rc<RefCell<Node>>, selector: &Selector) -> bool {
+    let n = node.borrow();
+    let element = match n.kind {
+        NodeKind::Element(ref element) => element,
+        _ => return false,
+    };
+    match selector {
+        // Type selectors are case-insensitive for HTML elements.
+        Selector::TypeSelector(name) => match element.namespace() {
+            Namespace::Html => element.local_name().eq_ignore_ascii_case(name),
+            _ => element.local_name() == *name,
+        },
+        Selector::ClassSelector(name) => has_class(element, name, is_in_quirks_mode(node)),
+        Selector::IdSelector(id) => match element.id() {
+            Some(ref value) if is_in_quirks_mode(node) => value.eq_ignore_ascii_case(id),
+            Some(ref value) => value == id,
+            None => false,
+        },
+        Selector::UnknownSelector => false,
+    }
+}
+
+fn parse_selectors(selectors: &str) -> Result<Vec<ComplexSelector>, DomException> {
+    parse_selector_list(selectors).map_err(|_| DomException::SyntaxError)
+}
+
+fn matches_selector_list(node: &Rc<RefCell<Node>>, selectors: &[ComplexSelector]) -> bool {
+    selectors
+        .iter()
+        .any(|selector| matches_complex_selector(node, selector, selector.compounds.len() - 1))
+}
+
+/// Returns true if `node` matches the compound selectors of `selector` up to `index`. They are
+/// matched from right to left, e.g. "p" first and then "div" for "div p".
+fn matches_complex_selector(
+    node: &Rc<RefCell<Node>>,
+    selector: &ComplexSelector,
+    index: usize,
+) -> bool {
+    if !is_element(node)
+        || !selector.compounds[index]
+            .iter()
+            .all(|s| matches_selector(node, s))
+    {
+        return false;
+    }
+    if index == 0 {
+        return true;
+    }
+
+    let mut ancestor = node.borrow().parent().upgrade();
+    while let Some(a) = ancestor {
+        if !is_element(&a) {
+            return false;
+        }
+        if matches_complex_selector(&a, selector, index - 1) {
+            return true;
+        }
+        if selector.combinators[index - 1] == Combinator::Child {
+            return false;
+        }
+        ancestor = a.borrow().parent().upgrade();
+    }
+    false
+}
+
+/// Returns true if the class attribute of `element` contains `name`.
+fn has_class(element: &Element, name: &str, quirks: bool) -> bool {
+    element.class_list().iter().any(|class| match quirks {
+        true => class.eq_ignore_ascii_case(name),
+        false => class == name,
+    })
+}
+
+/// Returns true if the document of `node` is in quirks mode, where classes and ids are
+/// case-insensitive.
+fn is_in_quirks_mode(node: &Rc<RefCell<Node>>) -> bool {
+    node_document(node).is_some_and(|d| d.borrow().quirks_mode() == QuirksMode::Quirks)
+}
+
+/// Returns the children of `node`.
+pub fn children(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+    let mut children = Vec::new();
+    let mut child = node.borrow().first_child();
+    while let Some(c) = child {
+        child = c.borrow().next_sibling();
+        children.push(c);
+    }
+    children
+}
+
+/// Inserts `node` before `reference` without checking that the tree stays valid. This is used
+/// by the HTML parser, which only builds valid trees.
+/// https://dom.spec.whatwg.org/#concept-node-insert
+pub(crate) fn insert_node_before(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    reference: Option<&Rc<RefCell<Node>>>,
+) {
+    insert_node(parent, node, reference, /*suppress_observers*/ false);
+}
+
+/// Inserts `node`, or the children of a DocumentFragment, and returns the inserted nodes.
+fn insert_node(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    reference: Option<&Rc<RefCell<Node>>>,
+    suppress_observers: bool,
+) -> Vec<Rc<RefCell<Node>>> {
+    let nodes = match node.borrow().kind {
+        NodeKind::DocumentFragment => children(node),
+        _ => vec![node.clone()],
+    };
+    if node.borrow().kind == NodeKind::DocumentFragment {
+        for child in &nodes {
+            remove_node(child, /*suppress_observers*/ true);
+        }
+        queue_child_list_record(node, Vec::new(), nodes.clone(), None, None);
+    }
+
+    let previous = match reference {
+        Some(reference) => reference.borrow().prev_sibling().upgrade(),
+        None => parent.borrow().last_child().upgrade(),
+    };
+    for n in &nodes {
+        insert_single_node(parent, n, reference);
+    }
+    if !suppress_observers {
+        queue_child_list_record(
+            parent,
+            nodes.clone(),
+            Vec::new(),
+            previous,
+            reference.cloned(),
+        );
+    }
+    nodes
+}
+
+fn insert_single_node(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    reference: Option<&Rc<RefCell<Node>>>,
+) {
+    detach_node(node);
+    if let Some(document) = node_document(parent) {
+        adopt_node(node, &document);
+    }
+    let prev = match reference {
+        Some(reference) => reference.borrow().prev_sibling().upgrade(),
+        None => parent.borrow().last_child().upgrade(),
+    };
+    match prev {
+        Some(ref prev) => prev.borrow_mut().set_next_sibling(Some(node.clone())),
+        None => parent.borrow_mut().set_first_child(Some(node.clone())),
+    }
+    match reference {
+        Some(reference) => reference.borrow_mut().set_prev_sibling(Rc::downgrade(node)),
+        None => parent.borrow_mut().set_last_child(Rc::downgrade(node)),
+    }
+
+    {
+        let mut n = node.borrow_mut();
+        n.set_parent(Rc::downgrade(parent));
+        n.set_prev_sibling(match prev {
+            Some(ref prev) => Rc::downgrade(prev),
+            None => Weak::new(),
+        });
+        n.set_next_sibling(reference.cloned());
+    }
+    index_ids(node);
+}
+
+/// Removes `node` from its parent if it has one.
+/// https://dom.spec.whatwg.org/#concept-node-remove
+pub(crate) fn detach_node(node: &Rc<RefCell<Node>>) {
+    remove_node(node, /*suppress_observers*/ false);
+}
+
+fn remove_node(node: &Rc<RefCell<Node>>, suppress_observers: bool) {
+    let parent = match node.borrow().parent().upgrade() {
+        Some(p) => p,
+        None => return,
+    };
+    update_id_index(node, false);
+    let prev = node.borrow().prev_sibling().upgrade();
+    let next = node.borrow().next_sibling();
+
+    match prev {
+        Some(ref p) => p.borrow_mut().set_next_sibling(next.clone()),
+        None => parent.borrow_mut().set_first_child(next.clone()),
+    }
+    let weak_prev = match prev {
+        Some(ref p) => Rc::downgrade(p),
+        None => Weak::new(),
+    };
+    match next {
+        Some(ref n) => n.borrow_mut().set_prev_sibling(weak_prev),
+        None => parent.borrow_mut().set_last_child(weak_prev),
+    }
+
+    {
+        let mut n = node.borrow_mut();
+        n.set_parent(Weak::new());
+        n.set_prev_sibling(Weak::new());
+        n.set_next_sibling(None);
+    }
+    if !suppress_observers {
+        queue_child_list_record(&parent, Vec::new(), vec![node.clone()], prev, next);
+    }
+}
+
+/// Adds the elements with an id in the tree rooted at `node` to the id index of the document
+/// if `node` is in a document.
+pub(crate) fn index_ids(node: &Rc<RefCell<Node>>) {
+    update_id_index(node, true);
+}
+
+fn update_id_index(node: &Rc<RefCell<Node>>, insert: bool) {
+    if node_document(node).is_none() {
+        return;
+    }
+    let elements: Vec<(String, Rc<RefCell<Node>>)> = inclusive_descendants(node)
+        .filter_map(|n| {
+            let id = match n.borrow().kind {
+                NodeKind::Element(ref element) => element.id(),
+                _ => None,
+            };
+            id.map(|id| (id, n))
+        })
+        .collect();
+    // Finding the document walks up to the root, so it's skipped for the nodes without ids
+    // that the parser inserts one by one.
+    if elements.is_empty() {
+        return;
+    }
+    let root = match connected_document(node) {
+        Some(root) => root,
+        None => return,
+    };
+
+    for (id, n) in elements {
+        if insert {
+            root.borrow_mut().add_id(&id, Rc::downgrade(&n));
+        } else {
+            root.borrow_mut().remove_id(&id, &n);
+        }
+    }
+}
+
+/// Returns the document `node` is in, or None if the root of its tree isn't a document.
+fn connected_document(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    // A node in a document always belongs to it, so a node without a document is skipped
+    // without walking up to its root.
+    node_document(node)?;
+    let root = ancestors(node).last().unwrap_or_else(|| node.clone());
+    let is_document = matches!(root.borrow().kind, NodeKind::Document);
+    is_document.then_some(root)
+}
+
+fn is_parent_of(parent: &Rc<RefCell<Node>>, child: &Rc<RefCell<Node>>) -> bool {
+    match child.borrow().parent().upgrade() {
+        Some(p) => Rc::ptr_eq(&p, parent),
+        None => false,
+    }
+}
+
+fn is_inclusive_ancestor(ancestor: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>) -> bool {
+    let mut current = Some(node.clone());
+    while let Some(n) = current {
+        if Rc::ptr_eq(&n, ancestor) {
+            return true;
+        }
+        current = n.borrow().parent().upgrade();
+    }
+    false
+}
+
+/// Checks that inserting `node` before `child`, or in place of `child` if `replacing` is true,
+/// keeps the tree valid.
+/// https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+fn ensure_pre_insertion_validity(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: Option<&Rc<RefCell<Node>>>,
+    replacing: bool,
+) -> Result<(), DomException> {
+    let is_document = match parent.borrow().kind {
+        NodeKind::Document => true,
+        NodeKind::DocumentFragment | NodeKind::Element(_) => false,
+        _ => return Err(DomException::HierarchyRequestError),
+    };
+    if is_inclusive_ancestor(node, parent) {
+        return Err(DomException::HierarchyRequestError);
+    }
+    if let Some(child) = child {
+        if !is_parent_of(parent, child) {
+            return Err(DomException::NotFoundError);
+        }
+    }
+
+    let element_count = match node.borrow().kind {
+        NodeKind::Document => return Err(DomException::HierarchyRequestError),
+        NodeKind::Text(_) if is_document => return Err(DomException::HierarchyRequestError),
+        NodeKind::DocumentType(_) if !is_document => {
+            return Err(DomException::HierarchyRequestError)
+        }
+        NodeKind::DocumentFragment => {
+            let children = children(node);
+            if is_document && children.iter().any(is_text) {
+                return Err(DomException::HierarchyRequestError);
+            }
+            children.iter().filter(|c| is_element(c)).count()
+        }
+        NodeKind::Element(_) => 1,
+        _ => 0,
+    };
+    if !is_document {
+        return Ok(());
+    }
+
+    // A document has at most one element and one doctype, and the doctype comes first.
+    let siblings = children(parent);
+    let position = match child {
+        Some(child) => siblings
+            .iter()
+            .position(|s| Rc::ptr_eq(s, child))
+            .unwrap_or(siblings.len()),
+        None => siblings.len(),
+    };
+    let preceding = &siblings[..position];
+    let following = match replacing {
+        true => &siblings[position + 1..],
+        false => &siblings[position..],
+    };
+    let mut others = siblings
+        .iter()
+        .filter(|s| !(replacing && child.is_some_and(|c| Rc::ptr_eq(s, c))));
+
+    let is_doctype = |n: &Rc<RefCell<Node>>| matches!(n.borrow().kind, NodeKind::DocumentType(_));
+    let valid = match element_count {
+        0 if is_doctype(node) => !others.any(is_doctype) && !preceding.iter().any(is_element),
+        0 => true,
+        1 => !others.any(is_element) && !following.iter().any(is_doctype),
+        _ => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(DomException::HierarchyRequestError)
+    }
+}
+
+fn is_element(node: &Rc<RefCell<Node>>) -> bool {
+    matches!(node.borrow().kind, NodeKind::Element(_))
+}
+
+fn is_text(node: &Rc<RefCell<Node>>) -> bool {
+    matches!(node.borrow().kind, NodeKind::Text(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::Window;
+    use crate::renderer::dom::serializer::serialize_node;
+    use crate::renderer::dom::test_utils::deep_tree;
+    use crate::renderer::dom::test_utils::element;
+    use crate::renderer::dom::test_utils::element_by_id;
+    use crate::renderer::dom::test_utils::parse;
+    use crate::renderer::dom::test_utils::text;
+    use alloc::string::String;
+    use alloc::vec;
+
+    /// Checks that the links between `parent` and its children are consistent.
+    fn assert_links(parent: &Rc<RefCell<Node>>) {
+        let children = children(parent);
+        for (i, child) in children.iter().enumerate() {
+            assert!(is_parent_of(parent, child));
+            let prev = child.borrow().prev_sibling().upgrade();
+            match i {
+                0 => assert!(prev.is_none()),
+                _ => assert!(Rc::ptr_eq(&prev.unwrap(), &children[i - 1])),
+            }
+        }
+        let last = parent.borrow().last_child().upgrade();
+        match children.last() {
+            Some(c) => assert!(Rc::ptr_eq(c, &last.unwrap())),
+            None => assert!(last.is_none()),
+        }
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let div = element("div");
+        let a = text("a");
+        let b = element("b");
+        let c = text("c");
+        append_child(&div, &c).unwrap();
+        insert_before(&div, &a, Some(&c)).unwrap();
+        insert_before(&div, &b, Some(&c)).unwrap();
+        assert_links(&div);
+        assert_eq!(String::from("<div>a<b></b>c</div>"), serialize_node(&div));
+
+        // Inserting a node again moves it.
+        append_child(&div, &a).unwrap();
+        assert_links(&div);
+        assert_eq!(String::from("<div><b></b>ca</div>"), serialize_node(&div));
+
+        assert!(is_same_node(&b, &remove_child(&div, &b).unwrap()));
+        assert!(b.borrow().parent().upgrade().is_none());
+        assert_links(&div);
+        assert_eq!(Err(DomException::NotFoundError), remove_child(&div, &b));
+        assert_eq!(String::from("<div>ca</div>"), serialize_node(&div));
+    }
+
+    #[test]
+    fn test_replace_child() {
+        let div = element("div");
+        let a = text("a");
+        let b = element("b");
+        append_child(&div, &a).unwrap();
+        append_child(&div, &b).unwrap();
+
+        let i = element("i");
+        assert!(is_same_node(&a, &replace_child(&div, &i, &a).unwrap()));
+        assert_links(&div);
+        assert_eq!(
+            String::from("<div><i></i><b></b></div>"),
+            serialize_node(&div)
+        );
+
+        // Replace a node with its next sibling.
+        replace_child(&div, &b, &i).unwrap();
+        assert_links(&div);
+        assert_eq!(String::from("<div><b></b></div>"), serialize_node(&div));
+    }
+
+    #[test]
+    fn test_document_fragment() {
+        let fragment = Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment)));
+        append_child(&fragment, &text("a")).unwrap();
+        append_child(&fragment, &element("b")).unwrap();
+        let div = element("div");
+        append_child(&div, &element("p")).unwrap();
+        let p = div.borrow().first_child().unwrap();
+        insert_before(&div, &fragment, Some(&p)).unwrap();
+        assert_links(&div);
+        assert!(fragment.borrow().first_child().is_none());
+        assert_eq!(
+            String::from("<div>a<b></b><p></p></div>"),
+            serialize_node(&div)
+        );
+    }
+
+    #[test]
+    fn test_hierarchy_request_error() {
+        let div = element("div");
+        let p = element("p");
+        append_child(&div, &p).unwrap();
+        assert_eq!(
+            Err(DomException::HierarchyRequestError),
+            append_child(&p, &div)
+        );
+        assert_eq!(
+            Err(DomException::HierarchyRequestError),
+            append_child(&div, &div)
+        );
+        assert_eq!(
+            Err(DomException::HierarchyRequestError),
+            append_child(&text("a"), &element("b"))
+        );
+        assert_eq!(
+            Err(DomException::NotFoundError),
+            insert_before(&div, &element("b"), Some(&element("i")))
+        );
+
+        let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+        append_child(&document, &element("html")).unwrap();
+        assert_eq!(
+            Err(DomException::HierarchyRequestError),
+            append_child(&document, &element("html"))
+        );
+        assert_eq!(
+            Err(DomException::HierarchyRequestError),
+            append_child(&document, &text("a"))
+        );
+    }
+
+    #[test]
+    fn test_clone_node() {
+        let div = element("div");
+        append_child(&div, &text("a")).unwrap();
+        let template = element("template");
+        let contents = template
+            .borrow()
+            .get_element()
+            .and_then(|e| e.template_contents())
+            .unwrap();
+        append_child(&contents, &element("p")).unwrap();
+        append_child(&div, &template).unwrap();
+
+        let shallow = clone_node(&div, false);
+        assert_eq!(String::from("<div></div>"), serialize_node(&shallow));
+
+        let deep = clone_node(&div, true);
+        assert_links(&deep);
+        assert_eq!(
+            String::from("<div>a<template><p></p></template></div>"),
+            serialize_node(&deep)
+        );
+        // The copy has its own template contents.
+        let cloned_contents = deep
+            .borrow()
+            .last_child()
+            .upgrade()
+            .and_then(|t| t.borrow().get_element())
+            .and_then(|e| e.template_contents())
+            .unwrap();
+        assert!(!Rc::ptr_eq(&contents, &cloned_contents));
+        assert!(deep.borrow().parent().upgrade().is_none());
+    }
+
+    #[test]
+    fn test_node_equality() {
+        let a1 = text("a");
+        let a2 = text("a");
+        assert!(is_equal_node(&a1, &a2));
+        assert!(!is_same_node(&a1, &a2));
+        assert!(!is_equal_node(&a1, &text("b")));
+
+        let div1 = element("div");
+        append_child(&div1, &a1).unwrap();
+        let div2 = element("div");
+        append_child(&div2, &a2).unwrap();
+        assert!(is_equal_node(&div1, &div2));
+        append_child(&div2, &element("p")).unwrap();
+        assert!(!is_equal_node(&div1, &div2));
+        assert_eq!(div1, div2);
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 100_000;
+        let root = deep_tree(depth);
+        let clone = clone_node(&root, true);
+        assert_eq!(depth, inclusive_descendants(&clone).count());
+        assert!(is_equal_node(&root, &clone));
+
+        let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+        append_child(&document, &root).unwrap();
+        let innermost = descendants(&root).last().unwrap();
+        assert!(Rc::ptr_eq(
+            &document,
+            &innermost.borrow().owner_document().upgrade().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_attribute_equality() {
+        let parse_p = |html: &str| {
+            let document = parse(html).borrow().document();
+            get_target_element_node(Some(document), ElementKind::P).unwrap()
+        };
+        // The order of attributes doesn't matter.
+        assert!(is_equal_node(
+            &parse_p("<p id=a class=b>"),
+            &parse_p("<p class=b id=a>")
+        ));
+        assert!(!is_equal_node(&parse_p("<p id=a>"), &parse_p("<p id=b>")));
+        assert!(!is_equal_node(&parse_p("<p id=a>"), &parse_p("<p>")));
+    }
+
+    #[test]
+    fn test_owner_document() {
+        let window = parse("<p>a</p><template><b></b></template>");
+        let document = window.borrow().document();
+        assert!(document.borrow().owner_document().upgrade().is_none());
+        assert!(Rc::ptr_eq(
+            &window,
+            &document.borrow().window().upgrade().unwrap()
+        ));
+
+        let p = get_target_element_node(Some(document.clone()), ElementKind::P).unwrap();
+        let text = p.borrow().first_child().unwrap();
+        assert!(Rc::ptr_eq(
+            &document,
+            &text.borrow().owner_document().upgrade().unwrap()
+        ));
+        assert!(Rc::ptr_eq(
+            &window,
+            &text.borrow().window().upgrade().unwrap()
+        ));
+
+        // Template contents belong to an inert document without a window.
+        let template =
+            get_target_element_node(Some(document.clone()), ElementKind::Template).unwrap();
+        let contents = template
+            .borrow()
+            .get_element()
+            .and_then(|e| e.template_contents())
+            .unwrap();
+        let b = contents.borrow().first_child().unwrap();
+        let inert_document = window.borrow().template_contents_owner_document();
+        assert!(Rc::ptr_eq(
+            &inert_document,
+            &b.borrow().owner_document().upgrade().unwrap()
+        ));
+        assert!(b.borrow().window().upgrade().is_none());
+
+        // Moving a node to another document adopts it with its descendants.
+        let other = Window::new();
+        let other_document = other.borrow().document();
+        let clone = clone_node(&p, true);
+        assert!(Rc::ptr_eq(
+            &document,
+            &clone.borrow().owner_document().upgrade().unwrap()
+        ));
+        append_child(&other_document, &clone).unwrap();
+        let text = clone.borrow().first_child().unwrap();
+        assert!(Rc::ptr_eq(
+            &other,
+            &text.borrow().window().upgrade().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_get_element_by_id() {
+        let window = parse("<p id=a>1</p><div><p id=b>2</p><p id=b>3</p></div>");
+        let document = window.borrow().document();
+        let a = get_element_by_id(&document, "a").unwrap();
+        assert_eq!(Some(text("1")), a.borrow().first_child());
+        // The first element in tree order wins.
+        let b = get_element_by_id(&document, "b").unwrap();
+        assert_eq!(Some(text("2")), b.borrow().first_child());
+        assert!(get_element_by_id(&document, "c").is_none());
+        assert!(get_element_by_id(&document, "").is_none());
+
+        // The index is updated when elements are removed and inserted.
+        let parent = b.borrow().parent().upgrade().unwrap();
+        remove_child(&parent, &b).unwrap();
+        let b = get_element_by_id(&document, "b").unwrap();
+        assert_eq!(Some(text("3")), b.borrow().first_child());
+        remove_child(&parent, &b).unwrap();
+        assert!(get_element_by_id(&document, "b").is_none());
+        append_child(&a, &b).unwrap();
+        assert!(is_same_node(
+            &b,
+            &get_element_by_id(&document, "b").unwrap()
+        ));
+
+        // Lookups in an element only find its descendants.
+        assert!(get_element_by_id(&parent, "a").is_none());
+        assert!(get_element_by_id(&a, "b").is_some());
+    }
+
+    #[test]
+    fn test_text_content() {
+        let window = parse("<!DOCTYPE html><div id=d>a<p>b<!-- c --></p>d</div>");
+        let document = window.borrow().document();
+        let div = element_by_id(&window, "d");
+        assert_eq!(Some("abd".to_string()), text_content(&div));
+        assert_eq!(None, text_content(&document));
+        let doctype = document.borrow().first_child().unwrap();
+        assert_eq!(None, text_content(&doctype));
+
+        set_text_content(&div, "e");
+        assert_eq!("<div id=\"d\">e</div>", serialize_node(&div));
+        set_text_content(&div, "");
+        assert!(div.borrow().first_child().is_none());
+        set_text_content(&doctype, "f");
+        assert_eq!(None, text_content(&doctype));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let div = element("div");
+        let p = element("p");
+        for child in [
+            text("a"),
+            text(""),
+            text("b"),
+            p.clone(),
+            text(""),
+            text("c"),
+        ] {
+            append_child(&div, &child).unwrap();
+        }
+        append_child(&p, &text("d")).unwrap();
+        append_child(&p, &text("e")).unwrap();
+
+        normalize(&div);
+        assert_links(&div);
+        assert_links(&p);
+        let first = div.borrow().first_child().unwrap();
+        assert_eq!(NodeKind::Text("ab".to_string()), first.borrow().kind);
+        assert_eq!(3, children(&div).len());
+        assert_eq!(1, children(&p).len());
+        assert_eq!("<div>ab<p>de</p>c</div>", serialize_node(&div));
+    }
+
+    #[test]
+    fn test_split_text() {
+        let div = element("div");
+        let a = text("héllo");
+        append_child(&div, &a).unwrap();
+        append_child(&div, &element("br")).unwrap();
+
+        let b = split_text(&a, 2).unwrap();
+        assert_links(&div);
+        assert_eq!(NodeKind::Text("hé".to_string()), a.borrow().kind);
+        assert_eq!(NodeKind::Text("llo".to_string()), b.borrow().kind);
+        assert!(Rc::ptr_eq(&b, &a.borrow().next_sibling().unwrap()));
+
+        let c = split_text(&b, 3).unwrap();
+        assert_eq!(NodeKind::Text(String::new()), c.borrow().kind);
+        assert_eq!(Err(DomException::IndexSizeError), split_text(&a, 3));
+        assert_eq!(Err(DomException::InvalidNodeTypeError), split_text(&div, 0));
+
+        normalize(&div);
+        assert_eq!("<div>héllo<br></div>", serialize_node(&div));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let div = element("div");
+        set_attribute(&div, "Title", "a").unwrap();
+        set_attribute(&div, "class", "b c b").unwrap();
+        set_attribute(&div, "title", "d").unwrap();
+        {
+            let node = div.borrow();
+            let e = node.get_element().unwrap();
+            // Names are case-insensitive for HTML elements.
+            assert_eq!(Some("d".to_string()), e.get_attribute("TITLE"));
+            assert!(e.has_attribute("class"));
+            assert_eq!(vec!["b", "c"], e.class_list());
+            let names: Vec<String> = e.attributes().iter().map(|a| a.name()).collect();
+            assert_eq!(vec!["title", "class"], names);
+        }
+        remove_attribute(&div, "TITLE").unwrap();
+        assert!(!div.borrow().get_element().unwrap().has_attribute("title"));
+
+        assert_eq!(
+            Err(DomException::InvalidCharacterError),
+            set_attribute(&div, "a b", "c")
+        );
+        assert_eq!(
+            Err(DomException::InvalidNodeTypeError),
+            set_attribute(&text("a"), "a", "b")
+        );
+
+        set_attribute_ns(&div, Some(AttributeNamespace::XLink), "xlink:href", "#a").unwrap();
+        assert_eq!(
+            Some("#a".to_string()),
+            div.borrow()
+                .get_element()
+                .unwrap()
+                .get_attribute_ns(Some(AttributeNamespace::XLink), "href")
+        );
+        assert_eq!(
+            Err(DomException::NamespaceError),
+            set_attribute_ns(&div, None, "xlink:href", "#a")
+        );
+        assert_eq!(
+            Err(DomException::NamespaceError),
+            set_attribute_ns(&div, Some(AttributeNamespace::XLink), "xmlns", "a")
+        );
+        remove_attribute_ns(&div, Some(AttributeNamespace::XLink), "href").unwrap();
+        assert!(!div
+            .borrow()
+            .get_element()
+            .unwrap()
+            .has_attribute("xlink:href"));
+    }
+
+    #[test]
+    fn test_id_index_on_attribute_change() {
+        let window = parse("<p id=a>1</p>");
+        let document = window.borrow().document();
+        let p = get_element_by_id(&document, "a").unwrap();
+
+        set_attribute(&p, "id", "b").unwrap();
+        assert!(get_element_by_id(&document, "a").is_none());
+        assert!(is_same_node(
+            &p,
+            &get_element_by_id(&document, "b").unwrap()
+        ));
+
+        remove_attribute(&p, "id").unwrap();
+        assert!(get_element_by_id(&document, "b").is_none());
+    }
+
+    #[test]
+    fn test_get_elements_by_tag_name() {
+        let window = parse("<p>1</p><div><P>2</P><svg><foreignObject/></svg></div>");
+        let document = window.borrow().document();
+        assert_eq!(2, get_elements_by_tag_name(&document, "P").len());
+        assert_eq!(
+            1,
+            get_elements_by_tag_name(&document, "foreignObject").len()
+        );
+        assert_eq!(
+            0,
+            get_elements_by_tag_name(&document, "foreignobject").len()
+        );
+        let div = get_elements_by_tag_name(&document, "div").pop().unwrap();
+        assert_eq!(3, get_elements_by_tag_name(&div, "*").len());
+    }
+
+    #[test]
+    fn test_get_elements_by_class_name() {
+        let window = parse("<!DOCTYPE html><p class='a b'>1</p><p class=b>2</p><p class='A'>3</p>");
+        let document = window.borrow().document();
+        assert_eq!(2, get_elements_by_class_name(&document, "b").len());
+        let elements = get_elements_by_class_name(&document, " b  a ");
+        assert_eq!(1, elements.len());
+        assert_eq!(Some(text("1")), elements[0].borrow().first_child());
+        assert_eq!(1, get_elements_by_class_name(&document, "a").len());
+        assert!(get_elements_by_class_name(&document, " ").is_empty());
+
+        // Classes are case-insensitive in quirks mode.
+        let window = parse("<p class='a'>1</p><p class='A'>2</p>");
+        let document = window.borrow().document();
+        assert_eq!(2, get_elements_by_class_name(&document, "a").len());
+
+        // The mode is kept by a document without a window and by its copies.
+        let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+        document.borrow_mut().set_quirks_mode(QuirksMode::Quirks);
+        let p = element("p");
+        set_attribute(&p, "class", "A").unwrap();
+        append_child(&document, &p).unwrap();
+        assert_eq!(1, get_elements_by_class_name(&document, "a").len());
+        let copy = clone_node(&document, true);
+        assert_eq!(QuirksMode::Quirks, copy.borrow().quirks_mode());
+        assert_eq!(1, get_elements_by_class_name(&copy, "a").len());
+    }
+
+    #[test]
+    fn test_query_selector() {
+        let window = parse(
+            "<!DOCTYPE html><div id=main><p class='note a'>1</p><section><p>2</p>\
+             <p class=note>3</p></section></div><p class=note>4</p>",
+        );
+        let document = window.borrow().document();
+        let first_text = |nodes: Vec<Rc<RefCell<Node>>>| -> Vec<Rc<RefCell<Node>>> {
+            nodes
+                .iter()
+                .map(|n| n.borrow().first_child().unwrap())
+                .collect()
+        };
+
+        let p = query_selector(&document, "div .note").unwrap().unwrap();
+        assert_eq!(Some(text("1")), p.borrow().first_child());
+        assert_eq!(
+            vec![text("1"), text("3")],
+            first_text(query_selector_all(&document, "#main p.note").unwrap())
+        );
+        assert_eq!(
+            vec![text("1"), text("4")],
+            first_text(query_selector_all(&document, "div > p, body > .note").unwrap())
+        );
+        assert_eq!(
+            vec![text("2"), text("3")],
+            first_text(query_selector_all(&document, "DIV * P").unwrap())
+        );
+        assert!(query_selector(&document, "span").unwrap().is_none());
+        assert_eq!(
+            Err(DomException::SyntaxError),
+            query_selector(&document, "p >")
+        );
+
+        // Only the descendants of the root are searched.
+        let section = query_selector(&document, "section").unwrap().unwrap();
+        assert_eq!(
+            vec![text("3")],
+            first_text(query_selector_all(&section, ".note").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_matches_and_closest() {
+        let window = parse("<div class=a><ul><li><b>x</b></li></ul></div>");
+        let document = window.borrow().document();
+        let b = query_selector(&document, "b").unwrap().unwrap();
+        assert_eq!(Ok(true), matches(&b, "li > b"));
+        assert_eq!(Ok(false), matches(&b, "ul > b"));
+        assert_eq!(Ok(true), matches(&b, "i, .a b"));
+
+        let div = closest(&b, ".a").unwrap().unwrap();
+        assert_eq!(Some(ElementKind::Div), div.borrow().element_kind());
+        assert!(is_same_node(&b, &closest(&b, "b").unwrap().unwrap()));
+        assert!(closest(&b, "p").unwrap().is_none());
+    }
+}
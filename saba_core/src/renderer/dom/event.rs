@@ -0,0 +1,474 @@
+//! Events and their dispatch to nodes and windows.
+//! https://dom.spec.whatwg.org/#events
+
+use crate::renderer::dom::api::DomException;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeKind;
+use crate::renderer::dom::node::Window;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::cell::RefCell;
+use core::fmt::Debug;
+use core::fmt::Formatter;
+
+/// A function called with the event when it's dispatched to a target it listens to. A
+/// listener is removed by passing the same Rc to `remove_event_listener`.
+pub type EventListenerCallback = Rc<dyn Fn(&mut Event)>;
+
+/// https://dom.spec.whatwg.org/#dom-event-eventphase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventPhase {
+    None,
+    Capturing,
+    AtTarget,
+    Bubbling,
+}
+
+/// A node or a window that events are dispatched to.
+/// https://dom.spec.whatwg.org/#interface-eventtarget
+#[derive(Debug, Clone)]
+pub enum EventTarget {
+    Node(Rc<RefCell<Node>>),
+    Window(Rc<RefCell<Window>>),
+}
+
+/// Targets are equal if they are the same object.
+impl PartialEq for EventTarget {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (EventTarget::Node(a), EventTarget::Node(b)) => Rc::ptr_eq(a, b),
+            (EventTarget::Window(a), EventTarget::Window(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// https://dom.spec.whatwg.org/#dictdef-addeventlisteneroptions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventListenerOptions {
+    /// Listens in the capturing phase instead of the bubbling phase.
+    pub capture: bool,
+    /// Removes the listener after it's called once.
+    pub once: bool,
+    /// Ignores `prevent_default` called by the listener.
+    pub passive: bool,
+}
+
+/// https://dom.spec.whatwg.org/#concept-event-listener
+#[derive(Clone)]
+pub struct EventListener {
+    event_type: String,
+    callback: EventListenerCallback,
+    options: EventListenerOptions,
+    /// Set when the listener is removed, so that a dispatch which already has it in its copy of
+    /// the listeners doesn't call it.
+    removed: Rc<Cell<bool>>,
+}
+
+impl Debug for EventListener {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        f.debug_struct("EventListener")
+            .field("event_type", &self.event_type)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+impl EventListener {
+    fn is(&self, event_type: &str, callback: &EventListenerCallback, capture: bool) -> bool {
+        self.event_type == event_type
+            && Rc::ptr_eq(&self.callback, callback)
+            && self.options.capture == capture
+    }
+}
+
+/// https://dom.spec.whatwg.org/#interface-event
+#[derive(Debug, Clone)]
+pub struct Event {
+    event_type: String,
+    bubbles: bool,
+    cancelable: bool,
+    target: Option<EventTarget>,
+    current_target: Option<EventTarget>,
+    phase: EventPhase,
+    stop_propagation: bool,
+    stop_immediate_propagation: bool,
+    canceled: bool,
+    in_passive_listener: bool,
+    dispatching: bool,
+}
+
+impl Event {
+    pub fn new(event_type: &str, bubbles: bool, cancelable: bool) -> Self {
+        Self {
+            event_type: String::from(event_type),
+            bubbles,
+            cancelable,
+            target: None,
+            current_target: None,
+            phase: EventPhase::None,
+            stop_propagation: false,
+            stop_immediate_propagation: false,
+            canceled: false,
+            in_passive_listener: false,
+            dispatching: false,
+        }
+    }
+
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+
+    pub fn bubbles(&self) -> bool {
+        self.bubbles
+    }
+
+    pub fn cancelable(&self) -> bool {
+        self.cancelable
+    }
+
+    /// The target the event was dispatched to.
+    pub fn target(&self) -> Option<EventTarget> {
+        self.target.clone()
+    }
+
+    /// The target whose listeners are being called.
+    pub fn current_target(&self) -> Option<EventTarget> {
+        self.current_target.clone()
+    }
+
+    pub fn event_phase(&self) -> EventPhase {
+        self.phase
+    }
+
+    /// Cancels the default action of the event, e.g. following a link for a click. This does
+    /// nothing if the event isn't cancelable.
+    /// https://dom.spec.whatwg.org/#dom-event-preventdefault
+    pub fn prevent_default(&mut self) {
+        if self.cancelable && !self.in_passive_listener {
+            self.canceled = true;
+        }
+    }
+
+    pub fn default_prevented(&self) -> bool {
+        self.canceled
+    }
+
+    /// Stops the event from reaching the next targets. The other listeners of the current
+    /// target are still called.
+    pub fn stop_propagation(&mut self) {
+        self.stop_propagation = true;
+    }
+
+    /// Stops the event from reaching the next targets and the other listeners of the current
+    /// target.
+    pub fn stop_immediate_propagation(&mut self) {
+        self.stop_propagation = true;
+        self.stop_immediate_propagation = true;
+    }
+}
+
+impl EventTarget {
+    fn with_listeners<R>(&self, f: impl FnOnce(&mut Vec<EventListener>) -> R) -> R {
+        match self {
+            EventTarget::Node(node) => f(node.borrow_mut().event_listeners_mut()),
+            EventTarget::Window(window) => f(window.borrow_mut().event_listeners_mut()),
+        }
+    }
+
+    /// Adds `callback` as a listener for events of `event_type`, unless it already listens to
+    /// them in the same phase.
+    /// https://dom.spec.whatwg.org/#dom-eventtarget-addeventlistener
+    pub fn add_event_listener(
+        &self,
+        event_type: &str,
+        callback: EventListenerCallback,
+        options: EventListenerOptions,
+    ) {
+        self.with_listeners(|listeners| {
+            if listeners
+                .iter()
+                .any(|l| l.is(event_type, &callback, options.capture))
+            {
+                return;
+            }
+            listeners.push(EventListener {
+                event_type: String::from(event_type),
+                callback,
+                options,
+                removed: Rc::new(Cell::new(false)),
+            });
+        });
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-eventtarget-removeeventlistener
+    pub fn remove_event_listener(
+        &self,
+        event_type: &str,
+        callback: &EventListenerCallback,
+        capture: bool,
+    ) {
+        self.with_listeners(|listeners| {
+            if let Some(i) = listeners
+                .iter()
+                .position(|l| l.is(event_type, callback, capture))
+            {
+                listeners.remove(i).removed.set(true);
+            }
+        });
+    }
+
+    /// Returns the next target in the event path, which is the parent node, or the window for
+    /// a document.
+    /// https://dom.spec.whatwg.org/#get-the-parent
+    fn parent(&self, event: &Event) -> Option<EventTarget> {
+        match self {
+            EventTarget::Node(node) => {
+                let node = node.borrow();
+                match node.kind {
+                    // The window doesn't get load events of the document, e.g. for images.
+                    NodeKind::Document if event.event_type != "load" => {
+                        node.window().upgrade().map(EventTarget::Window)
+                    }
+                    _ => node.parent().upgrade().map(EventTarget::Node),
+                }
+            }
+            EventTarget::Window(_) => None,
+        }
+    }
+
+    /// Dispatches `event` to this target and its ancestors. The listeners for the capturing
+    /// phase are called from the root to the target, and the others from the target to the
+    /// root if the event bubbles. Returns false if a listener canceled the event.
+    /// https://dom.spec.whatwg.org/#concept-event-dispatch
+    pub fn dispatch_event(&self, event: &mut Event) -> Result<bool, DomException> {
+        if event.dispatching {
+            return Err(DomException::InvalidStateError);
+        }
+        event.dispatching = true;
+        event.target = Some(self.clone());
+
+        let mut path = Vec::new();
+        let mut target = Some(self.clone());
+        while let Some(t) = target {
+            target = t.parent(event);
+            path.push(t);
+        }
+
+        for (i, target) in path.iter().enumerate().rev() {
+            event.phase = match i {
+                0 => EventPhase::AtTarget,
+                _ => EventPhase::Capturing,
+            };
+            invoke(target, event, /*capture*/ true);
+        }
+        for (i, target) in path.iter().enumerate() {
+            if i > 0 && !event.bubbles {
+                break;
+            }
+            event.phase = match i {
+                0 => EventPhase::AtTarget,
+                _ => EventPhase::Bubbling,
+            };
+            invoke(target, event, /*capture*/ false);
+        }
+
+        event.phase = EventPhase::None;
+        event.current_target = None;
+        event.dispatching = false;
+        event.stop_propagation = false;
+        event.stop_immediate_propagation = false;
+        Ok(!event.canceled)
+    }
+}
+
+/// Calls the listeners of `target` for the phase. The listeners are copied first, so that
+/// listeners added during the dispatch aren't called.
+/// https://dom.spec.whatwg.org/#concept-event-listener-invoke
+fn invoke(target: &EventTarget, event: &mut Event, capture: bool) {
+    if event.stop_propagation {
+        return;
+    }
+    event.current_target = Some(target.clone());
+
+    let listeners: Vec<EventListener> = target.with_listeners(|listeners| listeners.clone());
+    for listener in listeners {
+        if listener.event_type != event.event_type
+            || listener.options.capture != capture
+            || listener.removed.get()
+        {
+            continue;
+        }
+        if listener.options.once {
+            target.remove_event_listener(&listener.event_type, &listener.callback, capture);
+        }
+
+        event.in_passive_listener = listener.options.passive;
+        (listener.callback)(event);
+        event.in_passive_listener = false;
+        if event.stop_immediate_propagation {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::test_utils::element_by_id;
+    use crate::renderer::dom::test_utils::parse;
+    use alloc::format;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    /// Returns a listener that records `name` and the phase in `log`.
+    fn logger(log: &Rc<RefCell<Vec<String>>>, name: &str) -> EventListenerCallback {
+        let log = log.clone();
+        let name = name.to_string();
+        Rc::new(move |event: &mut Event| {
+            log.borrow_mut()
+                .push(format!("{}:{:?}", name, event.event_phase()));
+        })
+    }
+
+    fn capture() -> EventListenerOptions {
+        EventListenerOptions {
+            capture: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dispatch_phases() {
+        let window = parse("<div id=d><a id=a>link</a></div>");
+        let div = EventTarget::Node(element_by_id(&window, "d"));
+        let a = EventTarget::Node(element_by_id(&window, "a"));
+        let window = EventTarget::Window(window);
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        window.add_event_listener("click", logger(&log, "window"), capture());
+        window.add_event_listener("click", logger(&log, "window"), Default::default());
+        div.add_event_listener("click", logger(&log, "div"), capture());
+        div.add_event_listener("click", logger(&log, "div"), Default::default());
+        a.add_event_listener("click", logger(&log, "a"), Default::default());
+        a.add_event_listener("click", logger(&log, "a-capture"), capture());
+        div.add_event_listener("keydown", logger(&log, "keydown"), Default::default());
+
+        let mut event = Event::new("click", true, true);
+        assert_eq!(Ok(true), a.dispatch_event(&mut event));
+        assert_eq!(
+            vec![
+                "window:Capturing",
+                "div:Capturing",
+                "a-capture:AtTarget",
+                "a:AtTarget",
+                "div:Bubbling",
+                "window:Bubbling",
+            ],
+            *log.borrow()
+        );
+        assert_eq!(Some(a.clone()), event.target());
+        assert_eq!(None, event.current_target());
+        assert_eq!(EventPhase::None, event.event_phase());
+
+        // Events that don't bubble only reach the target after the capturing phase.
+        log.borrow_mut().clear();
+        a.dispatch_event(&mut Event::new("click", false, false))
+            .unwrap();
+        assert_eq!(
+            vec![
+                "window:Capturing",
+                "div:Capturing",
+                "a-capture:AtTarget",
+                "a:AtTarget",
+            ],
+            *log.borrow()
+        );
+    }
+
+    #[test]
+    fn test_prevent_default_and_stop_propagation() {
+        let window = parse("<div id=d><a id=a>link</a></div>");
+        let div = EventTarget::Node(element_by_id(&window, "d"));
+        let a = EventTarget::Node(element_by_id(&window, "a"));
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        a.add_event_listener(
+            "click",
+            Rc::new(|event: &mut Event| {
+                event.prevent_default();
+                event.stop_immediate_propagation();
+            }),
+            Default::default(),
+        );
+        a.add_event_listener("click", logger(&log, "a"), Default::default());
+        div.add_event_listener("click", logger(&log, "div"), Default::default());
+
+        assert_eq!(
+            Ok(false),
+            a.dispatch_event(&mut Event::new("click", true, true))
+        );
+        assert!(log.borrow().is_empty());
+        // The default action of an event that isn't cancelable can't be prevented.
+        assert_eq!(
+            Ok(true),
+            a.dispatch_event(&mut Event::new("click", true, false))
+        );
+    }
+
+    #[test]
+    fn test_add_and_remove_listeners() {
+        let window = parse("<p id=p>");
+        let p = EventTarget::Node(element_by_id(&window, "p"));
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let listener = logger(&log, "p");
+        // The same listener is added only once per phase.
+        p.add_event_listener("click", listener.clone(), Default::default());
+        p.add_event_listener("click", listener.clone(), Default::default());
+        p.add_event_listener(
+            "click",
+            logger(&log, "once"),
+            EventListenerOptions {
+                once: true,
+                ..Default::default()
+            },
+        );
+        p.dispatch_event(&mut Event::new("click", false, false))
+            .unwrap();
+        p.dispatch_event(&mut Event::new("click", false, false))
+            .unwrap();
+        assert_eq!(
+            vec!["p:AtTarget", "once:AtTarget", "p:AtTarget"],
+            *log.borrow()
+        );
+
+        log.borrow_mut().clear();
+        p.remove_event_listener("click", &listener, false);
+        p.dispatch_event(&mut Event::new("click", false, false))
+            .unwrap();
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_dispatch_while_dispatching() {
+        let window = parse("<p id=p>");
+        let p = EventTarget::Node(element_by_id(&window, "p"));
+
+        let result = Rc::new(RefCell::new(None));
+        let r = result.clone();
+        let target = p.clone();
+        p.add_event_listener(
+            "click",
+            Rc::new(move |event: &mut Event| {
+                *r.borrow_mut() = Some(target.dispatch_event(event));
+            }),
+            Default::default(),
+        );
+        p.dispatch_event(&mut Event::new("click", false, false))
+            .unwrap();
+        assert_eq!(Some(Err(DomException::InvalidStateError)), *result.borrow());
+    }
+}
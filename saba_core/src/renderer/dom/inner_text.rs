@@ -0,0 +1,271 @@
+//! The rendered text of an element with collapsed whitespace and line breaks between blocks.
+//! https://html.spec.whatwg.org/multipage/dom.html#the-innertext-idl-attribute
+
+use crate::renderer::dom::api::children;
+use crate::renderer::dom::api::text_content;
+use crate::renderer::dom::node::Element;
+use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeKind;
+use crate::renderer::dom::traversal::ancestors;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// Returns the text of `node` as it's rendered with the default styles. Elements such as
+/// <script> and <head> are skipped, blocks are separated by line breaks, and <br> is a line
+/// break. Whitespace in text is collapsed except in elements such as <pre>. Returns the text
+/// content if `node` itself isn't rendered. Use `LayoutView::inner_text` for the text with the
+/// styles of a page.
+pub fn inner_text(node: &Rc<RefCell<Node>>) -> String {
+    if node.borrow().is_hidden_by_default() {
+        return text_content(node).unwrap_or_default();
+    }
+
+    enum Step {
+        Enter(Rc<RefCell<Node>>),
+        Leave(ElementEnd),
+    }
+
+    let mut builder = InnerTextBuilder::new();
+    let in_preformatted = core::iter::once(node.clone())
+        .chain(ancestors(node))
+        .any(|n| {
+            n.borrow()
+                .get_element()
+                .is_some_and(|e| e.preserves_whitespace())
+        });
+    if in_preformatted {
+        builder.preformatted += 1;
+    }
+
+    let mut stack: Vec<Step> = children_of(node)
+        .into_iter()
+        .rev()
+        .map(Step::Enter)
+        .collect();
+    while let Some(step) = stack.pop() {
+        let node = match step {
+            Step::Enter(node) => node,
+            Step::Leave(end) => {
+                builder.leave_element(end);
+                continue;
+            }
+        };
+        if node.borrow().is_hidden_by_default() {
+            continue;
+        }
+        match node.borrow().kind {
+            NodeKind::Text(ref text) => builder.push_text(text),
+            NodeKind::Element(ref element) => {
+                let end = builder.enter_element(element, element.is_block_element());
+                stack.push(Step::Leave(end));
+            }
+            _ => {}
+        }
+        stack.extend(children_of(&node).into_iter().rev().map(Step::Enter));
+    }
+    builder.finish()
+}
+
+fn children_of(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+    match node.borrow().kind {
+        // Only the text of a text node is added, not its children.
+        NodeKind::Text(_) => vec![],
+        _ => children(node),
+    }
+}
+
+/// Returns the number of line breaks required before and after `element`. A paragraph is
+/// separated by an empty line.
+fn required_line_breaks(element: &Element, is_block: bool) -> usize {
+    match (element.kind(), is_block) {
+        (ElementKind::P, true) => 2,
+        (_, true) => 1,
+        (_, false) => 0,
+    }
+}
+
+/// Builds the text from text nodes and line breaks in tree order.
+/// https://html.spec.whatwg.org/multipage/dom.html#rendered-text-collection-steps
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InnerTextBuilder {
+    text: String,
+    /// The line breaks required before the next text, which are dropped at the start and at
+    /// the end of the text.
+    pending_line_breaks: usize,
+    /// Whether there was whitespace before the next text on the same line.
+    pending_space: bool,
+    /// The number of elements such as <pre> the text is in, where whitespace is kept.
+    preformatted: usize,
+}
+
+/// What to add after the contents of an element. See `InnerTextBuilder::enter_element()`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ElementEnd {
+    line_breaks: usize,
+    preformatted: bool,
+}
+
+impl InnerTextBuilder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the line breaks before `element`, or a line break for <br>. `is_block` is whether
+    /// the element is rendered as a block. The returned value is passed to `leave_element()`
+    /// after the contents of the element.
+    pub(crate) fn enter_element(&mut self, element: &Element, is_block: bool) -> ElementEnd {
+        if element.kind() == ElementKind::Br {
+            self.push_line_break();
+        }
+        let end = ElementEnd {
+            line_breaks: required_line_breaks(element, is_block),
+            preformatted: element.preserves_whitespace(),
+        };
+        self.push_line_breaks(end.line_breaks);
+        if end.preformatted {
+            self.preformatted += 1;
+        }
+        end
+    }
+
+    pub(crate) fn leave_element(&mut self, end: ElementEnd) {
+        self.push_line_breaks(end.line_breaks);
+        if end.preformatted {
+            self.preformatted -= 1;
+        }
+    }
+
+    /// Adds the data of a text node. A sequence of whitespace becomes a single space, which is
+    /// dropped at the start and at the end of a line. Whitespace is kept in elements such as
+    /// <pre>.
+    pub(crate) fn push_text(&mut self, text: &str) {
+        if self.preformatted > 0 {
+            if !text.is_empty() {
+                self.flush();
+                self.text.push_str(text);
+            }
+            return;
+        }
+        for c in text.chars() {
+            if c.is_ascii_whitespace() {
+                self.pending_space = true;
+                continue;
+            }
+            self.flush();
+            self.text.push(c);
+        }
+    }
+
+    /// Requires `count` line breaks between the text before and the text after.
+    fn push_line_breaks(&mut self, count: usize) {
+        self.pending_line_breaks = self.pending_line_breaks.max(count);
+        if count > 0 {
+            self.pending_space = false;
+        }
+    }
+
+    /// Adds a line break of <br>, which is kept even at the start or at the end.
+    fn push_line_break(&mut self) {
+        if self.pending_line_breaks > 0 && !self.text.is_empty() {
+            self.flush();
+        }
+        self.pending_line_breaks = 0;
+        self.pending_space = false;
+        self.text.push('\n');
+    }
+
+    fn flush(&mut self) {
+        if self.pending_line_breaks > 0 {
+            if !self.text.is_empty() {
+                for _ in 0..self.pending_line_breaks {
+                    self.text.push('\n');
+                }
+            }
+        } else if self.pending_space && !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push(' ');
+        }
+        self.pending_line_breaks = 0;
+        self.pending_space = false;
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.text
+    }
+}
+
+/// Collapses each sequence of whitespace in `text` into a single space.
+pub fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::new();
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !in_whitespace {
+                result.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            result.push(c);
+            in_whitespace = false;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::test_utils::element_by_id;
+    use crate::renderer::dom::test_utils::parse;
+
+    #[test]
+    fn test_inner_text() {
+        let window = parse(
+            "<div id=d>\n  <h1>Title</h1>\n  <p>a   <b>b</b>\n c</p><p>d<br>e</p>\
+             <div>f<script>g</script><div> h </div></div></div>",
+        );
+        let div = element_by_id(&window, "d");
+        assert_eq!("Title\n\na b c\n\nd\ne\n\nf\nh", inner_text(&div));
+    }
+
+    #[test]
+    fn test_inner_text_not_rendered() {
+        let window = parse("<head><title id=t> a  b </title></head>");
+        let title = element_by_id(&window, "t");
+        assert_eq!(" a  b ", inner_text(&title));
+    }
+
+    #[test]
+    fn test_inner_text_preformatted() {
+        let window = parse(
+            "<div id=d>a  b<pre id=p>\n  c\n\n d <b>e  f</b></pre>\
+             <textarea>g\n h</textarea><br><listing>\ni  j</listing> k  l</div>",
+        );
+        let div = element_by_id(&window, "d");
+        // The first newline in <pre>, <textarea> and <listing> is dropped by the parser.
+        assert_eq!("a b\n  c\n\n d e  f\ng\n h\ni  j k l", inner_text(&div));
+        let pre = element_by_id(&window, "p");
+        assert_eq!("  c\n\n d e  f", inner_text(&pre));
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let mut builder = InnerTextBuilder::new();
+        builder.push_line_breaks(2);
+        builder.push_line_break();
+        builder.push_text(" a ");
+        builder.push_line_breaks(1);
+        builder.push_line_breaks(2);
+        builder.push_text("b");
+        builder.push_line_breaks(1);
+        assert_eq!("\na\n\nb", builder.finish());
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        assert_eq!(" a b ", collapse_whitespace("\n a \t\n b\n"));
+    }
+}
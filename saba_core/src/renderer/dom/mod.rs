@@ -1,2 +1,10 @@
 pub mod api;
+pub mod event;
+pub mod inner_text;
+pub mod mutation_observer;
 pub mod node;
+pub mod serializer;
+pub mod traversal;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
@@ -0,0 +1,464 @@
+//! Observers that get records of the changes to the DOM tree made through the DOM API and the
+//! HTML parser.
+//! https://dom.spec.whatwg.org/#mutation-observers
+
+use crate::renderer::dom::api::DomException;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::Window;
+use crate::renderer::dom::traversal::ancestors;
+use crate::renderer::html::attribute::AttributeNamespace;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::rc::Weak;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Debug;
+use core::fmt::Formatter;
+
+/// The function called with the records queued since the last call.
+pub type MutationCallback = Box<dyn Fn(Vec<MutationRecord>, &Rc<MutationObserver>)>;
+
+/// The kinds of changes an observer is interested in.
+/// https://dom.spec.whatwg.org/#dictdef-mutationobserverinit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MutationObserverInit {
+    /// Insertion and removal of children.
+    pub child_list: bool,
+    pub attributes: bool,
+    /// Changes to the data of text and comments.
+    pub character_data: bool,
+    /// Observes the descendants of the node too.
+    pub subtree: bool,
+    /// Records the value of an attribute before the change. This implies `attributes`.
+    pub attribute_old_value: bool,
+    /// Records the data before the change. This implies `character_data`.
+    pub character_data_old_value: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutationRecordType {
+    ChildList,
+    Attributes,
+    CharacterData,
+}
+
+/// https://dom.spec.whatwg.org/#interface-mutationrecord
+#[derive(Debug, Clone)]
+pub struct MutationRecord {
+    pub record_type: MutationRecordType,
+    /// The parent of the added and removed nodes, the element whose attribute changed, or the
+    /// text or comment whose data changed.
+    pub target: Rc<RefCell<Node>>,
+    pub added_nodes: Vec<Rc<RefCell<Node>>>,
+    pub removed_nodes: Vec<Rc<RefCell<Node>>>,
+    /// The siblings around the added or removed nodes.
+    pub previous_sibling: Option<Rc<RefCell<Node>>>,
+    pub next_sibling: Option<Rc<RefCell<Node>>>,
+    /// The local name of the changed attribute.
+    pub attribute_name: Option<String>,
+    pub attribute_namespace: Option<AttributeNamespace>,
+    /// The value before the change if the observer asked for it.
+    pub old_value: Option<String>,
+}
+
+/// https://dom.spec.whatwg.org/#interface-mutationobserver
+pub struct MutationObserver {
+    callback: MutationCallback,
+    records: RefCell<Vec<MutationRecord>>,
+    /// The nodes this is registered to.
+    nodes: RefCell<Vec<Weak<RefCell<Node>>>>,
+}
+
+impl Debug for MutationObserver {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        f.debug_struct("MutationObserver")
+            .field("records", &self.records)
+            .finish()
+    }
+}
+
+/// An observer registered to a node with its options. The node doesn't keep the observer
+/// alive, because the callback of an observer usually refers to the node.
+/// https://dom.spec.whatwg.org/#registered-observer
+#[derive(Debug, Clone)]
+pub struct RegisteredObserver {
+    observer: Weak<MutationObserver>,
+    options: MutationObserverInit,
+}
+
+impl RegisteredObserver {
+    fn is(&self, observer: *const MutationObserver) -> bool {
+        self.observer.as_ptr() == observer
+    }
+}
+
+impl MutationObserver {
+    pub fn new(callback: MutationCallback) -> Rc<Self> {
+        Rc::new(Self {
+            callback,
+            records: RefCell::new(Vec::new()),
+            nodes: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Starts observing `target`. If this already observes `target`, the options are replaced.
+    /// Returns TypeError if the options don't ask for any kind of change.
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-observe
+    pub fn observe(
+        self: &Rc<Self>,
+        target: &Rc<RefCell<Node>>,
+        options: MutationObserverInit,
+    ) -> Result<(), DomException> {
+        let mut options = options;
+        options.attributes |= options.attribute_old_value;
+        options.character_data |= options.character_data_old_value;
+        if !options.child_list && !options.attributes && !options.character_data {
+            return Err(DomException::TypeError);
+        }
+
+        let window = target.borrow().window().upgrade();
+        if let Some(window) = window {
+            window.borrow_mut().set_has_mutation_observers();
+        }
+
+        let mut node = target.borrow_mut();
+        let registered = node.registered_observers_mut();
+        match registered.iter_mut().find(|r| r.is(Rc::as_ptr(self))) {
+            Some(r) => r.options = options,
+            None => {
+                registered.push(RegisteredObserver {
+                    observer: Rc::downgrade(self),
+                    options,
+                });
+                self.nodes.borrow_mut().push(Rc::downgrade(target));
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops observing all the nodes and drops the records that haven't been delivered.
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-disconnect
+    pub fn disconnect(self: &Rc<Self>) {
+        self.unregister();
+        self.records.borrow_mut().clear();
+    }
+
+    fn unregister(&self) {
+        for node in self.nodes.borrow_mut().drain(..) {
+            if let Some(node) = node.upgrade() {
+                node.borrow_mut()
+                    .registered_observers_mut()
+                    .retain(|r| !r.is(self));
+            }
+        }
+    }
+
+    /// Returns the records that haven't been delivered and removes them from the queue.
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-takerecords
+    pub fn take_records(&self) -> Vec<MutationRecord> {
+        core::mem::take(&mut *self.records.borrow_mut())
+    }
+}
+
+impl Drop for MutationObserver {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+/// Calls the observers that have records queued in the tree of `window` with their records.
+/// This should be called at the end of each task, e.g. after handling an input event, so that
+/// the changes made in the task are delivered in a batch. Records queued by the callbacks are
+/// delivered before this returns.
+/// https://dom.spec.whatwg.org/#notify-mutation-observers
+pub fn notify_mutation_observers(window: &Rc<RefCell<Window>>) {
+    loop {
+        let observers = core::mem::take(window.borrow_mut().pending_mutation_observers_mut());
+        if observers.is_empty() {
+            return;
+        }
+        for observer in observers {
+            let records = observer.take_records();
+            if !records.is_empty() {
+                (observer.callback)(records, &observer);
+            }
+        }
+    }
+}
+
+/// Queues a record of `added` and `removed` children of `target` for the observers.
+/// https://dom.spec.whatwg.org/#queue-a-tree-mutation-record
+pub(crate) fn queue_child_list_record(
+    target: &Rc<RefCell<Node>>,
+    added: Vec<Rc<RefCell<Node>>>,
+    removed: Vec<Rc<RefCell<Node>>>,
+    previous_sibling: Option<Rc<RefCell<Node>>>,
+    next_sibling: Option<Rc<RefCell<Node>>>,
+) {
+    if added.is_empty() && removed.is_empty() {
+        return;
+    }
+    let record = MutationRecord {
+        record_type: MutationRecordType::ChildList,
+        target: target.clone(),
+        added_nodes: added,
+        removed_nodes: removed,
+        previous_sibling,
+        next_sibling,
+        attribute_name: None,
+        attribute_namespace: None,
+        old_value: None,
+    };
+    queue_mutation_record(record, None);
+}
+
+/// Queues a record of a change to the attribute `local_name` of `target`.
+pub(crate) fn queue_attribute_record(
+    target: &Rc<RefCell<Node>>,
+    local_name: &str,
+    namespace: Option<AttributeNamespace>,
+    old_value: Option<String>,
+) {
+    let record = MutationRecord {
+        record_type: MutationRecordType::Attributes,
+        target: target.clone(),
+        added_nodes: Vec::new(),
+        removed_nodes: Vec::new(),
+        previous_sibling: None,
+        next_sibling: None,
+        attribute_name: Some(String::from(local_name)),
+        attribute_namespace: namespace,
+        old_value: None,
+    };
+    queue_mutation_record(record, old_value);
+}
+
+/// Queues a record of a change to the data of the text or comment `target`.
+pub(crate) fn queue_character_data_record(target: &Rc<RefCell<Node>>, old_value: String) {
+    let record = MutationRecord {
+        record_type: MutationRecordType::CharacterData,
+        target: target.clone(),
+        added_nodes: Vec::new(),
+        removed_nodes: Vec::new(),
+        previous_sibling: None,
+        next_sibling: None,
+        attribute_name: None,
+        attribute_namespace: None,
+        old_value: None,
+    };
+    queue_mutation_record(record, Some(old_value));
+}
+
+/// Gives a copy of `record` to each observer of the target or its ancestors that is interested
+/// in it. The old value is only given to the observers that asked for it.
+/// https://dom.spec.whatwg.org/#queue-a-mutation-record
+fn queue_mutation_record(record: MutationRecord, old_value: Option<String>) {
+    // Most documents have no observers, so the ancestors of each node the parser inserts
+    // aren't walked for them.
+    let window = record.target.borrow().window().upgrade();
+    if window
+        .as_ref()
+        .is_some_and(|w| !w.borrow().has_mutation_observers())
+    {
+        return;
+    }
+
+    // The observers with whether they want the old value.
+    let mut interested: Vec<(Rc<MutationObserver>, bool)> = Vec::new();
+    let nodes = core::iter::once(record.target.clone()).chain(ancestors(&record.target));
+    for node in nodes {
+        let is_target = Rc::ptr_eq(&node, &record.target);
+        for registered in node.borrow().registered_observers() {
+            let observer = match registered.observer.upgrade() {
+                Some(observer) => observer,
+                None => continue,
+            };
+            let options = registered.options;
+            let wants = match record.record_type {
+                MutationRecordType::ChildList => options.child_list,
+                MutationRecordType::Attributes => options.attributes,
+                MutationRecordType::CharacterData => options.character_data,
+            };
+            if !wants || (!is_target && !options.subtree) {
+                continue;
+            }
+            let wants_old_value = match record.record_type {
+                MutationRecordType::ChildList => false,
+                MutationRecordType::Attributes => options.attribute_old_value,
+                MutationRecordType::CharacterData => options.character_data_old_value,
+            };
+            match interested
+                .iter_mut()
+                .find(|(o, _)| Rc::ptr_eq(o, &observer))
+            {
+                Some((_, old)) => *old |= wants_old_value,
+                None => interested.push((observer, wants_old_value)),
+            }
+        }
+    }
+    if interested.is_empty() {
+        return;
+    }
+
+    for (observer, wants_old_value) in interested {
+        let mut record = record.clone();
+        if wants_old_value {
+            record.old_value = old_value.clone();
+        }
+        observer.records.borrow_mut().push(record);
+
+        // Observers of nodes without a window only get the records from `take_records`.
+        if let Some(ref window) = window {
+            let mut window = window.borrow_mut();
+            let pending = window.pending_mutation_observers_mut();
+            if !pending.iter().any(|o| Rc::ptr_eq(o, &observer)) {
+                pending.push(observer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::api::append_child;
+    use crate::renderer::dom::api::remove_child;
+    use crate::renderer::dom::api::set_attribute;
+    use crate::renderer::dom::api::set_data;
+    use crate::renderer::dom::test_utils::element;
+    use crate::renderer::dom::test_utils::element_by_id;
+    use crate::renderer::dom::test_utils::parse;
+    use alloc::string::ToString;
+
+    /// Returns an observer that appends the records it gets to `log`.
+    fn observer(log: &Rc<RefCell<Vec<Vec<MutationRecord>>>>) -> Rc<MutationObserver> {
+        let log = log.clone();
+        MutationObserver::new(Box::new(move |records, _| log.borrow_mut().push(records)))
+    }
+
+    #[test]
+    fn test_child_list() {
+        let window = parse("<div id=d><p id=p></p></div>");
+        let div = element_by_id(&window, "d");
+        let p = element_by_id(&window, "p");
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let observer = observer(&log);
+        let options = MutationObserverInit {
+            child_list: true,
+            ..Default::default()
+        };
+        observer.observe(&div, options).unwrap();
+
+        let span = element("span");
+        append_child(&div, &span).unwrap();
+        remove_child(&div, &p).unwrap();
+        // Changes to descendants aren't observed without subtree.
+        append_child(&span, &p).unwrap();
+        assert!(log.borrow().is_empty());
+
+        // The records are delivered in a batch.
+        notify_mutation_observers(&window);
+        assert_eq!(1, log.borrow().len());
+        let records = &log.borrow()[0];
+        assert_eq!(2, records.len());
+        assert_eq!(MutationRecordType::ChildList, records[0].record_type);
+        assert!(Rc::ptr_eq(&span, &records[0].added_nodes[0]));
+        assert!(Rc::ptr_eq(
+            &p,
+            records[0].previous_sibling.as_ref().unwrap()
+        ));
+        assert!(Rc::ptr_eq(&p, &records[1].removed_nodes[0]));
+        assert!(records[1].previous_sibling.is_none());
+        assert!(Rc::ptr_eq(&span, records[1].next_sibling.as_ref().unwrap()));
+
+        observer.disconnect();
+        remove_child(&div, &span).unwrap();
+        notify_mutation_observers(&window);
+        assert_eq!(1, log.borrow().len());
+    }
+
+    #[test]
+    fn test_attributes_and_character_data() {
+        let window = parse("<div id=d><p id=p>a</p></div>");
+        let div = element_by_id(&window, "d");
+        let p = element_by_id(&window, "p");
+        let text = p.borrow().first_child().unwrap();
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let observer = observer(&log);
+        let options = MutationObserverInit {
+            subtree: true,
+            attribute_old_value: true,
+            character_data: true,
+            ..Default::default()
+        };
+        observer.observe(&div, options).unwrap();
+
+        set_attribute(&p, "class", "x").unwrap();
+        set_attribute(&p, "class", "y").unwrap();
+        set_data(&text, "b").unwrap();
+        let records = observer.take_records();
+        assert_eq!(3, records.len());
+        assert_eq!(Some("class".to_string()), records[0].attribute_name);
+        assert_eq!(None, records[0].old_value);
+        assert_eq!(Some("x".to_string()), records[1].old_value);
+        assert_eq!(MutationRecordType::CharacterData, records[2].record_type);
+        // The old data isn't recorded without character_data_old_value.
+        assert_eq!(None, records[2].old_value);
+
+        // The taken records aren't delivered.
+        notify_mutation_observers(&window);
+        assert!(log.borrow().is_empty());
+        assert_eq!(
+            Err(DomException::TypeError),
+            observer.observe(&div, MutationObserverInit::default())
+        );
+    }
+
+    #[test]
+    fn test_observer_lifetime() {
+        let window = parse("<div id=d></div>");
+        // Nothing is queued for the nodes inserted by the parser.
+        assert!(!window.borrow().has_mutation_observers());
+        let div = element_by_id(&window, "d");
+
+        // The callback refers to the node, but the node doesn't keep the observer alive.
+        let target = div.clone();
+        let observer = MutationObserver::new(Box::new(move |_, _| {
+            target.borrow();
+        }));
+        let options = MutationObserverInit {
+            child_list: true,
+            ..Default::default()
+        };
+        observer.observe(&div, options).unwrap();
+        assert!(window.borrow().has_mutation_observers());
+        let weak = Rc::downgrade(&observer);
+        drop(observer);
+        assert!(weak.upgrade().is_none());
+        assert!(div.borrow().registered_observers().is_empty());
+    }
+
+    #[test]
+    fn test_observed_node_inserted_into_document() {
+        let window = parse("<div id=d></div>");
+        let div = element_by_id(&window, "d");
+        let span = element("span");
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let observer = observer(&log);
+        let options = MutationObserverInit {
+            child_list: true,
+            ..Default::default()
+        };
+        observer.observe(&span, options).unwrap();
+        assert!(!window.borrow().has_mutation_observers());
+
+        append_child(&div, &span).unwrap();
+        append_child(&span, &element("b")).unwrap();
+        notify_mutation_observers(&window);
+        assert_eq!(1, log.borrow().len());
+    }
+}
@@ -1,4 +1,10 @@
+use crate::renderer::dom::event::EventListener;
+use crate::renderer::dom::mutation_observer::MutationObserver;
+use crate::renderer::dom::mutation_observer::RegisteredObserver;
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::attribute::AttributeNamespace;
+use crate::renderer::html::attribute::Attributes;
+use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::rc::Rc;
 use alloc::rc::Weak;
@@ -12,19 +18,25 @@ use core::str::FromStr;
 #[derive(Debug, Clone)]
 pub enum NodeKind {
     Document,
+    DocumentType(DocumentType),
+    /// https://dom.spec.whatwg.org/#interface-documentfragment
+    DocumentFragment,
     Element(Element),
     Text(String),
+    Comment(String),
 }
 
+/// Compares the names and attributes of elements, and the data of text and comments.
 impl PartialEq for NodeKind {
     fn eq(&self, other: &Self) -> bool {
-        match &self {
-            NodeKind::Document => matches!(other, NodeKind::Document),
-            NodeKind::Element(e1) => match &other {
-                NodeKind::Element(e2) => e1.kind == e2.kind,
-                _ => false,
-            },
-            NodeKind::Text(_) => matches!(other, NodeKind::Text(_)),
+        match (self, other) {
+            (NodeKind::Document, NodeKind::Document) => true,
+            (NodeKind::DocumentFragment, NodeKind::DocumentFragment) => true,
+            (NodeKind::DocumentType(d1), NodeKind::DocumentType(d2)) => d1 == d2,
+            (NodeKind::Element(e1), NodeKind::Element(e2)) => e1 == e2,
+            (NodeKind::Text(s1), NodeKind::Text(s2)) => s1 == s2,
+            (NodeKind::Comment(s1), NodeKind::Comment(s2)) => s1 == s2,
+            _ => false,
         }
     }
 }
@@ -32,12 +44,24 @@ impl PartialEq for NodeKind {
 #[derive(Debug, Clone)]
 pub struct Node {
     pub kind: NodeKind,
+    /// The window of a document. This is empty for other nodes, which reach their window
+    /// through their owner document.
     window: Weak<RefCell<Window>>,
+    owner_document: Weak<RefCell<Node>>,
+    /// The elements in a document by their id, which are kept up to date when nodes are
+    /// inserted and removed. This is empty for other nodes.
+    ids: BTreeMap<String, Vec<Weak<RefCell<Node>>>>,
+    /// The mode of a document, which is decided by its DOCTYPE. This is NoQuirks for other
+    /// nodes.
+    /// https://dom.spec.whatwg.org/#concept-document-mode
+    quirks_mode: QuirksMode,
     parent: Weak<RefCell<Node>>,
     first_child: Option<Rc<RefCell<Node>>>,
     last_child: Weak<RefCell<Node>>,
     prev_sibling: Weak<RefCell<Node>>,
     next_sibling: Option<Rc<RefCell<Node>>>,
+    event_listeners: Vec<EventListener>,
+    registered_observers: Vec<RegisteredObserver>,
 }
 
 impl Node {
@@ -45,14 +69,31 @@ impl Node {
         Node {
             kind,
             window: Weak::new(),
+            owner_document: Weak::new(),
+            ids: BTreeMap::new(),
+            quirks_mode: QuirksMode::NoQuirks,
             parent: Weak::new(),
             first_child: None,
             last_child: Weak::new(),
             prev_sibling: Weak::new(),
             next_sibling: None,
+            event_listeners: Vec::new(),
+            registered_observers: Vec::new(),
         }
     }
 
+    pub(crate) fn event_listeners_mut(&mut self) -> &mut Vec<EventListener> {
+        &mut self.event_listeners
+    }
+
+    pub(crate) fn registered_observers(&self) -> &[RegisteredObserver] {
+        &self.registered_observers
+    }
+
+    pub(crate) fn registered_observers_mut(&mut self) -> &mut Vec<RegisteredObserver> {
+        &mut self.registered_observers
+    }
+
     pub fn set_parent(&mut self, parent: Weak<RefCell<Node>>) {
         self.parent = parent;
     }
@@ -97,67 +138,313 @@ impl Node {
         self.window = window;
     }
 
+    /// Returns the window of the document that this node belongs to. This is empty for a
+    /// document without a browsing context, e.g. the owner document of template contents.
+    pub fn window(&self) -> Weak<RefCell<Window>> {
+        match self.kind {
+            NodeKind::Document => self.window.clone(),
+            _ => match self.owner_document.upgrade() {
+                Some(document) => document.borrow().window(),
+                None => Weak::new(),
+            },
+        }
+    }
+
+    pub fn set_owner_document(&mut self, owner_document: Weak<RefCell<Node>>) {
+        self.owner_document = owner_document;
+    }
+
+    pub fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.quirks_mode = quirks_mode;
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    pub(crate) fn add_id(&mut self, id: &str, element: Weak<RefCell<Node>>) {
+        let elements = self.ids.entry(String::from(id)).or_default();
+        if !elements.iter().any(|e| e.ptr_eq(&element)) {
+            elements.push(element);
+        }
+    }
+
+    pub(crate) fn remove_id(&mut self, id: &str, element: &Rc<RefCell<Node>>) {
+        if let Some(elements) = self.ids.get_mut(id) {
+            elements.retain(|e| !e.ptr_eq(&Rc::downgrade(element)));
+            if elements.is_empty() {
+                self.ids.remove(id);
+            }
+        }
+    }
+
+    /// Returns the elements in this document whose id is `id`, in no particular order.
+    pub(crate) fn elements_with_id(&self, id: &str) -> Vec<Rc<RefCell<Node>>> {
+        match self.ids.get(id) {
+            Some(elements) => elements.iter().filter_map(|e| e.upgrade()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the document that this node belongs to. This is empty for a document and for a
+    /// node that has never been inserted into a document.
+    /// https://dom.spec.whatwg.org/#dom-node-ownerdocument
+    pub fn owner_document(&self) -> Weak<RefCell<Node>> {
+        self.owner_document.clone()
+    }
+
     pub fn kind(&self) -> NodeKind {
         self.kind.clone()
     }
 
     pub fn get_element(&self) -> Option<Element> {
         match self.kind {
-            NodeKind::Document | NodeKind::Text(_) => None,
             NodeKind::Element(ref element) => Some(element.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is an element that can't have any contents, e.g. <br> or <img>.
+    /// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+    pub fn is_void_element(&self) -> bool {
+        match self.kind {
+            NodeKind::Element(ref element) => {
+                element.namespace() == Namespace::Html
+                    && matches!(
+                        element.local_name.as_str(),
+                        "area"
+                            | "base"
+                            | "basefont"
+                            | "bgsound"
+                            | "br"
+                            | "col"
+                            | "embed"
+                            | "frame"
+                            | "hr"
+                            | "img"
+                            | "input"
+                            | "keygen"
+                            | "link"
+                            | "meta"
+                            | "param"
+                            | "source"
+                            | "track"
+                            | "wbr"
+                    )
+            }
+            _ => false,
         }
     }
 
     pub fn element_kind(&self) -> Option<ElementKind> {
         match self.kind {
-            NodeKind::Document | NodeKind::Text(_) => None,
             NodeKind::Element(ref element) => Some(element.kind()),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the node isn't rendered with the default styles, e.g. <head> or a
+    /// comment. Other elements are rendered as a block or inline by `is_block_element()`.
+    pub fn is_hidden_by_default(&self) -> bool {
+        match self.kind {
+            NodeKind::Element(ref element) => matches!(
+                element.kind(),
+                ElementKind::Head
+                    | ElementKind::Title
+                    | ElementKind::Base
+                    | ElementKind::Link
+                    | ElementKind::Meta
+                    | ElementKind::Style
+                    | ElementKind::Script
+                    | ElementKind::Template
+            ),
+            NodeKind::DocumentType(_) | NodeKind::Comment(_) => true,
+            NodeKind::Document | NodeKind::DocumentFragment | NodeKind::Text(_) => false,
         }
     }
 }
 
+/// Compares two nodes without their children. Use `is_equal_node()` to compare the trees, and
+/// `is_same_node()` to check if two nodes are the same node.
 impl PartialEq for Node {
     fn eq(&self, other: &Self) -> bool {
         self.kind == other.kind
     }
 }
 
+/// Drops the children and the following siblings with a stack instead of recursion, so that
+/// dropping a deeply nested tree doesn't overflow the stack.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut pending: Vec<Rc<RefCell<Node>>> = Vec::new();
+        pending.extend(self.first_child.take());
+        pending.extend(self.next_sibling.take());
+        while let Some(node) = pending.pop() {
+            // A node that is still used elsewhere is kept with its subtree.
+            if let Ok(node) = Rc::try_unwrap(node) {
+                let mut node = node.into_inner();
+                pending.extend(node.first_child.take());
+                pending.extend(node.next_sibling.take());
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Window {
     pub document: Rc<RefCell<Node>>,
+    /// A document without a browsing context that owns the contents of <template> elements, so
+    /// that e.g. images in them aren't loaded.
+    /// https://html.spec.whatwg.org/multipage/scripting.html#appropriate-template-contents-owner-document
+    template_contents_owner_document: Rc<RefCell<Node>>,
+    event_listeners: Vec<EventListener>,
+    /// The observers that have records to deliver.
+    pending_mutation_observers: Vec<Rc<MutationObserver>>,
+    /// Whether an observer has been registered to a node in the document. Mutation records
+    /// aren't queued until then.
+    has_mutation_observers: bool,
 }
 
 impl Window {
-    pub fn new() -> Self {
-        let window = Self {
-            document: Rc::new(RefCell::new(Node::new(NodeKind::Document))),
-        };
-
-        window
-            .document
-            .borrow_mut()
-            .set_window(Rc::downgrade(&Rc::new(RefCell::new(window.clone()))));
-
-        window
+    /// Returns a new window with an empty document. The window is returned in an Rc so that the
+    /// document can refer back to it.
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new_cyclic(|window| {
+            let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+            document.borrow_mut().set_window(window.clone());
+            RefCell::new(Self {
+                document,
+                template_contents_owner_document: Rc::new(RefCell::new(Node::new(
+                    NodeKind::Document,
+                ))),
+                event_listeners: Vec::new(),
+                pending_mutation_observers: Vec::new(),
+                has_mutation_observers: false,
+            })
+        })
     }
 
     pub fn document(&self) -> Rc<RefCell<Node>> {
         self.document.clone()
     }
+
+    pub fn template_contents_owner_document(&self) -> Rc<RefCell<Node>> {
+        self.template_contents_owner_document.clone()
+    }
+
+    pub(crate) fn event_listeners_mut(&mut self) -> &mut Vec<EventListener> {
+        &mut self.event_listeners
+    }
+
+    pub(crate) fn pending_mutation_observers_mut(&mut self) -> &mut Vec<Rc<MutationObserver>> {
+        &mut self.pending_mutation_observers
+    }
+
+    pub(crate) fn has_mutation_observers(&self) -> bool {
+        self.has_mutation_observers
+    }
+
+    pub(crate) fn set_has_mutation_observers(&mut self) {
+        self.has_mutation_observers = true;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentType {
+    name: String,
+    public_id: String,
+    system_id: String,
+}
+
+impl DocumentType {
+    pub fn new(name: String, public_id: String, system_id: String) -> Self {
+        Self {
+            name,
+            public_id,
+            system_id,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn public_id(&self) -> String {
+        self.public_id.clone()
+    }
+
+    pub fn system_id(&self) -> String {
+        self.system_id.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    Html,
+    MathMl,
+    Svg,
+}
+
+impl Namespace {
+    pub fn url(&self) -> &'static str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Element {
     pub kind: ElementKind,
-    pub attributes: Vec<Attribute>,
+    local_name: String,
+    namespace: Namespace,
+    attributes: Attributes,
+    /// The DocumentFragment holding the contents of a <template>, which aren't children of the
+    /// element so that they aren't rendered.
+    /// https://html.spec.whatwg.org/multipage/scripting.html#template-contents
+    template_contents: Option<Rc<RefCell<Node>>>,
 }
 
 impl Element {
     pub fn new(element_name: &str, attributes: Vec<Attribute>) -> Self {
+        Self::new_with_namespace(element_name, Namespace::Html, attributes)
+    }
+
+    pub fn new_with_namespace(
+        local_name: &str,
+        namespace: Namespace,
+        attributes: Vec<Attribute>,
+    ) -> Self {
+        // Only HTML elements are classified. e.g. <a> in SVG is not ElementKind::A.
+        let kind = match namespace {
+            Namespace::Html => {
+                ElementKind::from_local_name(local_name).unwrap_or(ElementKind::Unknown)
+            }
+            _ => ElementKind::Unknown,
+        };
+
+        let template_contents = match kind {
+            ElementKind::Template => {
+                Some(Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment))))
+            }
+            _ => None,
+        };
+
         Self {
-            kind: ElementKind::from_str(element_name)
-                .expect("failed to convert string to ElementKind"),
-            attributes,
+            kind,
+            local_name: String::from(local_name),
+            namespace,
+            attributes: Attributes::from(attributes),
+            template_contents,
         }
     }
 
@@ -165,47 +452,393 @@ impl Element {
         self.kind
     }
 
+    pub fn local_name(&self) -> &str {
+        &self.local_name
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+
+    /// Returns true if this is an HTML element whose local name is `local_name`.
+    pub fn is_html(&self, local_name: &str) -> bool {
+        self.namespace == Namespace::Html && self.local_name == local_name
+    }
+
+    /// Returns true if the whitespace in the text of this element is kept by default.
+    pub fn preserves_whitespace(&self) -> bool {
+        self.namespace == Namespace::Html
+            && matches!(
+                self.local_name.as_str(),
+                "pre" | "listing" | "plaintext" | "xmp" | "textarea"
+            )
+    }
+
     pub fn is_block_element(&self) -> bool {
-        match self.kind {
-            ElementKind::Body | ElementKind::P | ElementKind::H1 | ElementKind::H2 => true,
-            _ => false,
+        matches!(
+            self.kind,
+            ElementKind::Body
+                | ElementKind::Article
+                | ElementKind::Section
+                | ElementKind::Nav
+                | ElementKind::Aside
+                | ElementKind::H1
+                | ElementKind::H2
+                | ElementKind::H3
+                | ElementKind::H4
+                | ElementKind::H5
+                | ElementKind::H6
+                | ElementKind::Hgroup
+                | ElementKind::Header
+                | ElementKind::Footer
+                | ElementKind::Address
+                | ElementKind::Main
+                | ElementKind::P
+                | ElementKind::Hr
+                | ElementKind::Pre
+                | ElementKind::Blockquote
+                | ElementKind::Ol
+                | ElementKind::Ul
+                | ElementKind::Menu
+                | ElementKind::Li
+                | ElementKind::Dl
+                | ElementKind::Dt
+                | ElementKind::Dd
+                | ElementKind::Figure
+                | ElementKind::Figcaption
+                | ElementKind::Div
+                | ElementKind::Table
+                | ElementKind::Caption
+                | ElementKind::Tbody
+                | ElementKind::Thead
+                | ElementKind::Tfoot
+                | ElementKind::Tr
+                | ElementKind::Form
+                | ElementKind::Fieldset
+                | ElementKind::Legend
+                | ElementKind::Details
+                | ElementKind::Summary
+                | ElementKind::Dialog
+        )
+    }
+
+    pub fn attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
+    /// Attribute names are case-insensitive for HTML elements.
+    fn normalize_attribute_name(&self, name: &str) -> String {
+        match self.namespace {
+            Namespace::Html => name.to_ascii_lowercase(),
+            _ => String::from(name),
+        }
+    }
+
+    /// Returns the attribute whose qualified name is `name`, which is case-insensitive for
+    /// HTML elements.
+    fn find_attribute(&self, name: &str) -> Option<&Attribute> {
+        match self.namespace {
+            Namespace::Html => self.attributes.get_ascii_lowercase(name),
+            _ => self.attributes.get(name),
+        }
+    }
+
+    /// Returns the value of the attribute whose qualified name is `name`.
+    /// https://dom.spec.whatwg.org/#dom-element-getattribute
+    pub fn get_attribute(&self, name: &str) -> Option<String> {
+        self.find_attribute(name).map(|a| a.value())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-getattributens
+    pub fn get_attribute_ns(
+        &self,
+        namespace: Option<AttributeNamespace>,
+        local_name: &str,
+    ) -> Option<String> {
+        self.attributes
+            .get_ns(namespace, local_name)
+            .map(|a| a.value())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-getattributenode
+    pub fn get_attribute_node(&self, name: &str) -> Option<&Attribute> {
+        self.find_attribute(name)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.find_attribute(name).is_some()
+    }
+
+    /// Sets the attribute without updating the id index of the document or notifying
+    /// observers. Use `api::set_attribute` for elements in a tree. Returns the old value.
+    pub(crate) fn set_attribute(&mut self, name: &str, value: &str) -> Option<String> {
+        let name = self.normalize_attribute_name(name);
+        self.attributes.set(&name, value)
+    }
+
+    pub(crate) fn set_attribute_ns(&mut self, attr: Attribute) -> Option<String> {
+        self.attributes.set_ns(attr)
+    }
+
+    /// Adds `attr` unless the element already has an attribute with the same name.
+    pub(crate) fn add_attribute(&mut self, attr: Attribute) -> bool {
+        self.attributes.add(attr)
+    }
+
+    pub(crate) fn remove_attribute(&mut self, name: &str) -> Option<Attribute> {
+        let name = self.normalize_attribute_name(name);
+        self.attributes.remove(&name)
+    }
+
+    pub(crate) fn remove_attribute_ns(
+        &mut self,
+        namespace: Option<AttributeNamespace>,
+        local_name: &str,
+    ) -> Option<Attribute> {
+        self.attributes.remove_ns(namespace, local_name)
+    }
+
+    /// Returns the value of the id attribute, or None if it's missing or empty.
+    pub fn id(&self) -> Option<String> {
+        self.get_attribute("id").filter(|id| !id.is_empty())
+    }
+
+    /// Returns the classes in the class attribute without duplicates.
+    /// https://dom.spec.whatwg.org/#dom-element-classlist
+    pub fn class_list(&self) -> Vec<String> {
+        let mut classes: Vec<String> = Vec::new();
+        if let Some(value) = self.get_attribute("class") {
+            for class in value.split_ascii_whitespace() {
+                if !classes.iter().any(|c| c == class) {
+                    classes.push(String::from(class));
+                }
+            }
         }
+        classes
     }
 
-    pub fn attributes(&self) -> Vec<Attribute> {
-        self.attributes.clone()
+    /// Returns the contents of a <template>, or None for other elements.
+    pub fn template_contents(&self) -> Option<Rc<RefCell<Node>>> {
+        self.template_contents.clone()
     }
 }
 
+/// Elements are equal if they have the same name and the same attributes in any order. The
+/// template contents are not compared.
+impl PartialEq for Element {
+    fn eq(&self, other: &Self) -> bool {
+        self.local_name == other.local_name
+            && self.namespace == other.namespace
+            && self.attributes == other.attributes
+    }
+}
+
+/// A classification of the HTML elements that the browser knows about. Elements whose local
+/// name isn't listed here are `Unknown`, which behaves like HTMLUnknownElement.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ElementKind {
     Html,
     Head,
+    Title,
+    Base,
+    Link,
+    Meta,
     Style,
     Script,
+    Noscript,
+    Template,
     Body,
-    P,
+    Article,
+    Section,
+    Nav,
+    Aside,
     H1,
     H2,
+    H3,
+    H4,
+    H5,
+    H6,
+    Hgroup,
+    Header,
+    Footer,
+    Address,
+    Main,
+    P,
+    Hr,
+    Pre,
+    Blockquote,
+    Ol,
+    Ul,
+    Menu,
+    Li,
+    Dl,
+    Dt,
+    Dd,
+    Figure,
+    Figcaption,
+    Div,
     A,
+    Em,
+    Strong,
+    Small,
+    S,
+    Cite,
+    Q,
+    Dfn,
+    Abbr,
+    Code,
+    Var,
+    Samp,
+    Kbd,
+    Sub,
+    Sup,
+    I,
+    B,
+    U,
+    Mark,
+    Span,
+    Br,
+    Wbr,
+    Img,
+    Iframe,
+    Embed,
+    Object,
+    Video,
+    Audio,
+    Canvas,
+    Table,
+    Caption,
+    Colgroup,
+    Col,
+    Tbody,
+    Thead,
+    Tfoot,
+    Tr,
+    Td,
+    Th,
+    Form,
+    Label,
+    Input,
+    Button,
+    Select,
+    Optgroup,
+    Option,
+    Textarea,
+    Fieldset,
+    Legend,
+    Details,
+    Summary,
+    Dialog,
+    Unknown,
+}
+
+impl ElementKind {
+    /// Returns the kind of the HTML element whose local name is `s`, or None if the element
+    /// isn't known to this browser.
+    pub fn from_local_name(s: &str) -> Option<Self> {
+        match s {
+            "html" => Some(ElementKind::Html),
+            "head" => Some(ElementKind::Head),
+            "title" => Some(ElementKind::Title),
+            "base" => Some(ElementKind::Base),
+            "link" => Some(ElementKind::Link),
+            "meta" => Some(ElementKind::Meta),
+            "style" => Some(ElementKind::Style),
+            "script" => Some(ElementKind::Script),
+            "noscript" => Some(ElementKind::Noscript),
+            "template" => Some(ElementKind::Template),
+            "body" => Some(ElementKind::Body),
+            "article" => Some(ElementKind::Article),
+            "section" => Some(ElementKind::Section),
+            "nav" => Some(ElementKind::Nav),
+            "aside" => Some(ElementKind::Aside),
+            "h1" => Some(ElementKind::H1),
+            "h2" => Some(ElementKind::H2),
+            "h3" => Some(ElementKind::H3),
+            "h4" => Some(ElementKind::H4),
+            "h5" => Some(ElementKind::H5),
+            "h6" => Some(ElementKind::H6),
+            "hgroup" => Some(ElementKind::Hgroup),
+            "header" => Some(ElementKind::Header),
+            "footer" => Some(ElementKind::Footer),
+            "address" => Some(ElementKind::Address),
+            "main" => Some(ElementKind::Main),
+            "p" => Some(ElementKind::P),
+            "hr" => Some(ElementKind::Hr),
+            "pre" => Some(ElementKind::Pre),
+            "blockquote" => Some(ElementKind::Blockquote),
+            "ol" => Some(ElementKind::Ol),
+            "ul" => Some(ElementKind::Ul),
+            "menu" => Some(ElementKind::Menu),
+            "li" => Some(ElementKind::Li),
+            "dl" => Some(ElementKind::Dl),
+            "dt" => Some(ElementKind::Dt),
+            "dd" => Some(ElementKind::Dd),
+            "figure" => Some(ElementKind::Figure),
+            "figcaption" => Some(ElementKind::Figcaption),
+            "div" => Some(ElementKind::Div),
+            "a" => Some(ElementKind::A),
+            "em" => Some(ElementKind::Em),
+            "strong" => Some(ElementKind::Strong),
+            "small" => Some(ElementKind::Small),
+            "s" => Some(ElementKind::S),
+            "cite" => Some(ElementKind::Cite),
+            "q" => Some(ElementKind::Q),
+            "dfn" => Some(ElementKind::Dfn),
+            "abbr" => Some(ElementKind::Abbr),
+            "code" => Some(ElementKind::Code),
+            "var" => Some(ElementKind::Var),
+            "samp" => Some(ElementKind::Samp),
+            "kbd" => Some(ElementKind::Kbd),
+            "sub" => Some(ElementKind::Sub),
+            "sup" => Some(ElementKind::Sup),
+            "i" => Some(ElementKind::I),
+            "b" => Some(ElementKind::B),
+            "u" => Some(ElementKind::U),
+            "mark" => Some(ElementKind::Mark),
+            "span" => Some(ElementKind::Span),
+            "br" => Some(ElementKind::Br),
+            "wbr" => Some(ElementKind::Wbr),
+            "img" => Some(ElementKind::Img),
+            "iframe" => Some(ElementKind::Iframe),
+            "embed" => Some(ElementKind::Embed),
+            "object" => Some(ElementKind::Object),
+            "video" => Some(ElementKind::Video),
+            "audio" => Some(ElementKind::Audio),
+            "canvas" => Some(ElementKind::Canvas),
+            "table" => Some(ElementKind::Table),
+            "caption" => Some(ElementKind::Caption),
+            "colgroup" => Some(ElementKind::Colgroup),
+            "col" => Some(ElementKind::Col),
+            "tbody" => Some(ElementKind::Tbody),
+            "thead" => Some(ElementKind::Thead),
+            "tfoot" => Some(ElementKind::Tfoot),
+            "tr" => Some(ElementKind::Tr),
+            "td" => Some(ElementKind::Td),
+            "th" => Some(ElementKind::Th),
+            "form" => Some(ElementKind::Form),
+            "label" => Some(ElementKind::Label),
+            "input" => Some(ElementKind::Input),
+            "button" => Some(ElementKind::Button),
+            "select" => Some(ElementKind::Select),
+            "optgroup" => Some(ElementKind::Optgroup),
+            "option" => Some(ElementKind::Option),
+            "textarea" => Some(ElementKind::Textarea),
+            "fieldset" => Some(ElementKind::Fieldset),
+            "legend" => Some(ElementKind::Legend),
+            "details" => Some(ElementKind::Details),
+            "summary" => Some(ElementKind::Summary),
+            "dialog" => Some(ElementKind::Dialog),
+            _ => None,
+        }
+    }
 }
 
 impl FromStr for ElementKind {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "html" => Ok(ElementKind::Html),
-            "head" => Ok(ElementKind::Head),
-            "style" => Ok(ElementKind::Style),
-            "script" => Ok(ElementKind::Script),
-            "body" => Ok(ElementKind::Body),
-            "p" => Ok(ElementKind::P),
-            "h1" => Ok(ElementKind::H1),
-            "h2" => Ok(ElementKind::H2),
-            "a" => Ok(ElementKind::A),
-            _ => Err(format!("unimplemented element name: {:?}", s)),
-        }
+        Self::from_local_name(s).ok_or_else(|| format!("unknown element name: {:?}", s))
     }
 }
 
@@ -214,13 +847,97 @@ impl Display for ElementKind {
         let s = match self {
             ElementKind::Html => "html",
             ElementKind::Head => "head",
+            ElementKind::Title => "title",
+            ElementKind::Base => "base",
+            ElementKind::Link => "link",
+            ElementKind::Meta => "meta",
             ElementKind::Style => "style",
             ElementKind::Script => "script",
+            ElementKind::Noscript => "noscript",
+            ElementKind::Template => "template",
             ElementKind::Body => "body",
+            ElementKind::Article => "article",
+            ElementKind::Section => "section",
+            ElementKind::Nav => "nav",
+            ElementKind::Aside => "aside",
             ElementKind::H1 => "h1",
             ElementKind::H2 => "h2",
+            ElementKind::H3 => "h3",
+            ElementKind::H4 => "h4",
+            ElementKind::H5 => "h5",
+            ElementKind::H6 => "h6",
+            ElementKind::Hgroup => "hgroup",
+            ElementKind::Header => "header",
+            ElementKind::Footer => "footer",
+            ElementKind::Address => "address",
+            ElementKind::Main => "main",
             ElementKind::P => "p",
+            ElementKind::Hr => "hr",
+            ElementKind::Pre => "pre",
+            ElementKind::Blockquote => "blockquote",
+            ElementKind::Ol => "ol",
+            ElementKind::Ul => "ul",
+            ElementKind::Menu => "menu",
+            ElementKind::Li => "li",
+            ElementKind::Dl => "dl",
+            ElementKind::Dt => "dt",
+            ElementKind::Dd => "dd",
+            ElementKind::Figure => "figure",
+            ElementKind::Figcaption => "figcaption",
+            ElementKind::Div => "div",
             ElementKind::A => "a",
+            ElementKind::Em => "em",
+            ElementKind::Strong => "strong",
+            ElementKind::Small => "small",
+            ElementKind::S => "s",
+            ElementKind::Cite => "cite",
+            ElementKind::Q => "q",
+            ElementKind::Dfn => "dfn",
+            ElementKind::Abbr => "abbr",
+            ElementKind::Code => "code",
+            ElementKind::Var => "var",
+            ElementKind::Samp => "samp",
+            ElementKind::Kbd => "kbd",
+            ElementKind::Sub => "sub",
+            ElementKind::Sup => "sup",
+            ElementKind::I => "i",
+            ElementKind::B => "b",
+            ElementKind::U => "u",
+            ElementKind::Mark => "mark",
+            ElementKind::Span => "span",
+            ElementKind::Br => "br",
+            ElementKind::Wbr => "wbr",
+            ElementKind::Img => "img",
+            ElementKind::Iframe => "iframe",
+            ElementKind::Embed => "embed",
+            ElementKind::Object => "object",
+            ElementKind::Video => "video",
+            ElementKind::Audio => "audio",
+            ElementKind::Canvas => "canvas",
+            ElementKind::Table => "table",
+            ElementKind::Caption => "caption",
+            ElementKind::Colgroup => "colgroup",
+            ElementKind::Col => "col",
+            ElementKind::Tbody => "tbody",
+            ElementKind::Thead => "thead",
+            ElementKind::Tfoot => "tfoot",
+            ElementKind::Tr => "tr",
+            ElementKind::Td => "td",
+            ElementKind::Th => "th",
+            ElementKind::Form => "form",
+            ElementKind::Label => "label",
+            ElementKind::Input => "input",
+            ElementKind::Button => "button",
+            ElementKind::Select => "select",
+            ElementKind::Optgroup => "optgroup",
+            ElementKind::Option => "option",
+            ElementKind::Textarea => "textarea",
+            ElementKind::Fieldset => "fieldset",
+            ElementKind::Legend => "legend",
+            ElementKind::Details => "details",
+            ElementKind::Summary => "summary",
+            ElementKind::Dialog => "dialog",
+            ElementKind::Unknown => "unknown",
         };
         write!(f, "{}", s)
     }
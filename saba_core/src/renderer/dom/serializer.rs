@@ -0,0 +1,235 @@
+//! https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+
+use crate::renderer::dom::node::Namespace;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeKind;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// Serializes the children of `node` as HTML. This is the value of innerHTML.
+pub fn serialize_children(node: &Rc<RefCell<Node>>) -> String {
+    let mut result = String::new();
+    let mut stack = Vec::new();
+    push_children(node, &mut stack);
+    serialize_into(stack, &mut result);
+    result
+}
+
+/// Serializes `node` itself and its descendants as HTML. This is the value of outerHTML.
+pub fn serialize_node(node: &Rc<RefCell<Node>>) -> String {
+    let mut result = String::new();
+    serialize_into(vec![Step::Node(node.clone())], &mut result);
+    result
+}
+
+enum Step {
+    Node(Rc<RefCell<Node>>),
+    EndTag(String),
+}
+
+/// Serializes the nodes in `stack` with their descendants. The stack is used instead of
+/// recursion so that a deeply nested tree doesn't overflow the stack.
+fn serialize_into(mut stack: Vec<Step>, result: &mut String) {
+    while let Some(step) = stack.pop() {
+        let node = match step {
+            Step::Node(node) => node,
+            Step::EndTag(name) => {
+                result.push_str("</");
+                result.push_str(&name);
+                result.push('>');
+                continue;
+            }
+        };
+
+        match node.borrow().kind {
+            NodeKind::Document | NodeKind::DocumentFragment => {}
+            NodeKind::DocumentType(ref doctype) => {
+                result.push_str("<!DOCTYPE ");
+                result.push_str(&doctype.name());
+                result.push('>');
+            }
+            NodeKind::Element(ref element) => {
+                let name = element.local_name();
+                result.push('<');
+                result.push_str(name);
+                for attr in element.attributes() {
+                    result.push(' ');
+                    result.push_str(&attr.name());
+                    result.push_str("=\"");
+                    escape_into(&attr.value(), /*attribute_mode*/ true, result);
+                    result.push('"');
+                }
+                result.push('>');
+
+                if !node.borrow().is_void_element() {
+                    stack.push(Step::EndTag(name.to_string()));
+                }
+            }
+            NodeKind::Text(ref text) => {
+                if is_raw_text_parent(&node) {
+                    result.push_str(text);
+                } else {
+                    escape_into(text, /*attribute_mode*/ false, result);
+                }
+            }
+            NodeKind::Comment(ref data) => {
+                result.push_str("<!--");
+                result.push_str(data);
+                result.push_str("-->");
+            }
+        }
+        push_children(&node, &mut stack);
+    }
+}
+
+/// Pushes the children of `node` to `stack` so that the first child is popped first.
+fn push_children(node: &Rc<RefCell<Node>>, stack: &mut Vec<Step>) {
+    if node.borrow().is_void_element() {
+        return;
+    }
+
+    // The contents of a <template> are serialized as its children.
+    let template_contents = match node.borrow().kind {
+        NodeKind::Element(ref element) => element.template_contents(),
+        _ => None,
+    };
+    let parent = template_contents.unwrap_or_else(|| node.clone());
+
+    let mut child = parent.borrow().last_child().upgrade();
+    while let Some(c) = child {
+        child = c.borrow().prev_sibling().upgrade();
+        stack.push(Step::Node(c));
+    }
+}
+
+/// Returns true if the text in `node` is serialized literally because its parent's contents
+/// aren't parsed as markup, e.g. <style> or <script>.
+fn is_raw_text_parent(node: &Rc<RefCell<Node>>) -> bool {
+    let parent = match node.borrow().parent().upgrade() {
+        Some(p) => p,
+        None => return false,
+    };
+    let parent = parent.borrow();
+    let element = match parent.kind {
+        NodeKind::Element(ref e) => e,
+        _ => return false,
+    };
+
+    // Scripting is never enabled, so the contents of <noscript> are escaped.
+    element.namespace() == Namespace::Html
+        && matches!(
+            element.local_name(),
+            "style" | "script" | "xmp" | "iframe" | "noembed" | "noframes" | "plaintext"
+        )
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+fn escape_into(s: &str, attribute_mode: bool, result: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '\u{A0}' => result.push_str("&nbsp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' if attribute_mode => result.push_str("&quot;"),
+            _ => result.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::api::get_target_element_node;
+    use crate::renderer::dom::node::ElementKind;
+    use crate::renderer::dom::test_utils::deep_tree;
+    use crate::renderer::dom::test_utils::element_by_id;
+    use crate::renderer::dom::test_utils::parse;
+
+    fn parse_document(html: &str) -> Rc<RefCell<Node>> {
+        parse(html).borrow().document()
+    }
+
+    #[test]
+    fn test_serialize_document() {
+        let html = "<!DOCTYPE html><html><head><title>t</title></head><body><p id=\"a\">x</p><!--c--></body></html>";
+        assert_eq!(html, serialize_children(&parse_document(html)));
+    }
+
+    #[test]
+    fn test_serialize_deep_tree() {
+        let depth = 100_000;
+        let html = serialize_node(&deep_tree(depth));
+        assert_eq!(depth * "<div></div>".len(), html.len());
+        assert!(html.starts_with("<div><div>"));
+    }
+
+    #[test]
+    fn test_serialize_escaping() {
+        let document = parse_document("<p title='a&amp;\"b\"'>1 &lt; 2 &amp;&nbsp;3</p>");
+        let p = get_target_element_node(Some(document), ElementKind::P).expect("failed to get p");
+        assert_eq!(
+            "<p title=\"a&amp;&quot;b&quot;\">1 &lt; 2 &amp;&nbsp;3</p>",
+            serialize_node(&p)
+        );
+        assert_eq!("1 &lt; 2 &amp;&nbsp;3", serialize_children(&p));
+    }
+
+    #[test]
+    fn test_serialize_void_elements() {
+        let document = parse_document("<body>a<br>b<img src=x><hr></body>");
+        let body =
+            get_target_element_node(Some(document), ElementKind::Body).expect("failed to get body");
+        assert_eq!("a<br>b<img src=\"x\"><hr>", serialize_children(&body));
+    }
+
+    #[test]
+    fn test_serialize_raw_text() {
+        let document = parse_document("<head><style>p > a { color: red; }</style></head>");
+        let style = get_target_element_node(Some(document), ElementKind::Style)
+            .expect("failed to get style");
+        assert_eq!("p > a { color: red; }", serialize_children(&style));
+
+        let window = parse("<script id=s>if (a < b && c) {}</script>");
+        let script = element_by_id(&window, "s");
+        assert_eq!("if (a < b && c) {}", serialize_children(&script));
+    }
+
+    #[test]
+    fn test_serialize_attribute_escaping() {
+        let window = parse("<p id=p title='&amp;&quot;&nbsp;&lt;&gt;'></p>");
+        let p = element_by_id(&window, "p");
+        assert_eq!(
+            "<p id=\"p\" title=\"&amp;&quot;&nbsp;&lt;&gt;\"></p>",
+            serialize_node(&p)
+        );
+    }
+
+    #[test]
+    fn test_serialize_template() {
+        let window = parse("<template id=t><p>a</p><template>b</template></template>");
+        let template = element_by_id(&window, "t");
+        // The contents aren't children of the template.
+        assert!(template.borrow().first_child().is_none());
+        assert_eq!(
+            "<p>a</p><template>b</template>",
+            serialize_children(&template)
+        );
+        assert_eq!(
+            "<template id=\"t\"><p>a</p><template>b</template></template>",
+            serialize_node(&template)
+        );
+    }
+
+    #[test]
+    fn test_serialize_noscript() {
+        // Scripting is disabled, so <noscript> has elements and its text is escaped.
+        let window = parse("<body><noscript id=n><p>a &amp; b</p></noscript></body>");
+        let noscript = element_by_id(&window, "n");
+        assert_eq!("<p>a &amp; b</p>", serialize_children(&noscript));
+    }
+}
@@ -0,0 +1,50 @@
+//! Helpers shared by the tests of the DOM modules.
+
+use crate::renderer::dom::api::append_child;
+use crate::renderer::dom::api::get_element_by_id;
+use crate::renderer::dom::node::Element;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeKind;
+use crate::renderer::dom::node::Window;
+use crate::renderer::html::parser::HtmlParser;
+use crate::renderer::html::token::HtmlTokenizer;
+use alloc::rc::Rc;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+pub(crate) fn parse(html: &str) -> Rc<RefCell<Window>> {
+    let t = HtmlTokenizer::new(html.to_string());
+    HtmlParser::new(t).construct_tree()
+}
+
+/// Returns the element whose id is `id` in the document of `window`.
+pub(crate) fn element_by_id(window: &Rc<RefCell<Window>>, id: &str) -> Rc<RefCell<Node>> {
+    let document = window.borrow().document();
+    get_element_by_id(&document, id).expect("failed to get element by id")
+}
+
+/// Returns a new element without attributes or a document.
+pub(crate) fn element(name: &str) -> Rc<RefCell<Node>> {
+    Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+        name,
+        Vec::new(),
+    )))))
+}
+
+/// Returns a new text node without a document.
+pub(crate) fn text(s: &str) -> Rc<RefCell<Node>> {
+    Rc::new(RefCell::new(Node::new(NodeKind::Text(s.to_string()))))
+}
+
+/// Returns the outermost element of `depth` nested <div> elements without a document. The
+/// tree is built from the innermost element, so that each insertion is cheap.
+pub(crate) fn deep_tree(depth: usize) -> Rc<RefCell<Node>> {
+    let mut root = element("div");
+    for _ in 1..depth {
+        let parent = element("div");
+        append_child(&parent, &root).expect("failed to append a child");
+        root = parent;
+    }
+    root
+}
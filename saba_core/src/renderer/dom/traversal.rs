@@ -0,0 +1,588 @@
+//! Iterators over the DOM tree. They follow the links between nodes instead of using recursion,
+//! so that they work for arbitrarily deep trees.
+//! https://dom.spec.whatwg.org/#traversal
+
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeKind;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+/// The bits of `what_to_show` for TreeWalker and NodeIterator.
+/// https://dom.spec.whatwg.org/#interface-nodefilter
+pub const SHOW_ALL: u32 = 0xFFFF_FFFF;
+pub const SHOW_ELEMENT: u32 = 0x1;
+pub const SHOW_TEXT: u32 = 0x4;
+pub const SHOW_COMMENT: u32 = 0x80;
+pub const SHOW_DOCUMENT: u32 = 0x100;
+pub const SHOW_DOCUMENT_TYPE: u32 = 0x200;
+pub const SHOW_DOCUMENT_FRAGMENT: u32 = 0x400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterResult {
+    Accept,
+    /// Skips the node with its descendants in TreeWalker. This is the same as Skip in
+    /// NodeIterator.
+    Reject,
+    /// Skips the node but not its descendants.
+    Skip,
+}
+
+/// A function that decides which nodes a TreeWalker or NodeIterator returns.
+pub type NodeFilter = Box<dyn Fn(&Rc<RefCell<Node>>) -> FilterResult>;
+
+/// Returns an iterator over the descendants of `node` in tree order, i.e. preorder.
+pub fn descendants(node: &Rc<RefCell<Node>>) -> Descendants {
+    Descendants {
+        root: node.clone(),
+        next: node.borrow().first_child(),
+    }
+}
+
+/// Returns an iterator over `node` and its descendants in tree order.
+pub fn inclusive_descendants(node: &Rc<RefCell<Node>>) -> Descendants {
+    Descendants {
+        root: node.clone(),
+        next: Some(node.clone()),
+    }
+}
+
+/// Returns an iterator over the ancestors of `node` from its parent to the root.
+pub fn ancestors(node: &Rc<RefCell<Node>>) -> Ancestors {
+    Ancestors {
+        next: node.borrow().parent().upgrade(),
+    }
+}
+
+/// Returns an iterator over the siblings after `node`.
+pub fn following_siblings(node: &Rc<RefCell<Node>>) -> FollowingSiblings {
+    FollowingSiblings {
+        next: node.borrow().next_sibling(),
+    }
+}
+
+/// Returns an iterator over the siblings before `node` from the nearest one.
+pub fn preceding_siblings(node: &Rc<RefCell<Node>>) -> PrecedingSiblings {
+    PrecedingSiblings {
+        next: node.borrow().prev_sibling().upgrade(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Descendants {
+    root: Rc<RefCell<Node>>,
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Descendants {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = following(&node, &self.root);
+        Some(node)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ancestors {
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Ancestors {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().parent().upgrade();
+        Some(node)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FollowingSiblings {
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for FollowingSiblings {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().next_sibling();
+        Some(node)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PrecedingSiblings {
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for PrecedingSiblings {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().prev_sibling().upgrade();
+        Some(node)
+    }
+}
+
+/// Returns the node after `node` in tree order within `root`.
+/// https://dom.spec.whatwg.org/#concept-tree-following
+fn following(node: &Rc<RefCell<Node>>, root: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    if let Some(child) = node.borrow().first_child() {
+        return Some(child);
+    }
+    let mut node = node.clone();
+    loop {
+        if Rc::ptr_eq(&node, root) {
+            return None;
+        }
+        if let Some(sibling) = node.borrow().next_sibling() {
+            return Some(sibling);
+        }
+        let parent = node.borrow().parent().upgrade()?;
+        node = parent;
+    }
+}
+
+/// Returns the node before `node` in tree order within `root`.
+/// https://dom.spec.whatwg.org/#concept-tree-preceding
+fn preceding(node: &Rc<RefCell<Node>>, root: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    if Rc::ptr_eq(node, root) {
+        return None;
+    }
+    let sibling = node.borrow().prev_sibling().upgrade();
+    match sibling {
+        Some(sibling) => {
+            // The last node in tree order in the subtree of the previous sibling.
+            let mut node = sibling;
+            loop {
+                let last_child = node.borrow().last_child().upgrade();
+                match last_child {
+                    Some(child) => node = child,
+                    None => return Some(node),
+                }
+            }
+        }
+        None => node.borrow().parent().upgrade(),
+    }
+}
+
+/// https://dom.spec.whatwg.org/#concept-node-filter
+fn filter_node(
+    node: &Rc<RefCell<Node>>,
+    what_to_show: u32,
+    filter: &Option<NodeFilter>,
+) -> FilterResult {
+    let bit = match node.borrow().kind {
+        NodeKind::Element(_) => SHOW_ELEMENT,
+        NodeKind::Text(_) => SHOW_TEXT,
+        NodeKind::Comment(_) => SHOW_COMMENT,
+        NodeKind::Document => SHOW_DOCUMENT,
+        NodeKind::DocumentType(_) => SHOW_DOCUMENT_TYPE,
+        NodeKind::DocumentFragment => SHOW_DOCUMENT_FRAGMENT,
+    };
+    if what_to_show & bit == 0 {
+        return FilterResult::Skip;
+    }
+    match filter {
+        Some(filter) => filter(node),
+        None => FilterResult::Accept,
+    }
+}
+
+fn first_or_last_child(node: &Rc<RefCell<Node>>, first: bool) -> Option<Rc<RefCell<Node>>> {
+    match first {
+        true => node.borrow().first_child(),
+        false => node.borrow().last_child().upgrade(),
+    }
+}
+
+fn next_or_prev_sibling(node: &Rc<RefCell<Node>>, next: bool) -> Option<Rc<RefCell<Node>>> {
+    match next {
+        true => node.borrow().next_sibling(),
+        false => node.borrow().prev_sibling().upgrade(),
+    }
+}
+
+/// Moves around the subtree of `root` to the nodes accepted by the filter, skipping the
+/// subtrees of rejected nodes.
+/// https://dom.spec.whatwg.org/#interface-treewalker
+pub struct TreeWalker {
+    root: Rc<RefCell<Node>>,
+    what_to_show: u32,
+    filter: Option<NodeFilter>,
+    current: Rc<RefCell<Node>>,
+}
+
+impl TreeWalker {
+    pub fn new(root: &Rc<RefCell<Node>>, what_to_show: u32, filter: Option<NodeFilter>) -> Self {
+        Self {
+            root: root.clone(),
+            what_to_show,
+            filter,
+            current: root.clone(),
+        }
+    }
+
+    pub fn root(&self) -> Rc<RefCell<Node>> {
+        self.root.clone()
+    }
+
+    pub fn current_node(&self) -> Rc<RefCell<Node>> {
+        self.current.clone()
+    }
+
+    pub fn set_current_node(&mut self, node: Rc<RefCell<Node>>) {
+        self.current = node;
+    }
+
+    fn filter(&self, node: &Rc<RefCell<Node>>) -> FilterResult {
+        filter_node(node, self.what_to_show, &self.filter)
+    }
+
+    fn accept(&mut self, node: Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+        self.current = node.clone();
+        Some(node)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-treewalker-parentnode
+    pub fn parent_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current.clone();
+        while !Rc::ptr_eq(&node, &self.root) {
+            let parent = node.borrow().parent().upgrade();
+            node = parent?;
+            if self.filter(&node) == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+        None
+    }
+
+    pub fn first_child(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_children(true)
+    }
+
+    pub fn last_child(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_children(false)
+    }
+
+    pub fn next_sibling(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_siblings(true)
+    }
+
+    pub fn previous_sibling(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse_siblings(false)
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-traverse-children
+    fn traverse_children(&mut self, first: bool) -> Option<Rc<RefCell<Node>>> {
+        let mut node = first_or_last_child(&self.current, first)?;
+        loop {
+            match self.filter(&node) {
+                FilterResult::Accept => return self.accept(node),
+                FilterResult::Skip => {
+                    // Look into the children of a skipped node.
+                    if let Some(child) = first_or_last_child(&node, first) {
+                        node = child;
+                        continue;
+                    }
+                }
+                FilterResult::Reject => {}
+            }
+
+            loop {
+                if let Some(sibling) = next_or_prev_sibling(&node, first) {
+                    node = sibling;
+                    break;
+                }
+                let parent = node.borrow().parent().upgrade()?;
+                if Rc::ptr_eq(&parent, &self.root) || Rc::ptr_eq(&parent, &self.current) {
+                    return None;
+                }
+                node = parent;
+            }
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-traverse-siblings
+    fn traverse_siblings(&mut self, next: bool) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current.clone();
+        if Rc::ptr_eq(&node, &self.root) {
+            return None;
+        }
+        loop {
+            let mut sibling = next_or_prev_sibling(&node, next);
+            while let Some(s) = sibling {
+                node = s;
+                let result = self.filter(&node);
+                if result == FilterResult::Accept {
+                    return self.accept(node);
+                }
+                sibling = first_or_last_child(&node, next);
+                if result == FilterResult::Reject || sibling.is_none() {
+                    sibling = next_or_prev_sibling(&node, next);
+                }
+            }
+            let parent = node.borrow().parent().upgrade();
+            node = parent?;
+            if Rc::ptr_eq(&node, &self.root) || self.filter(&node) == FilterResult::Accept {
+                return None;
+            }
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-treewalker-previousnode
+    pub fn previous_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current.clone();
+        while !Rc::ptr_eq(&node, &self.root) {
+            let mut sibling = node.borrow().prev_sibling().upgrade();
+            while let Some(s) = sibling {
+                node = s;
+                let mut result = self.filter(&node);
+                // Go to the last node in tree order in the subtree of the sibling.
+                while result != FilterResult::Reject {
+                    let last_child = node.borrow().last_child().upgrade();
+                    match last_child {
+                        Some(child) => {
+                            node = child;
+                            result = self.filter(&node);
+                        }
+                        None => break,
+                    }
+                }
+                if result == FilterResult::Accept {
+                    return self.accept(node);
+                }
+                sibling = node.borrow().prev_sibling().upgrade();
+            }
+            if Rc::ptr_eq(&node, &self.root) {
+                return None;
+            }
+            let parent = node.borrow().parent().upgrade();
+            node = parent?;
+            if self.filter(&node) == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+        None
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-treewalker-nextnode
+    pub fn next_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current.clone();
+        let mut result = FilterResult::Accept;
+        loop {
+            while result != FilterResult::Reject {
+                let first_child = node.borrow().first_child();
+                match first_child {
+                    Some(child) => {
+                        node = child;
+                        result = self.filter(&node);
+                        if result == FilterResult::Accept {
+                            return self.accept(node);
+                        }
+                    }
+                    None => break,
+                }
+            }
+
+            // Go to the next node that is not a descendant.
+            let mut temporary = node.clone();
+            loop {
+                if Rc::ptr_eq(&temporary, &self.root) {
+                    return None;
+                }
+                if let Some(sibling) = temporary.borrow().next_sibling() {
+                    node = sibling;
+                    break;
+                }
+                let parent = temporary.borrow().parent().upgrade()?;
+                temporary = parent;
+            }
+            result = self.filter(&node);
+            if result == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+    }
+}
+
+/// Iterates over the nodes in the subtree of `root` accepted by the filter in tree order.
+/// Unlike TreeWalker, rejecting a node doesn't skip its descendants. The iterator isn't
+/// adjusted when nodes are removed from the tree.
+/// https://dom.spec.whatwg.org/#interface-nodeiterator
+pub struct NodeIterator {
+    root: Rc<RefCell<Node>>,
+    what_to_show: u32,
+    filter: Option<NodeFilter>,
+    reference: Rc<RefCell<Node>>,
+    pointer_before_reference: bool,
+}
+
+impl NodeIterator {
+    pub fn new(root: &Rc<RefCell<Node>>, what_to_show: u32, filter: Option<NodeFilter>) -> Self {
+        Self {
+            root: root.clone(),
+            what_to_show,
+            filter,
+            reference: root.clone(),
+            pointer_before_reference: true,
+        }
+    }
+
+    pub fn root(&self) -> Rc<RefCell<Node>> {
+        self.root.clone()
+    }
+
+    pub fn reference_node(&self) -> Rc<RefCell<Node>> {
+        self.reference.clone()
+    }
+
+    pub fn next_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse(true)
+    }
+
+    pub fn previous_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.traverse(false)
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-nodeiterator-traverse
+    fn traverse(&mut self, next: bool) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.reference.clone();
+        let mut before_node = self.pointer_before_reference;
+        loop {
+            if next {
+                if before_node {
+                    before_node = false;
+                } else {
+                    node = following(&node, &self.root)?;
+                }
+            } else if before_node {
+                node = preceding(&node, &self.root)?;
+            } else {
+                before_node = true;
+            }
+
+            if filter_node(&node, self.what_to_show, &self.filter) == FilterResult::Accept {
+                break;
+            }
+        }
+        self.reference = node.clone();
+        self.pointer_before_reference = before_node;
+        Some(node)
+    }
+}
+
+impl Iterator for NodeIterator {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_node()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::test_utils::element_by_id;
+    use crate::renderer::dom::test_utils::parse;
+    use alloc::string::String;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    /// Returns the names of elements and the data of text nodes.
+    fn names<I: Iterator<Item = Rc<RefCell<Node>>>>(nodes: I) -> Vec<String> {
+        nodes
+            .map(|n| match n.borrow().kind {
+                NodeKind::Element(ref e) => e.local_name().to_string(),
+                NodeKind::Text(ref s) => s.clone(),
+                _ => "?".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_iterators() {
+        let window = parse("<div id=d><p>a<b>b</b></p><i>c</i><hr></div>");
+        let div = element_by_id(&window, "d");
+        assert_eq!(
+            vec!["p", "a", "b", "b", "i", "c", "hr"],
+            names(descendants(&div))
+        );
+        assert_eq!(
+            vec!["div", "p", "a", "b", "b", "i", "c", "hr"],
+            names(inclusive_descendants(&div))
+        );
+
+        let b = div
+            .borrow()
+            .first_child()
+            .and_then(|p| p.borrow().last_child().upgrade())
+            .unwrap();
+        assert_eq!(vec!["p", "div", "body", "html", "?"], names(ancestors(&b)));
+
+        let i = following_siblings(&div.borrow().first_child().unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(vec!["hr"], names(following_siblings(&i)));
+        assert_eq!(vec!["p"], names(preceding_siblings(&i)));
+    }
+
+    #[test]
+    fn test_tree_walker() {
+        let window = parse("<div id=d><p>a<b>b</b></p><i>c</i><hr></div>");
+        let div = element_by_id(&window, "d");
+
+        // Skipping <p> visits its children, and rejecting <i> skips its text.
+        let filter: NodeFilter = Box::new(|n| match n.borrow().element_kind() {
+            Some(kind) if kind.to_string() == "p" => FilterResult::Skip,
+            Some(kind) if kind.to_string() == "i" => FilterResult::Reject,
+            _ => FilterResult::Accept,
+        });
+        let mut walker = TreeWalker::new(&div, SHOW_ALL, Some(filter));
+        let mut visited = Vec::new();
+        while let Some(node) = walker.next_node() {
+            visited.push(node);
+        }
+        assert_eq!(vec!["a", "b", "b", "hr"], names(visited.into_iter()));
+
+        let mut backwards = Vec::new();
+        while let Some(node) = walker.previous_node() {
+            backwards.push(node);
+        }
+        assert_eq!(vec!["b", "b", "a", "div"], names(backwards.into_iter()));
+
+        let mut walker = TreeWalker::new(&div, SHOW_ELEMENT, None);
+        let p = walker.first_child().unwrap();
+        assert_eq!(vec!["p"], names([p].into_iter()));
+        assert_eq!(vec!["i"], names(walker.next_sibling().into_iter()));
+        assert_eq!(vec!["hr"], names(walker.next_sibling().into_iter()));
+        assert!(walker.next_sibling().is_none());
+        assert_eq!(vec!["div"], names(walker.parent_node().into_iter()));
+        assert!(walker.parent_node().is_none());
+        assert_eq!(vec!["hr"], names(walker.last_child().into_iter()));
+    }
+
+    #[test]
+    fn test_node_iterator() {
+        let window = parse("<div id=d><p>a<b>b</b></p><i>c</i></div>");
+        let div = element_by_id(&window, "d");
+
+        let mut iterator = NodeIterator::new(&div, SHOW_TEXT, None);
+        assert_eq!(vec!["a", "b"], names(iterator.by_ref().take(2)));
+        assert_eq!(vec!["b"], names(iterator.previous_node().into_iter()));
+        assert_eq!(vec!["b", "c"], names(iterator.by_ref()));
+
+        // Rejected nodes don't hide their descendants.
+        let filter: NodeFilter = Box::new(|_| FilterResult::Reject);
+        let mut iterator = NodeIterator::new(&div, SHOW_ELEMENT, Some(filter));
+        assert!(iterator.next_node().is_none());
+        let mut iterator = NodeIterator::new(&div, SHOW_ALL & !SHOW_ELEMENT, None);
+        assert_eq!(vec!["a", "b", "c"], names(iterator.by_ref()));
+    }
+}
@@ -1,9 +1,26 @@
+use alloc::format;
 use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The namespace of an attribute on a foreign element, e.g. xlink:href on <svg>. Other
+/// attributes don't have a namespace.
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-foreign-attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeNamespace {
+    /// http://www.w3.org/1999/xlink
+    XLink,
+    /// http://www.w3.org/XML/1998/namespace
+    Xml,
+    /// http://www.w3.org/2000/xmlns/
+    Xmlns,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Attribute {
     name: String,
     value: String,
+    prefix: Option<String>,
+    namespace: Option<AttributeNamespace>,
 }
 
 impl Attribute {
@@ -11,6 +28,17 @@ impl Attribute {
         Self {
             name: String::new(),
             value: String::new(),
+            prefix: None,
+            namespace: None,
+        }
+    }
+
+    pub fn with_value(name: &str, value: &str) -> Self {
+        Self {
+            name: String::from(name),
+            value: String::from(value),
+            prefix: None,
+            namespace: None,
         }
     }
 
@@ -22,12 +50,270 @@ impl Attribute {
         }
     }
 
+    pub fn set_name(&mut self, name: &str) {
+        self.name = String::from(name);
+    }
+
+    /// Puts the attribute in `namespace`. `local_name` is the name without `prefix`.
+    pub fn set_namespace(
+        &mut self,
+        prefix: Option<&str>,
+        local_name: &str,
+        namespace: AttributeNamespace,
+    ) {
+        self.prefix = prefix.map(String::from);
+        self.name = String::from(local_name);
+        self.namespace = Some(namespace);
+    }
+
+    pub fn set_value(&mut self, value: &str) {
+        self.value = String::from(value);
+    }
+
+    /// Returns the qualified name, e.g. "xlink:href" for a namespaced attribute.
     pub fn name(&self) -> String {
+        match self.prefix {
+            Some(ref prefix) => format!("{}:{}", prefix, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Returns the characters of the qualified name, so that it can be compared without
+    /// building a String.
+    fn qualified_name_chars(&self) -> impl Iterator<Item = char> + '_ {
+        let prefix = self
+            .prefix
+            .iter()
+            .flat_map(|prefix| prefix.chars().chain(core::iter::once(':')));
+        prefix.chain(self.name.chars())
+    }
+
+    fn has_qualified_name(&self, name: &str) -> bool {
+        self.qualified_name_chars().eq(name.chars())
+    }
+
+    pub fn local_name(&self) -> String {
         self.name.clone()
     }
 
+    pub fn prefix(&self) -> Option<String> {
+        self.prefix.clone()
+    }
+
+    pub fn namespace(&self) -> Option<AttributeNamespace> {
+        self.namespace
+    }
+
     pub fn value(&self) -> String {
         self.value.clone()
     }
 }
 
+/// The attributes of an element in the order they were added. An element never has two
+/// attributes with the same qualified name, or with the same namespace and local name.
+/// https://dom.spec.whatwg.org/#concept-element-attribute
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    list: Vec<Attribute>,
+}
+
+impl Attributes {
+    pub fn new() -> Self {
+        Self { list: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, Attribute> {
+        self.list.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<Attribute> {
+        self.list.clone()
+    }
+
+    /// Returns the attribute whose qualified name is `name`.
+    /// https://dom.spec.whatwg.org/#concept-element-attributes-get-by-name
+    pub fn get(&self, name: &str) -> Option<&Attribute> {
+        self.list.iter().find(|a| a.has_qualified_name(name))
+    }
+
+    /// Returns the attribute whose qualified name is `name` in ASCII lowercase, which is how
+    /// the attributes of HTML elements are looked up.
+    pub fn get_ascii_lowercase(&self, name: &str) -> Option<&Attribute> {
+        let lowercase = || name.chars().map(|c| c.to_ascii_lowercase());
+        self.list
+            .iter()
+            .find(|a| a.qualified_name_chars().eq(lowercase()))
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-element-attributes-get-by-namespace
+    pub fn get_ns(
+        &self,
+        namespace: Option<AttributeNamespace>,
+        local_name: &str,
+    ) -> Option<&Attribute> {
+        self.list
+            .iter()
+            .find(|a| a.namespace == namespace && a.name == local_name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Appends `attr` unless there is already an attribute with the same name. Returns true if
+    /// it was appended.
+    pub fn add(&mut self, attr: Attribute) -> bool {
+        let same_name = self
+            .list
+            .iter()
+            .any(|a| a.qualified_name_chars().eq(attr.qualified_name_chars()));
+        if same_name || self.get_ns(attr.namespace, &attr.name).is_some() {
+            return false;
+        }
+        self.list.push(attr);
+        true
+    }
+
+    /// Sets the value of the attribute whose qualified name is `name`, or appends a new
+    /// attribute without a namespace. Returns the old value.
+    pub fn set(&mut self, name: &str, value: &str) -> Option<String> {
+        match self.list.iter_mut().find(|a| a.has_qualified_name(name)) {
+            Some(attr) => {
+                let old = attr.value();
+                attr.set_value(value);
+                Some(old)
+            }
+            None => {
+                self.list.push(Attribute::with_value(name, value));
+                None
+            }
+        }
+    }
+
+    /// Sets the value of the attribute with the same namespace and local name as `attr`, or
+    /// appends `attr`. Returns the old value.
+    pub fn set_ns(&mut self, attr: Attribute) -> Option<String> {
+        match self
+            .list
+            .iter_mut()
+            .find(|a| a.namespace == attr.namespace && a.name == attr.name)
+        {
+            Some(old) => {
+                let value = old.value();
+                old.set_value(&attr.value);
+                Some(value)
+            }
+            None => {
+                self.list.push(attr);
+                None
+            }
+        }
+    }
+
+    /// Removes the attribute whose qualified name is `name`.
+    pub fn remove(&mut self, name: &str) -> Option<Attribute> {
+        let i = self.list.iter().position(|a| a.has_qualified_name(name))?;
+        Some(self.list.remove(i))
+    }
+
+    pub fn remove_ns(
+        &mut self,
+        namespace: Option<AttributeNamespace>,
+        local_name: &str,
+    ) -> Option<Attribute> {
+        let i = self
+            .list
+            .iter()
+            .position(|a| a.namespace == namespace && a.name == local_name)?;
+        Some(self.list.remove(i))
+    }
+}
+
+/// Drops the attributes whose name is already used by an earlier attribute.
+impl From<Vec<Attribute>> for Attributes {
+    fn from(attributes: Vec<Attribute>) -> Self {
+        let mut map = Self::new();
+        for attr in attributes {
+            map.add(attr);
+        }
+        map
+    }
+}
+
+impl<'a> IntoIterator for &'a Attributes {
+    type Item = &'a Attribute;
+    type IntoIter = core::slice::Iter<'a, Attribute>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.iter()
+    }
+}
+
+/// Attributes are equal if they have the same attributes in any order.
+impl PartialEq for Attributes {
+    fn eq(&self, other: &Self) -> bool {
+        self.list.len() == other.list.len() && self.list.iter().all(|a| other.list.contains(a))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_attributes() {
+        let mut attributes = Attributes::from(vec![
+            Attribute::with_value("id", "a"),
+            Attribute::with_value("class", "b"),
+            Attribute::with_value("id", "c"),
+        ]);
+        // The first attribute wins.
+        assert_eq!(2, attributes.len());
+        assert_eq!(Some("a".into()), attributes.get("id").map(|a| a.value()));
+
+        assert_eq!(Some("a".into()), attributes.set("id", "d"));
+        assert_eq!(None, attributes.set("title", "e"));
+        let names: Vec<String> = attributes.iter().map(|a| a.name()).collect();
+        assert_eq!(vec!["id", "class", "title"], names);
+
+        assert!(attributes.remove("class").is_some());
+        assert!(attributes.remove("class").is_none());
+        assert!(!attributes.contains("class"));
+    }
+
+    #[test]
+    fn test_namespaced_attributes() {
+        let mut href = Attribute::with_value("xlink:href", "#a");
+        href.set_namespace(Some("xlink"), "href", AttributeNamespace::XLink);
+        assert_eq!("xlink:href", href.name());
+
+        let mut attributes = Attributes::from(vec![Attribute::with_value("href", "#b"), href]);
+        assert_eq!(2, attributes.len());
+        assert_eq!(
+            Some("#a".into()),
+            attributes
+                .get_ns(Some(AttributeNamespace::XLink), "href")
+                .map(|a| a.value())
+        );
+        assert_eq!(Some("#b".into()), attributes.get("href").map(|a| a.value()));
+        assert!(attributes.get("xlink:href").is_some());
+        assert!(attributes.get("xlink:hre").is_none());
+        assert!(attributes.get("xlinkhref").is_none());
+        assert!(attributes.get_ascii_lowercase("XLink:HREF").is_some());
+        // An attribute without a prefix has the same qualified name as a prefixed one.
+        assert!(!attributes.add(Attribute::with_value("xlink:href", "#c")));
+        assert!(attributes
+            .remove_ns(Some(AttributeNamespace::XLink), "href")
+            .is_some());
+        assert!(!attributes.contains("xlink:href"));
+    }
+}
@@ -0,0 +1,319 @@
+//! https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding
+
+use crate::encoding::Encoding;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The number of bytes the prescan looks at.
+pub const PRESCAN_LENGTH: usize = 1024;
+
+/// Determines the encoding of an HTML document from its first bytes. The byte order mark
+/// wins over `transport_encoding`, e.g. the charset parameter of Content-Type, which wins over
+/// a `<meta>` declaration.
+pub fn sniff_encoding(bytes: &[u8], transport_encoding: Option<Encoding>) -> Encoding {
+    if let Some(encoding) = bom_encoding(bytes) {
+        return encoding;
+    }
+    if let Some(encoding) = transport_encoding {
+        return encoding;
+    }
+
+    let prefix = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    if let Some(encoding) = prescan(prefix) {
+        return encoding;
+    }
+
+    // Without any declaration, guess UTF-8 if the bytes are valid as UTF-8.
+    match core::str::from_utf8(prefix) {
+        Ok(_) => Encoding::Utf8,
+        // The last character may be cut off at the end of the prefix.
+        Err(e) if e.error_len().is_none() && bytes.len() >= PRESCAN_LENGTH => Encoding::Utf8,
+        Err(_) => Encoding::Windows1252,
+    }
+}
+
+/// https://encoding.spec.whatwg.org/#bom-sniff
+pub fn bom_encoding(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        Some(Encoding::Utf8)
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        Some(Encoding::Utf16Be)
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        Some(Encoding::Utf16Le)
+    } else {
+        None
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+pub fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.starts_with(b"<!--") {
+            // The "--" of "-->" may be the same as the one of "<!--".
+            match find(&bytes[pos + 2..], b"-->") {
+                Some(i) => pos += 2 + i + 2,
+                None => return None,
+            }
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest.get(5).is_some_and(|b| is_whitespace(*b) || *b == b'/')
+        {
+            pos += 5;
+            if let Some(encoding) = meta_encoding(bytes, &mut pos) {
+                return Some(encoding);
+            }
+        } else if rest.len() >= 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic()
+                || (rest[1] == b'/' && rest.get(2).is_some_and(|b| b.is_ascii_alphabetic())))
+        {
+            // Skip the tag with its attributes, which may contain '>' in quotes.
+            while pos < bytes.len() && !is_whitespace(bytes[pos]) && bytes[pos] != b'>' {
+                pos += 1;
+            }
+            while get_attribute(bytes, &mut pos).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            match rest.iter().position(|b| *b == b'>') {
+                Some(i) => pos += i,
+                None => return None,
+            }
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Reads the attributes of a `<meta>` element and returns the encoding it declares.
+fn meta_encoding(bytes: &[u8], pos: &mut usize) -> Option<Encoding> {
+    let mut attribute_names: Vec<String> = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = get_attribute(bytes, pos) {
+        if attribute_names.contains(&name) {
+            continue;
+        }
+        match name.as_str() {
+            "http-equiv" if value == "content-type" => got_pragma = true,
+            "content" if charset.is_none() => {
+                if let Some(encoding) = extract_encoding(&value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = label_encoding(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        attribute_names.push(name);
+    }
+
+    match need_pragma {
+        None => None,
+        Some(true) if !got_pragma => None,
+        // A document that could be parsed as ASCII can't be in UTF-16.
+        _ => match charset? {
+            Encoding::Utf16Be | Encoding::Utf16Le => Some(Encoding::Utf8),
+            encoding => Some(encoding),
+        },
+    }
+}
+
+/// Returns the encoding for a label in a `<meta>` element.
+fn label_encoding(label: &str) -> Option<Encoding> {
+    if label.trim().eq_ignore_ascii_case("x-user-defined") {
+        return Some(Encoding::Windows1252);
+    }
+    Encoding::for_label(label)
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+fn get_attribute(bytes: &[u8], pos: &mut usize) -> Option<(String, String)> {
+    while *pos < bytes.len() && (is_whitespace(bytes[*pos]) || bytes[*pos] == b'/') {
+        *pos += 1;
+    }
+    if *pos >= bytes.len() || bytes[*pos] == b'>' {
+        return None;
+    }
+
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    loop {
+        let b = *bytes.get(*pos)?;
+        if b == b'=' && !name.is_empty() {
+            *pos += 1;
+            break;
+        }
+        if is_whitespace(b) {
+            while bytes.get(*pos).is_some_and(|b| is_whitespace(*b)) {
+                *pos += 1;
+            }
+            if bytes.get(*pos) != Some(&b'=') {
+                return Some((to_string(name), to_string(value)));
+            }
+            *pos += 1;
+            break;
+        }
+        if b == b'/' || b == b'>' {
+            return Some((to_string(name), to_string(value)));
+        }
+        name.push(b.to_ascii_lowercase());
+        *pos += 1;
+    }
+
+    while bytes.get(*pos).is_some_and(|b| is_whitespace(*b)) {
+        *pos += 1;
+    }
+    let b = *bytes.get(*pos)?;
+    if b == b'"' || b == b'\'' {
+        loop {
+            *pos += 1;
+            let c = *bytes.get(*pos)?;
+            if c == b {
+                *pos += 1;
+                return Some((to_string(name), to_string(value)));
+            }
+            value.push(c.to_ascii_lowercase());
+        }
+    }
+    if b == b'>' {
+        return Some((to_string(name), to_string(value)));
+    }
+    loop {
+        let b = *bytes.get(*pos)?;
+        if is_whitespace(b) || b == b'>' {
+            return Some((to_string(name), to_string(value)));
+        }
+        value.push(b.to_ascii_lowercase());
+        *pos += 1;
+    }
+}
+
+/// Returns the encoding of the charset parameter in `content`, such as
+/// "text/html; charset=Shift_JIS". This is used for both the content attribute of `<meta>` and
+/// the Content-Type header.
+/// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+pub fn extract_encoding(content: &str) -> Option<Encoding> {
+    let lower = content.to_ascii_lowercase();
+    let bytes = lower.as_bytes();
+    let mut pos = 0;
+    loop {
+        pos += find(&bytes[pos..], b"charset")? + "charset".len();
+        while bytes.get(pos).is_some_and(|b| is_whitespace(*b)) {
+            pos += 1;
+        }
+        if bytes.get(pos) == Some(&b'=') {
+            break;
+        }
+    }
+
+    pos += 1;
+    while bytes.get(pos).is_some_and(|b| is_whitespace(*b)) {
+        pos += 1;
+    }
+    let rest = &lower[pos..];
+    match rest.as_bytes().first()? {
+        quote @ (b'"' | b'\'') => {
+            let end = rest[1..].find(*quote as char)?;
+            label_encoding(&rest[1..1 + end])
+        }
+        _ => {
+            let end = rest
+                .find(['\t', '\n', '\x0C', '\r', ' ', ';'])
+                .unwrap_or(rest.len());
+            label_encoding(&rest[..end])
+        }
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn find(bytes: &[u8], needle: &[u8]) -> Option<usize> {
+    bytes.windows(needle.len()).position(|w| w == needle)
+}
+
+fn to_string(bytes: Vec<u8>) -> String {
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bom() {
+        assert_eq!(
+            Encoding::Utf16Le,
+            sniff_encoding(b"\xFF\xFE<\x00", Some(Encoding::ShiftJis))
+        );
+        assert_eq!(
+            Encoding::Utf8,
+            sniff_encoding(b"\xEF\xBB\xBF<meta charset=euc-jp>", None)
+        );
+    }
+
+    #[test]
+    fn test_transport_encoding() {
+        assert_eq!(
+            Encoding::EucJp,
+            sniff_encoding(b"<meta charset=shift_jis>", Some(Encoding::EucJp))
+        );
+    }
+
+    #[test]
+    fn test_prescan() {
+        assert_eq!(
+            Some(Encoding::ShiftJis),
+            prescan(b"<!DOCTYPE html><html><head><META CHARSET=\"Shift_JIS\">")
+        );
+        assert_eq!(
+            Some(Encoding::EucJp),
+            prescan(b"<meta http-equiv=Content-Type content='text/html; charset=EUC-JP'>")
+        );
+        // The content attribute is ignored without http-equiv.
+        assert_eq!(None, prescan(b"<meta content='text/html; charset=EUC-JP'>"));
+        // Declarations in comments and attribute values are skipped.
+        assert_eq!(
+            Some(Encoding::Iso2022Jp),
+            prescan(
+                b"<!-- <meta charset=euc-jp> --><p title='<meta charset=utf-8>'>\
+                  <meta charset=iso-2022-jp>"
+            )
+        );
+        assert_eq!(Some(Encoding::Utf8), prescan(b"<meta charset=utf-16>"));
+        assert_eq!(None, prescan(b"<meta charset=unknown>"));
+    }
+
+    #[test]
+    fn test_default_encoding() {
+        assert_eq!(
+            Encoding::Utf8,
+            sniff_encoding("<p>caf\u{E9}".as_bytes(), None)
+        );
+        assert_eq!(Encoding::Windows1252, sniff_encoding(b"<p>caf\xE9", None));
+    }
+
+    #[test]
+    fn test_extract_encoding() {
+        assert_eq!(
+            Some(Encoding::ShiftJis),
+            extract_encoding("text/html; charset=Shift_JIS")
+        );
+        assert_eq!(
+            Some(Encoding::Utf8),
+            extract_encoding("text/html;charset = \"utf-8\"")
+        );
+        assert_eq!(None, extract_encoding("text/html"));
+        assert_eq!(None, extract_encoding("text/html; charset=\"utf-8"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Helpers for parsing SVG and MathML elements in HTML.
+//! https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+
+use crate::renderer::dom::node::Element;
+use crate::renderer::dom::node::Namespace;
+use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::attribute::AttributeNamespace;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Returns true if `element` is a MathML text integration point, whose contents are parsed as
+/// HTML except for <mglyph> and <malignmark>.
+/// https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point
+pub fn is_mathml_text_integration_point(element: &Element) -> bool {
+    element.namespace() == Namespace::MathMl
+        && matches!(element.local_name(), "mi" | "mo" | "mn" | "ms" | "mtext")
+}
+
+/// Returns true if `element` is an HTML integration point, whose contents are parsed as HTML.
+/// https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point
+pub fn is_html_integration_point(element: &Element) -> bool {
+    match element.namespace() {
+        Namespace::MathMl => {
+            element.local_name() == "annotation-xml"
+                && element.get_attribute("encoding").is_some_and(|encoding| {
+                    encoding.eq_ignore_ascii_case("text/html")
+                        || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+                })
+        }
+        Namespace::Svg => matches!(element.local_name(), "foreignObject" | "desc" | "title"),
+        Namespace::Html => false,
+    }
+}
+
+/// Returns true if a start tag makes the parser leave SVG or MathML content, e.g. <p> in
+/// <svg>.
+pub fn breaks_out_of_foreign_content(tag: &str, attributes: &[Attribute]) -> bool {
+    match tag {
+        "font" => attributes
+            .iter()
+            .any(|a| matches!(a.name().as_str(), "color" | "face" | "size")),
+        _ => matches!(
+            tag,
+            "b" | "big"
+                | "blockquote"
+                | "body"
+                | "br"
+                | "center"
+                | "code"
+                | "dd"
+                | "div"
+                | "dl"
+                | "dt"
+                | "em"
+                | "embed"
+                | "h1"
+                | "h2"
+                | "h3"
+                | "h4"
+                | "h5"
+                | "h6"
+                | "head"
+                | "hr"
+                | "i"
+                | "img"
+                | "li"
+                | "listing"
+                | "menu"
+                | "meta"
+                | "nobr"
+                | "ol"
+                | "p"
+                | "pre"
+                | "ruby"
+                | "s"
+                | "small"
+                | "span"
+                | "strong"
+                | "strike"
+                | "sub"
+                | "sup"
+                | "table"
+                | "tt"
+                | "u"
+                | "ul"
+                | "var"
+        ),
+    }
+}
+
+/// Returns the SVG tag name for a tag name lowercased by the tokenizer, e.g. "foreignObject"
+/// for "foreignobject".
+pub fn adjust_svg_tag_name(tag: &str) -> String {
+    let adjusted = SVG_TAG_NAMES
+        .iter()
+        .find(|(lower, _)| *lower == tag)
+        .map(|(_, name)| *name)
+        .unwrap_or(tag);
+    String::from(adjusted)
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+pub fn adjust_svg_attributes(attributes: &[Attribute]) -> Vec<Attribute> {
+    attributes
+        .iter()
+        .map(|attr| {
+            let mut attr = attr.clone();
+            if let Some((_, name)) = SVG_ATTRIBUTE_NAMES
+                .iter()
+                .find(|(lower, _)| *lower == attr.name())
+            {
+                attr.set_name(name);
+            }
+            attr
+        })
+        .collect()
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-mathml-attributes
+pub fn adjust_mathml_attributes(attributes: &[Attribute]) -> Vec<Attribute> {
+    attributes
+        .iter()
+        .map(|attr| {
+            let mut attr = attr.clone();
+            if attr.name() == "definitionurl" {
+                attr.set_name("definitionURL");
+            }
+            attr
+        })
+        .collect()
+}
+
+/// Puts attributes such as xlink:href in their namespace.
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-foreign-attributes
+pub fn adjust_foreign_attributes(attributes: &[Attribute]) -> Vec<Attribute> {
+    attributes
+        .iter()
+        .map(|attr| {
+            let mut attr = attr.clone();
+            if let Some((_, prefix, local_name, namespace)) = FOREIGN_ATTRIBUTES
+                .iter()
+                .find(|(name, _, _, _)| *name == attr.name())
+            {
+                attr.set_namespace(*prefix, local_name, *namespace);
+            }
+            attr
+        })
+        .collect()
+}
+
+/// The attribute name, its prefix, local name and namespace.
+static FOREIGN_ATTRIBUTES: [(&str, Option<&str>, &str, AttributeNamespace); 11] = [
+    (
+        "xlink:actuate",
+        Some("xlink"),
+        "actuate",
+        AttributeNamespace::XLink,
+    ),
+    (
+        "xlink:arcrole",
+        Some("xlink"),
+        "arcrole",
+        AttributeNamespace::XLink,
+    ),
+    (
+        "xlink:href",
+        Some("xlink"),
+        "href",
+        AttributeNamespace::XLink,
+    ),
+    (
+        "xlink:role",
+        Some("xlink"),
+        "role",
+        AttributeNamespace::XLink,
+    ),
+    (
+        "xlink:show",
+        Some("xlink"),
+        "show",
+        AttributeNamespace::XLink,
+    ),
+    (
+        "xlink:title",
+        Some("xlink"),
+        "title",
+        AttributeNamespace::XLink,
+    ),
+    (
+        "xlink:type",
+        Some("xlink"),
+        "type",
+        AttributeNamespace::XLink,
+    ),
+    ("xml:lang", Some("xml"), "lang", AttributeNamespace::Xml),
+    ("xml:space", Some("xml"), "space", AttributeNamespace::Xml),
+    ("xmlns", None, "xmlns", AttributeNamespace::Xmlns),
+    (
+        "xmlns:xlink",
+        Some("xmlns"),
+        "xlink",
+        AttributeNamespace::Xmlns,
+    ),
+];
+
+static SVG_TAG_NAMES: [(&str, &str); 37] = [
+    ("altglyph", "altGlyph"),
+    ("altglyphdef", "altGlyphDef"),
+    ("altglyphitem", "altGlyphItem"),
+    ("animatecolor", "animateColor"),
+    ("animatemotion", "animateMotion"),
+    ("animatetransform", "animateTransform"),
+    ("clippath", "clipPath"),
+    ("feblend", "feBlend"),
+    ("fecolormatrix", "feColorMatrix"),
+    ("fecomponenttransfer", "feComponentTransfer"),
+    ("fecomposite", "feComposite"),
+    ("feconvolvematrix", "feConvolveMatrix"),
+    ("fediffuselighting", "feDiffuseLighting"),
+    ("fedisplacementmap", "feDisplacementMap"),
+    ("fedistantlight", "feDistantLight"),
+    ("fedropshadow", "feDropShadow"),
+    ("feflood", "feFlood"),
+    ("fefunca", "feFuncA"),
+    ("fefuncb", "feFuncB"),
+    ("fefuncg", "feFuncG"),
+    ("fefuncr", "feFuncR"),
+    ("fegaussianblur", "feGaussianBlur"),
+    ("feimage", "feImage"),
+    ("femerge", "feMerge"),
+    ("femergenode", "feMergeNode"),
+    ("femorphology", "feMorphology"),
+    ("feoffset", "feOffset"),
+    ("fepointlight", "fePointLight"),
+    ("fespecularlighting", "feSpecularLighting"),
+    ("fespotlight", "feSpotLight"),
+    ("fetile", "feTile"),
+    ("feturbulence", "feTurbulence"),
+    ("foreignobject", "foreignObject"),
+    ("glyphref", "glyphRef"),
+    ("lineargradient", "linearGradient"),
+    ("radialgradient", "radialGradient"),
+    ("textpath", "textPath"),
+];
+
+static SVG_ATTRIBUTE_NAMES: [(&str, &str); 58] = [
+    ("attributename", "attributeName"),
+    ("attributetype", "attributeType"),
+    ("basefrequency", "baseFrequency"),
+    ("baseprofile", "baseProfile"),
+    ("calcmode", "calcMode"),
+    ("clippathunits", "clipPathUnits"),
+    ("diffuseconstant", "diffuseConstant"),
+    ("edgemode", "edgeMode"),
+    ("filterunits", "filterUnits"),
+    ("glyphref", "glyphRef"),
+    ("gradienttransform", "gradientTransform"),
+    ("gradientunits", "gradientUnits"),
+    ("kernelmatrix", "kernelMatrix"),
+    ("kernelunitlength", "kernelUnitLength"),
+    ("keypoints", "keyPoints"),
+    ("keysplines", "keySplines"),
+    ("keytimes", "keyTimes"),
+    ("lengthadjust", "lengthAdjust"),
+    ("limitingconeangle", "limitingConeAngle"),
+    ("markerheight", "markerHeight"),
+    ("markerunits", "markerUnits"),
+    ("markerwidth", "markerWidth"),
+    ("maskcontentunits", "maskContentUnits"),
+    ("maskunits", "maskUnits"),
+    ("numoctaves", "numOctaves"),
+    ("pathlength", "pathLength"),
+    ("patterncontentunits", "patternContentUnits"),
+    ("patterntransform", "patternTransform"),
+    ("patternunits", "patternUnits"),
+    ("pointsatx", "pointsAtX"),
+    ("pointsaty", "pointsAtY"),
+    ("pointsatz", "pointsAtZ"),
+    ("preservealpha", "preserveAlpha"),
+    ("preserveaspectratio", "preserveAspectRatio"),
+    ("primitiveunits", "primitiveUnits"),
+    ("refx", "refX"),
+    ("refy", "refY"),
+    ("repeatcount", "repeatCount"),
+    ("repeatdur", "repeatDur"),
+    ("requiredextensions", "requiredExtensions"),
+    ("requiredfeatures", "requiredFeatures"),
+    ("specularconstant", "specularConstant"),
+    ("specularexponent", "specularExponent"),
+    ("spreadmethod", "spreadMethod"),
+    ("startoffset", "startOffset"),
+    ("stddeviation", "stdDeviation"),
+    ("stitchtiles", "stitchTiles"),
+    ("surfacescale", "surfaceScale"),
+    ("systemlanguage", "systemLanguage"),
+    ("tablevalues", "tableValues"),
+    ("targetx", "targetX"),
+    ("targety", "targetY"),
+    ("textlength", "textLength"),
+    ("viewbox", "viewBox"),
+    ("viewtarget", "viewTarget"),
+    ("xchannelselector", "xChannelSelector"),
+    ("ychannelselector", "yChannelSelector"),
+    ("zoomandpan", "zoomAndPan"),
+];
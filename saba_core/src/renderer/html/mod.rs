@@ -1,3 +1,7 @@
 pub mod attribute;
+pub mod encoding_sniffer;
+pub mod entities;
+pub mod foreign_content;
+pub mod parse_error;
 pub mod parser;
 pub mod token;
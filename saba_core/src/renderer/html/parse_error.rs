@@ -0,0 +1,198 @@
+//! https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+
+/// A position in the HTML input. Lines and columns start at 1, and columns count characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new(1, 1)
+    }
+}
+
+/// The range of the input a token was created from. `end` is the position right after the
+/// last character.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    // Errors in tokenization. These are defined in the spec.
+    AbruptClosingOfEmptyComment,
+    AbruptDoctypePublicIdentifier,
+    AbruptDoctypeSystemIdentifier,
+    AbsenceOfDigitsInNumericCharacterReference,
+    CdataInHtmlContent,
+    CharacterReferenceOutsideUnicodeRange,
+    ControlCharacterReference,
+    DuplicateAttribute,
+    EndTagWithAttributes,
+    EndTagWithTrailingSolidus,
+    EofBeforeTagName,
+    EofInCdata,
+    EofInComment,
+    EofInDoctype,
+    EofInScriptHtmlCommentLikeText,
+    EofInTag,
+    IncorrectlyClosedComment,
+    IncorrectlyOpenedComment,
+    InvalidCharacterSequenceAfterDoctypeName,
+    InvalidFirstCharacterOfTagName,
+    MissingAttributeValue,
+    MissingDoctypeName,
+    MissingDoctypePublicIdentifier,
+    MissingDoctypeSystemIdentifier,
+    MissingEndTagName,
+    MissingQuoteBeforeDoctypePublicIdentifier,
+    MissingQuoteBeforeDoctypeSystemIdentifier,
+    MissingSemicolonAfterCharacterReference,
+    MissingWhitespaceAfterDoctypePublicKeyword,
+    MissingWhitespaceAfterDoctypeSystemKeyword,
+    MissingWhitespaceBeforeDoctypeName,
+    MissingWhitespaceBetweenAttributes,
+    MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+    NestedComment,
+    NoncharacterCharacterReference,
+    NullCharacterReference,
+    SurrogateCharacterReference,
+    UnexpectedCharacterAfterDoctypeSystemIdentifier,
+    UnexpectedCharacterInAttributeName,
+    UnexpectedCharacterInUnquotedAttributeValue,
+    UnexpectedEqualsSignBeforeAttributeName,
+    UnexpectedNullCharacter,
+    UnexpectedQuestionMarkInsteadOfTagName,
+    UnexpectedSolidusInTag,
+    UnknownNamedCharacterReference,
+
+    // Errors in tree construction. The spec calls all of them just "parse error", so these
+    // names are our own.
+    MissingDoctype,
+    UnexpectedDoctype,
+    UnexpectedStartTag,
+    UnexpectedEndTag,
+    UnexpectedCharacter,
+    EofWithOpenElements,
+}
+
+impl ParseErrorKind {
+    /// Returns the name of the error, e.g. "eof-in-tag".
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParseErrorKind::AbruptClosingOfEmptyComment => "abrupt-closing-of-empty-comment",
+            ParseErrorKind::AbruptDoctypePublicIdentifier => "abrupt-doctype-public-identifier",
+            ParseErrorKind::AbruptDoctypeSystemIdentifier => "abrupt-doctype-system-identifier",
+            ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference => {
+                "absence-of-digits-in-numeric-character-reference"
+            }
+            ParseErrorKind::CdataInHtmlContent => "cdata-in-html-content",
+            ParseErrorKind::CharacterReferenceOutsideUnicodeRange => {
+                "character-reference-outside-unicode-range"
+            }
+            ParseErrorKind::ControlCharacterReference => "control-character-reference",
+            ParseErrorKind::DuplicateAttribute => "duplicate-attribute",
+            ParseErrorKind::EndTagWithAttributes => "end-tag-with-attributes",
+            ParseErrorKind::EndTagWithTrailingSolidus => "end-tag-with-trailing-solidus",
+            ParseErrorKind::EofBeforeTagName => "eof-before-tag-name",
+            ParseErrorKind::EofInCdata => "eof-in-cdata",
+            ParseErrorKind::EofInComment => "eof-in-comment",
+            ParseErrorKind::EofInDoctype => "eof-in-doctype",
+            ParseErrorKind::EofInScriptHtmlCommentLikeText => {
+                "eof-in-script-html-comment-like-text"
+            }
+            ParseErrorKind::EofInTag => "eof-in-tag",
+            ParseErrorKind::IncorrectlyClosedComment => "incorrectly-closed-comment",
+            ParseErrorKind::IncorrectlyOpenedComment => "incorrectly-opened-comment",
+            ParseErrorKind::InvalidCharacterSequenceAfterDoctypeName => {
+                "invalid-character-sequence-after-doctype-name"
+            }
+            ParseErrorKind::InvalidFirstCharacterOfTagName => "invalid-first-character-of-tag-name",
+            ParseErrorKind::MissingAttributeValue => "missing-attribute-value",
+            ParseErrorKind::MissingDoctypeName => "missing-doctype-name",
+            ParseErrorKind::MissingDoctypePublicIdentifier => "missing-doctype-public-identifier",
+            ParseErrorKind::MissingDoctypeSystemIdentifier => "missing-doctype-system-identifier",
+            ParseErrorKind::MissingEndTagName => "missing-end-tag-name",
+            ParseErrorKind::MissingQuoteBeforeDoctypePublicIdentifier => {
+                "missing-quote-before-doctype-public-identifier"
+            }
+            ParseErrorKind::MissingQuoteBeforeDoctypeSystemIdentifier => {
+                "missing-quote-before-doctype-system-identifier"
+            }
+            ParseErrorKind::MissingSemicolonAfterCharacterReference => {
+                "missing-semicolon-after-character-reference"
+            }
+            ParseErrorKind::MissingWhitespaceAfterDoctypePublicKeyword => {
+                "missing-whitespace-after-doctype-public-keyword"
+            }
+            ParseErrorKind::MissingWhitespaceAfterDoctypeSystemKeyword => {
+                "missing-whitespace-after-doctype-system-keyword"
+            }
+            ParseErrorKind::MissingWhitespaceBeforeDoctypeName => {
+                "missing-whitespace-before-doctype-name"
+            }
+            ParseErrorKind::MissingWhitespaceBetweenAttributes => {
+                "missing-whitespace-between-attributes"
+            }
+            ParseErrorKind::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers => {
+                "missing-whitespace-between-doctype-public-and-system-identifiers"
+            }
+            ParseErrorKind::NestedComment => "nested-comment",
+            ParseErrorKind::NoncharacterCharacterReference => "noncharacter-character-reference",
+            ParseErrorKind::NullCharacterReference => "null-character-reference",
+            ParseErrorKind::SurrogateCharacterReference => "surrogate-character-reference",
+            ParseErrorKind::UnexpectedCharacterAfterDoctypeSystemIdentifier => {
+                "unexpected-character-after-doctype-system-identifier"
+            }
+            ParseErrorKind::UnexpectedCharacterInAttributeName => {
+                "unexpected-character-in-attribute-name"
+            }
+            ParseErrorKind::UnexpectedCharacterInUnquotedAttributeValue => {
+                "unexpected-character-in-unquoted-attribute-value"
+            }
+            ParseErrorKind::UnexpectedEqualsSignBeforeAttributeName => {
+                "unexpected-equals-sign-before-attribute-name"
+            }
+            ParseErrorKind::UnexpectedNullCharacter => "unexpected-null-character",
+            ParseErrorKind::UnexpectedQuestionMarkInsteadOfTagName => {
+                "unexpected-question-mark-instead-of-tag-name"
+            }
+            ParseErrorKind::UnexpectedSolidusInTag => "unexpected-solidus-in-tag",
+            ParseErrorKind::UnknownNamedCharacterReference => "unknown-named-character-reference",
+            ParseErrorKind::MissingDoctype => "missing-doctype",
+            ParseErrorKind::UnexpectedDoctype => "unexpected-doctype",
+            ParseErrorKind::UnexpectedStartTag => "unexpected-start-tag",
+            ParseErrorKind::UnexpectedEndTag => "unexpected-end-tag",
+            ParseErrorKind::UnexpectedCharacter => "unexpected-character",
+            ParseErrorKind::EofWithOpenElements => "eof-with-open-elements",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub position: Position,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, position: Position) -> Self {
+        Self { kind, position }
+    }
+}
@@ -1,663 +1,3839 @@
+use crate::renderer::dom::api::detach_node;
+use crate::renderer::dom::api::index_ids;
+use crate::renderer::dom::api::insert_node_before;
+use crate::renderer::dom::api::node_document;
+use crate::renderer::dom::mutation_observer::queue_attribute_record;
+use crate::renderer::dom::node::DocumentType;
 use crate::renderer::dom::node::Element;
 use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::node::Namespace;
 use crate::renderer::dom::node::Node;
 use crate::renderer::dom::node::NodeKind;
+use crate::renderer::dom::node::QuirksMode;
 use crate::renderer::dom::node::Window;
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::foreign_content::adjust_foreign_attributes;
+use crate::renderer::html::foreign_content::adjust_mathml_attributes;
+use crate::renderer::html::foreign_content::adjust_svg_attributes;
+use crate::renderer::html::foreign_content::adjust_svg_tag_name;
+use crate::renderer::html::foreign_content::breaks_out_of_foreign_content;
+use crate::renderer::html::foreign_content::is_html_integration_point;
+use crate::renderer::html::foreign_content::is_mathml_text_integration_point;
+use crate::renderer::html::parse_error::ParseError;
+use crate::renderer::html::parse_error::ParseErrorKind;
+use crate::renderer::html::parse_error::Span;
 use crate::renderer::html::token::HtmlToken;
 use crate::renderer::html::token::HtmlTokenizer;
+use crate::renderer::html::token::State;
 use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::cell::RefCell;
-use core::str::FromStr;
 
 #[derive(Debug, Clone)]
 pub struct HtmlParser {
     window: Rc<RefCell<Window>>,
     mode: InsertionMode,
-    oritinal_insertion_mode: InsertionMode,
+    original_insertion_mode: InsertionMode,
     stack_of_open_elements: Vec<Rc<RefCell<Node>>>,
+    active_formatting_elements: Vec<FormattingElement>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#stack-of-template-insertion-modes
+    template_insertion_modes: Vec<InsertionMode>,
+    head_element: Option<Rc<RefCell<Node>>>,
+    form_element: Option<Rc<RefCell<Node>>>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#frameset-ok-flag
+    frameset_ok: bool,
+    ignore_next_line_feed: bool,
+    foster_parenting: bool,
+    pending_table_character_tokens: String,
+    used_insertion_modes: Vec<InsertionMode>,
+    context_element: Option<Rc<RefCell<Node>>>,
+    /// The span of the token being processed.
+    current_span: Span,
+    errors: Vec<ParseError>,
     t: HtmlTokenizer,
 }
 
+/// An entry of the list of active formatting elements.
+/// https://html.spec.whatwg.org/multipage/parsing.html#list-of-active-formatting-elements
+#[derive(Debug, Clone)]
+enum FormattingElement {
+    Marker,
+    Element(Rc<RefCell<Node>>),
+}
+
+/// The result of the adoption agency algorithm when it can't find a formatting element to
+/// close, in which case the end tag is handled like "any other end tag".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AdoptionAgencyResult {
+    Done,
+    AnyOtherEndTag,
+}
+
 impl HtmlParser {
     pub fn new(t: HtmlTokenizer) -> Self {
         Self {
-            window: Rc::new(RefCell::new(Window::new())),
+            window: Window::new(),
             mode: InsertionMode::Initial,
-            oritinal_insertion_mode: InsertionMode::Initial,
+            original_insertion_mode: InsertionMode::Initial,
             stack_of_open_elements: Vec::new(),
+            active_formatting_elements: Vec::new(),
+            template_insertion_modes: Vec::new(),
+            head_element: None,
+            form_element: None,
+            frameset_ok: true,
+            ignore_next_line_feed: false,
+            foster_parenting: false,
+            pending_table_character_tokens: String::new(),
+            used_insertion_modes: Vec::new(),
+            context_element: None,
+            current_span: Span::default(),
+            errors: Vec::new(),
             t,
         }
     }
 
+    /// Parses the rest of the input until the end and returns the window with the document.
     pub fn construct_tree(&mut self) -> Rc<RefCell<Window>> {
-        let mut token = self.t.next();
+        self.t.close();
+        self.run();
+        self.window.clone()
+    }
 
-        while token.is_some() {
-            match self.mode {
-                InsertionMode::Initial => {
-                    if let Some(HtmlToken::Char(_)) = token {
-                        token = self.t.next();
-                        continue;
-                    }
+    /// Parses a chunk of encoded input for a parser created with a tokenizer from
+    /// `HtmlTokenizer::new_streaming()`. The encoding is determined from the byte order mark,
+    /// the transport encoding set on the tokenizer, or a `<meta>` declaration, so the bytes are
+    /// held until 1024 of them arrive unless a transport encoding is set. The document built so
+    /// far is available from `window()`, and `construct_tree()` finishes parsing after the last
+    /// chunk.
+    pub fn push_bytes(&mut self, chunk: &[u8]) {
+        self.t.push_bytes(chunk);
+        self.run();
+    }
 
-                    self.mode = InsertionMode::BeforeHtml;
-                    continue;
+    /// Parses a chunk of input. See `push_bytes()`.
+    pub fn push_str(&mut self, chunk: &str) {
+        self.t.push_str(chunk);
+        self.run();
+    }
+
+    /// Returns the window with the document parsed so far.
+    pub fn window(&self) -> Rc<RefCell<Window>> {
+        self.window.clone()
+    }
+
+    /// Parses the input as the contents of `context`, e.g. for setting innerHTML, and returns
+    /// the parsed nodes. The returned nodes have no parent.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#html-fragment-parsing-algorithm
+    pub fn parse_fragment(&mut self, context: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+        let root = Rc::new(RefCell::new(self.create_element("html", Vec::new())));
+        let document = self.window.borrow().document();
+        insert_node_before(&document, &root, None);
+        self.stack_of_open_elements.push(root.clone());
+        // The fragment is parsed in the mode of the context's document, e.g. a <table> doesn't
+        // close a <p> in quirks mode.
+        if let Some(context_document) = node_document(context) {
+            let quirks_mode = context_document.borrow().quirks_mode();
+            self.set_quirks_mode(quirks_mode);
+        }
+        self.context_element = Some(context.clone());
+        if is_html_element(context, "template") {
+            self.template_insertion_modes
+                .push(InsertionMode::InTemplate);
+        }
+        self.reset_insertion_mode_appropriately();
+
+        // The contents of e.g. <title> are text, as if they followed its start tag.
+        if let Some(element) = context.borrow().get_element() {
+            if element.namespace() == Namespace::Html {
+                let state = match element.local_name() {
+                    "title" | "textarea" => Some(State::RcData),
+                    "style" | "xmp" | "iframe" | "noembed" | "noframes" => Some(State::RawText),
+                    "script" => Some(State::ScriptData),
+                    "plaintext" => Some(State::PlainText),
+                    _ => None,
+                };
+                // No start tag has been emitted, so even </title> is text here.
+                if let Some(state) = state {
+                    self.t.switch_to(state);
                 }
-                InsertionMode::BeforeHtml => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "html" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.mode = InsertionMode::BeforeHead;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
-                    }
-                    self.insert_element("html", Vec::new());
-                    self.mode = InsertionMode::BeforeHead;
-                    continue;
+            }
+        }
+
+        // A <form> around the context element is the form owner of the parsed controls.
+        let mut node = Some(context.clone());
+        while let Some(n) = node {
+            if is_html_element(&n, "form") {
+                self.form_element = Some(n);
+                break;
+            }
+            node = n.borrow().parent().upgrade();
+        }
+
+        self.t.close();
+        self.run();
+
+        let mut nodes = Vec::new();
+        loop {
+            let child = match root.borrow().first_child() {
+                Some(child) => child,
+                None => break,
+            };
+            detach_node(&child);
+            nodes.push(child);
+        }
+        nodes
+    }
+
+    fn run(&mut self) {
+        loop {
+            let in_foreign_element = match self.adjusted_current_node() {
+                Some(node) => !is_html_element_node(&node),
+                None => false,
+            };
+            self.t.set_cdata_allowed(in_foreign_element);
+
+            let token = match self.t.next_with_span() {
+                Some(t) => {
+                    self.current_span = t.span;
+                    t.token
                 }
-                InsertionMode::BeforeHead => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "head" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.mode = InsertionMode::InHead;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
-                    }
-                    self.insert_element("head", Vec::new());
-                    self.mode = InsertionMode::InHead;
-                    continue;
+                // Wait for the next chunk.
+                None if !self.t.is_closed() => return,
+                None => {
+                    let end = self.current_span.end;
+                    self.current_span = Span::new(end, end);
+                    HtmlToken::Eof
                 }
-                InsertionMode::InHead => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "style" || tag == "script" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.oritinal_insertion_mode = self.mode;
-                                self.mode = InsertionMode::Text;
-                                token = self.t.next();
-                                continue;
-                            }
-                            if tag == "body" {
-                                self.pop_until(ElementKind::Head);
-                                self.mode = InsertionMode::AfterHead;
-                                continue;
-                            }
-                            if let Ok(_element_kind) = ElementKind::from_str(tag) {
-                                self.pop_until(ElementKind::Head);
-                                self.mode = InsertionMode::AfterHead;
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::EndTag { ref tag }) => {
-                            if tag == "head" {
-                                self.mode = InsertionMode::AfterHead;
-                                token = self.t.next();
-                                self.pop_until(ElementKind::Head);
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                    }
-                    token = self.t.next();
+            };
+            self.errors.extend(self.t.take_errors());
+
+            if self.ignore_next_line_feed {
+                self.ignore_next_line_feed = false;
+                if token == HtmlToken::Char('\n') {
                     continue;
                 }
-                InsertionMode::AfterHead => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::StartTag {
-                            ref tag,
-                            self_closing: _,
-                            ref attributes,
-                        }) => {
-                            if tag == "body" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.mode = InsertionMode::InBody;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
-                    }
-                    self.insert_element("body", Vec::new());
-                    self.mode = InsertionMode::InBody;
-                    continue;
+            }
+
+            let is_eof = token == HtmlToken::Eof;
+            self.dispatch(token);
+            if is_eof {
+                break;
+            }
+        }
+    }
+
+    /// Returns the parse errors found in the tokenizer and the tree construction, in the order
+    /// they were found.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    fn parse_error(&mut self, kind: ParseErrorKind) {
+        self.errors
+            .push(ParseError::new(kind, self.current_span.start));
+    }
+
+    /// Returns the insertion modes that processed at least one token, in the order they were
+    /// first used. This is used to group conformance test results by insertion mode.
+    pub fn used_insertion_modes(&self) -> &[InsertionMode] {
+        &self.used_insertion_modes
+    }
+
+    /// Processes a token from the tokenizer with the rules for the current insertion mode, or
+    /// with the rules for SVG and MathML contents.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher
+    fn dispatch(&mut self, token: HtmlToken) {
+        let node = match self.adjusted_current_node() {
+            Some(node) => node,
+            None => return self.process_token(token),
+        };
+        let is_html_content = match node.borrow().kind {
+            NodeKind::Element(ref element) if element.namespace() != Namespace::Html => match token
+            {
+                HtmlToken::StartTag { ref tag, .. } => {
+                    (is_mathml_text_integration_point(element)
+                        && tag != "mglyph"
+                        && tag != "malignmark")
+                        || (element.namespace() == Namespace::MathMl
+                            && element.local_name() == "annotation-xml"
+                            && tag == "svg")
+                        || is_html_integration_point(element)
                 }
-                InsertionMode::InBody => match token {
-                    Some(HtmlToken::StartTag {
-                        ref tag,
-                        self_closing: _,
-                        ref attributes,
-                    }) => match tag.as_str() {
-                        "p" => {
-                            self.insert_element(tag, attributes.to_vec());
-                            token = self.t.next();
-                            continue;
-                        }
-                        "h1" | "h2" => {
-                            self.insert_element(tag, attributes.to_vec());
-                            token = self.t.next();
-                            continue;
-                        }
-                        "a" => {
-                            self.insert_element(tag, attributes.to_vec());
-                            token = self.t.next();
-                            continue;
-                        }
-                        _ => {
-                            token = self.t.next();
-                        }
-                    },
-                    Some(HtmlToken::EndTag { ref tag }) => match tag.as_str() {
-                        "body" => {
-                            self.mode = InsertionMode::AfterBody;
-                            token = self.t.next();
-                            if !self.contain_in_stack(ElementKind::Body) {
-                                continue;
-                            }
-                            self.pop_until(ElementKind::Body);
-                            continue;
-                        }
-                        "html" => {
-                            if self.pop_current_node(ElementKind::Body) {
-                                self.mode = InsertionMode::AfterBody;
-                                assert!(self.pop_current_node(ElementKind::Html));
-                            } else {
-                                token = self.t.next();
-                            }
-                            continue;
-                        }
-                        "p" => {
-                            let element_kind = ElementKind::from_str(tag)
-                                .expect("failed to convert string to ElementKind");
-                            token = self.t.next();
-                            self.pop_until(element_kind);
-                            continue;
-                        }
-                        "h1" | "h2" => {
-                            let element_kind = ElementKind::from_str(tag)
-                                .expect("failed to convert string to ElementKind");
-                            token = self.t.next();
-                            self.pop_until(element_kind);
-                            continue;
-                        }
-                        "a" => {
-                            let element_kind = ElementKind::from_str(tag)
-                                .expect("failed to convert string to ElementKind");
-                            token = self.t.next();
-                            self.pop_until(element_kind);
-                            continue;
-                        }
-                        _ => {
-                            token = self.t.next();
-                        }
-                    },
-                    Some(HtmlToken::Char(c)) => {
-                        self.insert_char(c);
-                        token = self.t.next();
-                        continue;
-                    }
-                    Some(HtmlToken::Eof) | None => {
-                        return self.window.clone();
-                    }
-                },
-                InsertionMode::Text => {
-                    match token {
-                        Some(HtmlToken::Char(c)) => {
-                            self.insert_char(c);
-                            token = self.t.next();
-                            continue;
-                        }
-                        Some(HtmlToken::EndTag { ref tag }) => {
-                            if tag == "style" {
-                                self.pop_until(ElementKind::Style);
-                                self.mode = self.oritinal_insertion_mode;
-                                token = self.t.next();
-                                continue;
-                            }
-                            if tag == "script" {
-                                self.pop_until(ElementKind::Script);
-                                self.mode = self.oritinal_insertion_mode;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
-                    }
-                    self.mode = self.oritinal_insertion_mode;
+                HtmlToken::Char(_) => {
+                    is_mathml_text_integration_point(element) || is_html_integration_point(element)
                 }
-                InsertionMode::AfterBody => {
-                    match token {
-                        Some(HtmlToken::Char(_c)) => {
-                            token = self.t.next();
-                            continue;
-                        }
-                        Some(HtmlToken::EndTag { ref tag }) => {
-                            if tag == "html" {
-                                self.mode = InsertionMode::AfterAfterBody;
-                                token = self.t.next();
-                                continue;
-                            }
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
-                    }
-                    self.mode = InsertionMode::InBody;
+                HtmlToken::Eof => true,
+                _ => false,
+            },
+            _ => true,
+        };
+        if is_html_content {
+            self.process_token(token);
+        } else {
+            self.handle_in_foreign_content(token);
+        }
+    }
+
+    fn process_token(&mut self, token: HtmlToken) {
+        self.process_token_in(self.mode, token);
+    }
+
+    /// Processes `token` using the rules for `mode` without changing the current insertion mode.
+    fn process_token_in(&mut self, mode: InsertionMode, token: HtmlToken) {
+        if !self.used_insertion_modes.contains(&mode) {
+            self.used_insertion_modes.push(mode);
+        }
+
+        match mode {
+            InsertionMode::Initial => self.handle_initial(token),
+            InsertionMode::BeforeHtml => self.handle_before_html(token),
+            InsertionMode::BeforeHead => self.handle_before_head(token),
+            InsertionMode::InHead => self.handle_in_head(token),
+            InsertionMode::AfterHead => self.handle_after_head(token),
+            InsertionMode::InBody => self.handle_in_body(token),
+            InsertionMode::InHeadNoscript => self.handle_in_head_noscript(token),
+            InsertionMode::Text => self.handle_text(token),
+            InsertionMode::InTable => self.handle_in_table(token),
+            InsertionMode::InTableText => self.handle_in_table_text(token),
+            InsertionMode::InCaption => self.handle_in_caption(token),
+            InsertionMode::InColumnGroup => self.handle_in_column_group(token),
+            InsertionMode::InTableBody => self.handle_in_table_body(token),
+            InsertionMode::InRow => self.handle_in_row(token),
+            InsertionMode::InCell => self.handle_in_cell(token),
+            InsertionMode::InSelect => self.handle_in_select(token),
+            InsertionMode::InSelectInTable => self.handle_in_select_in_table(token),
+            InsertionMode::InTemplate => self.handle_in_template(token),
+            InsertionMode::AfterBody => self.handle_after_body(token),
+            InsertionMode::InFrameset => self.handle_in_frameset(token),
+            InsertionMode::AfterFrameset => self.handle_after_frameset(token),
+            InsertionMode::AfterAfterBody => self.handle_after_after_body(token),
+            InsertionMode::AfterAfterFrameset => self.handle_after_after_frameset(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+    fn handle_initial(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => return,
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+                return;
+            }
+            HtmlToken::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+                force_quirks,
+            } => {
+                self.insert_doctype(name, public_id, system_id, force_quirks);
+                self.mode = InsertionMode::BeforeHtml;
+                return;
+            }
+            _ => {}
+        }
+
+        // A document without a DOCTYPE is rendered in quirks mode.
+        self.parse_error(ParseErrorKind::MissingDoctype);
+        self.set_quirks_mode(QuirksMode::Quirks);
+        self.mode = InsertionMode::BeforeHtml;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-before-html-insertion-mode
+    fn handle_before_html(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Doctype { .. } => {
+                self.parse_error(ParseErrorKind::UnexpectedDoctype);
+                return;
+            }
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+                return;
+            }
+            HtmlToken::Char(c) if is_whitespace(c) => return,
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "html" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::BeforeHead;
+                return;
+            }
+            HtmlToken::EndTag { ref tag }
+                if !matches!(tag.as_str(), "head" | "body" | "html" | "br") =>
+            {
+                self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                return;
+            }
+            _ => {}
+        }
+
+        self.insert_element("html", Vec::new());
+        self.mode = InsertionMode::BeforeHead;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-before-head-insertion-mode
+    fn handle_before_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => return,
+            HtmlToken::Comment(ref data) => {
+                self.insert_comment(data, None);
+                return;
+            }
+            HtmlToken::Doctype { .. } => {
+                self.parse_error(ParseErrorKind::UnexpectedDoctype);
+                return;
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => {
+                self.handle_in_body(token);
+                return;
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } if tag == "head" => {
+                self.head_element = Some(self.insert_element(tag, attributes.to_vec()));
+                self.mode = InsertionMode::InHead;
+                return;
+            }
+            HtmlToken::EndTag { ref tag }
+                if !matches!(tag.as_str(), "head" | "body" | "html" | "br") =>
+            {
+                self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                return;
+            }
+            _ => {}
+        }
+
+        self.head_element = Some(self.insert_element("head", Vec::new()));
+        self.mode = InsertionMode::InHead;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inhead
+    fn handle_in_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {
+                self.insert_char(c);
+                return;
+            }
+            HtmlToken::Comment(ref data) => {
+                self.insert_comment(data, None);
+                return;
+            }
+            HtmlToken::Doctype { .. } => {
+                self.parse_error(ParseErrorKind::UnexpectedDoctype);
+                return;
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => {
+                    self.handle_in_body(token.clone());
+                    return;
                 }
-                InsertionMode::AfterAfterBody => {
-                    match token {
-                        Some(HtmlToken::Char(_c)) => {
-                            token = self.t.next();
-                            continue;
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        _ => {}
-                    }
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                    return;
+                }
+                "title" => {
+                    self.parse_text_element(tag, attributes, State::RcData);
+                    return;
+                }
+                "noframes" | "style" => {
+                    self.parse_text_element(tag, attributes, State::RawText);
+                    return;
+                }
+                "noscript" => {
+                    // Scripting is never enabled, so the contents of <noscript> are parsed.
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InHeadNoscript;
+                    return;
+                }
+                "script" => {
+                    self.parse_text_element(tag, attributes, State::ScriptData);
+                    return;
+                }
+                "template" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.active_formatting_elements
+                        .push(FormattingElement::Marker);
+                    self.mode = InsertionMode::InTemplate;
+                    self.template_insertion_modes
+                        .push(InsertionMode::InTemplate);
+                    return;
+                }
+                "head" => return,
+                _ => {}
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "head" => {
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    return;
+                }
+                "body" | "html" | "br" => {}
+                "template" => {
+                    self.close_template_element();
+                    return;
+                }
+                _ => {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+            },
+            _ => {}
+        }
+
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::AfterHead;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inheadnoscript
+    fn handle_in_head_noscript(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Doctype { .. } => {
+                self.parse_error(ParseErrorKind::UnexpectedDoctype);
+                return;
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => {
+                self.handle_in_body(token.clone());
+                return;
+            }
+            HtmlToken::EndTag { ref tag } if tag == "noscript" => {
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InHead;
+                return;
+            }
+            HtmlToken::Char(c) if is_whitespace(c) => {
+                self.handle_in_head(token);
+                return;
+            }
+            HtmlToken::Comment(_) => {
+                self.handle_in_head(token);
+                return;
+            }
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style"
+                ) =>
+            {
+                self.handle_in_head(token.clone());
+                return;
+            }
+            HtmlToken::StartTag { ref tag, .. } if matches!(tag.as_str(), "head" | "noscript") => {
+                return;
+            }
+            HtmlToken::EndTag { ref tag } if tag != "br" => return,
+            _ => {}
+        }
+
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InHead;
+        self.process_token(token);
+    }
 
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-head-insertion-mode
+    fn handle_after_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {
+                self.insert_char(c);
+                return;
+            }
+            HtmlToken::Comment(ref data) => {
+                self.insert_comment(data, None);
+                return;
+            }
+            HtmlToken::Doctype { .. } => {
+                self.parse_error(ParseErrorKind::UnexpectedDoctype);
+                return;
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => {
+                    self.handle_in_body(token.clone());
+                    return;
+                }
+                "body" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.frameset_ok = false;
                     self.mode = InsertionMode::InBody;
+                    return;
+                }
+                "frameset" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InFrameset;
+                    return;
+                }
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                | "style" | "template" | "title" => {
+                    // Elements for the head that appear after </head> are still put in the head.
+                    let head = match self.head_element {
+                        Some(ref head) => head.clone(),
+                        None => return,
+                    };
+                    self.stack_of_open_elements.push(head.clone());
+                    self.handle_in_head(token.clone());
+                    self.remove_from_stack(&head);
+                    return;
                 }
+                "head" => return,
+                _ => {}
+            },
+            HtmlToken::EndTag { ref tag } if tag == "template" => {
+                self.handle_in_head(token.clone());
+                return;
+            }
+            HtmlToken::EndTag { ref tag } if !matches!(tag.as_str(), "body" | "html" | "br") => {
+                self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                return;
             }
+            _ => {}
         }
 
-        self.window.clone()
+        self.insert_element("body", Vec::new());
+        self.mode = InsertionMode::InBody;
+        self.process_token(token);
     }
 
-    fn create_element(&self, tag: &str, attributes: Vec<Attribute>) -> Node {
-        Node::new(NodeKind::Element(Element::new(tag, attributes)))
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    fn handle_in_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) => {
+                if c == '\0' {
+                    return;
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_char(c);
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing,
+                ref attributes,
+            } => self.handle_in_body_start_tag(tag, self_closing, attributes),
+            HtmlToken::EndTag { ref tag } => self.handle_in_body_end_tag(tag),
+            HtmlToken::Eof => {
+                if !self.template_insertion_modes.is_empty() {
+                    self.handle_in_template(token);
+                    return;
+                }
+                let has_unclosed_element = self.stack_of_open_elements.iter().any(|node| {
+                    ![
+                        "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+                        "tbody", "td", "tfoot", "th", "thead", "tr", "body", "html",
+                    ]
+                    .iter()
+                    .any(|tag| is_html_element(node, tag))
+                });
+                if has_unclosed_element {
+                    self.parse_error(ParseErrorKind::EofWithOpenElements);
+                }
+            }
+        }
     }
 
-    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) {
-        let window = self.window.borrow();
-        let current = match self.stack_of_open_elements.last() {
-            Some(node) => node.clone(),
-            None => window.document(),
-        };
-        let node = Rc::new(RefCell::new(self.create_element(tag, attributes)));
-        if current.borrow().first_child().is_some() {
-            let mut last_sibling = current.borrow().first_child();
-            loop {
-                last_sibling = match last_sibling {
-                    Some(ref node) => {
-                        if node.borrow().next_sibling().is_some() {
-                            node.borrow().next_sibling()
-                        } else {
-                            break;
-                        }
+    fn handle_in_body_start_tag(
+        &mut self,
+        tag: &str,
+        self_closing: bool,
+        attributes: &[Attribute],
+    ) {
+        match tag {
+            "html" => {
+                if self.has_template_on_stack() {
+                    return;
+                }
+                if let Some(html) = self.stack_of_open_elements.first().cloned() {
+                    merge_attributes(&html, attributes);
+                }
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "template" | "title" => {
+                self.handle_in_head(HtmlToken::StartTag {
+                    tag: String::from(tag),
+                    self_closing: false,
+                    attributes: attributes.to_vec(),
+                });
+            }
+            "body" => {
+                if self.stack_of_open_elements.len() < 2
+                    || !is_html_element(&self.stack_of_open_elements[1], "body")
+                    || self.has_template_on_stack()
+                {
+                    return;
+                }
+                self.frameset_ok = false;
+                let body = self.stack_of_open_elements[1].clone();
+                merge_attributes(&body, attributes);
+            }
+            "frameset" => {
+                self.parse_error(ParseErrorKind::UnexpectedStartTag);
+                if self.stack_of_open_elements.len() < 2
+                    || !is_html_element(&self.stack_of_open_elements[1], "body")
+                    || !self.frameset_ok
+                {
+                    return;
+                }
+                // The body is replaced with the frameset if it has no content yet.
+                let body = self.stack_of_open_elements[1].clone();
+                detach_node(&body);
+                self.stack_of_open_elements.truncate(1);
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::InFrameset;
+            }
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog"
+            | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer" | "header"
+            | "hgroup" | "main" | "menu" | "nav" | "ol" | "p" | "search" | "section"
+            | "summary" | "ul" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_element_in_button_scope();
+                if is_heading(&self.current_node()) {
+                    self.stack_of_open_elements.pop();
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "pre" | "listing" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                // A newline right after <pre> is ignored for authoring convenience.
+                self.ignore_next_line_feed = true;
+                self.frameset_ok = false;
+            }
+            "plaintext" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                // There is no way out of the PLAINTEXT state, so the rest of the input is text.
+                self.t.switch_to(State::PlainText);
+            }
+            "form" => {
+                let has_template = self.has_template_on_stack();
+                if self.form_element.is_some() && !has_template {
+                    return;
+                }
+                self.close_p_element_in_button_scope();
+                let form = self.insert_element(tag, attributes.to_vec());
+                // Forms in a template are associated with their form owner when cloned.
+                if !has_template {
+                    self.form_element = Some(form);
+                }
+            }
+            "li" | "dd" | "dt" => {
+                self.frameset_ok = false;
+                // Close the previous list item implicitly. e.g. <li>a<li>b
+                let closing_tags: &[&str] = if tag == "li" { &["li"] } else { &["dd", "dt"] };
+                for i in (0..self.stack_of_open_elements.len()).rev() {
+                    let node = self.stack_of_open_elements[i].clone();
+                    if let Some(name) = closing_tags.iter().find(|t| is_html_element(&node, t)) {
+                        self.generate_implied_end_tags(Some(name));
+                        self.pop_until(name);
+                        break;
+                    }
+                    if is_special(&node)
+                        && !is_html_element(&node, "address")
+                        && !is_html_element(&node, "div")
+                        && !is_html_element(&node, "p")
+                    {
+                        break;
                     }
-                    None => unimplemented!("last_sibling should be Some"),
+                }
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "button" => {
+                if self.has_element_in_scope("button", Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until("button");
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+            }
+            "a" => {
+                if let Some(a) = self.find_formatting_element_after_last_marker("a") {
+                    self.run_adoption_agency_algorithm("a");
+                    self.remove_from_active_formatting_elements(&a);
+                    self.remove_from_stack(&a);
+                }
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong"
+            | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_element_in_scope("nobr", Scope::Default) {
+                    self.run_adoption_agency_algorithm("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.active_formatting_elements
+                    .push(FormattingElement::Marker);
+                self.frameset_ok = false;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" | "input" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                if tag != "input" || !is_hidden_input(attributes) {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+            }
+            "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                self.frameset_ok = false;
+            }
+            "image" => {
+                // "image" is a misspelling of "img" which is common enough to be handled.
+                self.handle_in_body_start_tag("img", self_closing, attributes);
+            }
+            "optgroup" | "option" => {
+                if is_html_element(&self.current_node(), "option") {
+                    self.stack_of_open_elements.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "rb" | "rtc" => {
+                if self.has_element_in_scope("ruby", Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "rp" | "rt" => {
+                if self.has_element_in_scope("ruby", Scope::Default) {
+                    self.generate_implied_end_tags(Some("rtc"));
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "table" => {
+                if self.quirks_mode() != QuirksMode::Quirks {
+                    self.close_p_element_in_button_scope();
+                }
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            "textarea" => {
+                self.parse_text_element(tag, attributes, State::RcData);
+                self.ignore_next_line_feed = true;
+                self.frameset_ok = false;
+            }
+            "xmp" => {
+                self.close_p_element_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.frameset_ok = false;
+                self.parse_text_element(tag, attributes, State::RawText);
+            }
+            "iframe" => {
+                self.frameset_ok = false;
+                self.parse_text_element(tag, attributes, State::RawText);
+            }
+            "noembed" => self.parse_text_element(tag, attributes, State::RawText),
+            "select" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+                self.mode = match self.mode {
+                    InsertionMode::InTable
+                    | InsertionMode::InCaption
+                    | InsertionMode::InTableBody
+                    | InsertionMode::InRow
+                    | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                    _ => InsertionMode::InSelect,
                 };
             }
+            "math" | "svg" => {
+                self.reconstruct_active_formatting_elements();
+                let (namespace, attributes) = match tag {
+                    "math" => (Namespace::MathMl, adjust_mathml_attributes(attributes)),
+                    _ => (Namespace::Svg, adjust_svg_attributes(attributes)),
+                };
+                let attributes = adjust_foreign_attributes(&attributes);
+                self.insert_foreign_element(tag, attributes, namespace);
+                if self_closing {
+                    self.stack_of_open_elements.pop();
+                }
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+            _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+            }
+        }
+    }
+
+    fn handle_in_body_end_tag(&mut self, tag: &str) {
+        match tag {
+            "template" => self.close_template_element(),
+            "body" => {
+                if !self.has_element_in_scope("body", Scope::Default) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.mode = InsertionMode::AfterBody;
+            }
+            "html" => {
+                if !self.has_element_in_scope("body", Scope::Default) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.mode = InsertionMode::AfterBody;
+                self.process_token(HtmlToken::EndTag {
+                    tag: String::from(tag),
+                });
+            }
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details"
+            | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer"
+            | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "pre"
+            | "search" | "section" | "summary" | "ul" => {
+                if !self.has_element_in_scope(tag, Scope::Default) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(tag);
+            }
+            "form" if self.has_template_on_stack() => {
+                if !self.has_element_in_scope("form", Scope::Default) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !is_html_element(&self.current_node(), "form") {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                }
+                self.pop_until("form");
+            }
+            "form" => {
+                let node = match self.form_element.take() {
+                    Some(node) => node,
+                    None => return,
+                };
+                if !self.has_node_in_scope(&node, Scope::Default) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.remove_from_stack(&node);
+            }
+            "p" => {
+                if !self.has_element_in_scope("p", Scope::Button) {
+                    // </p> without <p> creates an empty p element.
+                    self.insert_element("p", Vec::new());
+                }
+                self.close_p_element();
+            }
+            "li" => {
+                if !self.has_element_in_scope("li", Scope::ListItem) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(Some("li"));
+                self.pop_until("li");
+            }
+            "dd" | "dt" => {
+                if !self.has_element_in_scope(tag, Scope::Default) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(Some(tag));
+                self.pop_until(tag);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if !HEADINGS
+                    .iter()
+                    .any(|h| self.has_element_in_scope(h, Scope::Default))
+                {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                // Any heading closes any other heading. e.g. <h1>a</h2>
+                while let Some(node) = self.stack_of_open_elements.pop() {
+                    if is_heading(&node) {
+                        break;
+                    }
+                }
+            }
+            "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small"
+            | "strike" | "strong" | "tt" | "u" => {
+                if self.run_adoption_agency_algorithm(tag) == AdoptionAgencyResult::AnyOtherEndTag {
+                    self.close_element_for_end_tag(tag);
+                }
+            }
+            "applet" | "marquee" | "object" => {
+                if !self.has_element_in_scope(tag, Scope::Default) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(tag);
+                self.clear_active_formatting_elements_up_to_last_marker();
+            }
+            "br" => {
+                // </br> is treated as <br>.
+                self.handle_in_body_start_tag("br", false, &[]);
+            }
+            _ => self.close_element_for_end_tag(tag),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+    fn handle_in_foreign_content(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) => {
+                if c == '\0' {
+                    self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                    self.insert_char('\u{FFFD}');
+                    return;
+                }
+                self.insert_char(c);
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing,
+                ref attributes,
+            } => {
+                if breaks_out_of_foreign_content(tag, attributes) {
+                    self.parse_error(ParseErrorKind::UnexpectedStartTag);
+                    self.pop_until_html_content();
+                    self.process_token(token);
+                    return;
+                }
+
+                let namespace = match self.adjusted_current_node() {
+                    Some(node) => match node.borrow().kind {
+                        NodeKind::Element(ref e) => e.namespace(),
+                        _ => Namespace::Html,
+                    },
+                    None => Namespace::Html,
+                };
+                let (tag, attributes) = match namespace {
+                    Namespace::Svg => (adjust_svg_tag_name(tag), adjust_svg_attributes(attributes)),
+                    Namespace::MathMl => (tag.clone(), adjust_mathml_attributes(attributes)),
+                    Namespace::Html => (tag.clone(), attributes.clone()),
+                };
+                let attributes = adjust_foreign_attributes(&attributes);
+                self.insert_foreign_element(&tag, attributes, namespace);
+                // Scripts aren't run, so a self-closing <script> in SVG is simply closed too.
+                if self_closing {
+                    self.stack_of_open_elements.pop();
+                }
+            }
+            HtmlToken::EndTag { ref tag } => {
+                if tag == "br" || tag == "p" {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    self.pop_until_html_content();
+                    self.process_token(token);
+                    return;
+                }
+
+                let mut i = match self.stack_of_open_elements.len() {
+                    0 => return,
+                    len => len - 1,
+                };
+                if !has_local_name_ignoring_case(&self.stack_of_open_elements[i], tag) {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                }
+                loop {
+                    // The root element is never closed in the fragment case.
+                    if i == 0 {
+                        return;
+                    }
+                    if has_local_name_ignoring_case(&self.stack_of_open_elements[i], tag) {
+                        self.stack_of_open_elements.truncate(i);
+                        return;
+                    }
+                    i -= 1;
+                    if is_html_element_node(&self.stack_of_open_elements[i]) {
+                        self.process_token(token);
+                        return;
+                    }
+                }
+            }
+            HtmlToken::Eof => self.process_token(token),
+        }
+    }
+
+    /// Pops SVG and MathML elements until the current node is an HTML element or an
+    /// integration point.
+    fn pop_until_html_content(&mut self) {
+        while let Some(node) = self.stack_of_open_elements.last() {
+            let is_html_content = match node.borrow().kind {
+                NodeKind::Element(ref element) => {
+                    element.namespace() == Namespace::Html
+                        || is_mathml_text_integration_point(element)
+                        || is_html_integration_point(element)
+                }
+                _ => true,
+            };
+            if is_html_content {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// Inserts an element whose contents are tokenized as text in `state` until its end tag.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generic-raw-text-element-parsing-algorithm
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generic-rcdata-element-parsing-algorithm
+    fn parse_text_element(&mut self, tag: &str, attributes: &[Attribute], state: State) {
+        self.insert_element(tag, attributes.to_vec());
+        self.t.switch_to(state);
+        self.original_insertion_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incdata
+    fn handle_text(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) => self.insert_char(c),
+            HtmlToken::Eof => {
+                self.stack_of_open_elements.pop();
+                self.mode = self.original_insertion_mode;
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { .. } => {
+                self.stack_of_open_elements.pop();
+                self.mode = self.original_insertion_mode;
+            }
+            _ => {}
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intable
+    fn handle_in_table(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(_) if is_table_context(&self.current_node()) => {
+                self.pending_table_character_tokens = String::new();
+                self.original_insertion_mode = self.mode;
+                self.mode = InsertionMode::InTableText;
+                self.process_token(token);
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "caption" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.active_formatting_elements
+                        .push(FormattingElement::Marker);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element("colgroup", Vec::new());
+                    self.mode = InsertionMode::InColumnGroup;
+                    self.process_token(token.clone());
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_element("tbody", Vec::new());
+                    self.mode = InsertionMode::InTableBody;
+                    self.process_token(token.clone());
+                }
+                "table" => {
+                    // A nested <table> closes the current table.
+                    self.parse_error(ParseErrorKind::UnexpectedStartTag);
+                    if !self.has_element_in_scope("table", Scope::Table) {
+                        return;
+                    }
+                    self.pop_until("table");
+                    self.reset_insertion_mode_appropriately();
+                    self.process_token(token.clone());
+                }
+                "style" | "script" | "template" => self.handle_in_head(token.clone()),
+                "input" if is_hidden_input(attributes) => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                }
+                "form" => {
+                    if self.form_element.is_some() || self.has_template_on_stack() {
+                        return;
+                    }
+                    self.form_element = Some(self.insert_element(tag, attributes.to_vec()));
+                    self.stack_of_open_elements.pop();
+                }
+                _ => self.process_token_with_foster_parenting(token.clone()),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "table" => {
+                    if !self.has_element_in_scope("table", Scope::Table) {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                        return;
+                    }
+                    self.pop_until("table");
+                    self.reset_insertion_mode_appropriately();
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => {}
+                "template" => self.handle_in_head(token.clone()),
+                _ => self.process_token_with_foster_parenting(token.clone()),
+            },
+            HtmlToken::Eof => self.handle_in_body(token),
+            _ => self.process_token_with_foster_parenting(token),
+        }
+    }
+
+    /// Processes `token` using the rules for the "in body" insertion mode with foster parenting
+    /// enabled, so that content misplaced in a table is moved before the table.
+    fn process_token_with_foster_parenting(&mut self, token: HtmlToken) {
+        self.foster_parenting = true;
+        self.handle_in_body(token);
+        self.foster_parenting = false;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intabletext
+    fn handle_in_table_text(&mut self, token: HtmlToken) {
+        if let HtmlToken::Char(c) = token {
+            if c != '\0' {
+                self.pending_table_character_tokens.push(c);
+            }
+            return;
+        }
+
+        let pending = core::mem::take(&mut self.pending_table_character_tokens);
+        if pending.chars().all(is_whitespace) {
+            for c in pending.chars() {
+                self.insert_char(c);
+            }
+        } else {
+            self.parse_error(ParseErrorKind::UnexpectedCharacter);
+            for c in pending.chars() {
+                self.process_token_with_foster_parenting(HtmlToken::Char(c));
+            }
+        }
+
+        self.mode = self.original_insertion_mode;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incaption
+    fn handle_in_caption(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::EndTag { ref tag } if tag == "caption" => {
+                self.close_caption();
+            }
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                if self.close_caption() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag } if tag == "table" => {
+                if self.close_caption() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag }
+                if matches!(
+                    tag.as_str(),
+                    "body"
+                        | "col"
+                        | "colgroup"
+                        | "html"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) => {}
+            _ => self.handle_in_body(token),
+        }
+    }
+
+    /// Closes the caption element and returns to the "in table" insertion mode. Returns false
+    /// if there is no caption to close.
+    fn close_caption(&mut self) -> bool {
+        if !self.has_element_in_scope("caption", Scope::Table) {
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.pop_until("caption");
+        self.clear_active_formatting_elements_up_to_last_marker();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incolgroup
+    fn handle_in_column_group(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {
+                self.insert_char(c);
+                return;
+            }
+            HtmlToken::Comment(ref data) => {
+                self.insert_comment(data, None);
+                return;
+            }
+            HtmlToken::Doctype { .. } => {
+                self.parse_error(ParseErrorKind::UnexpectedDoctype);
+                return;
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => {
+                    self.handle_in_body(token.clone());
+                    return;
+                }
+                "col" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                    return;
+                }
+                "template" => {
+                    self.handle_in_head(token.clone());
+                    return;
+                }
+                _ => {}
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "colgroup" => {
+                    if is_html_element(&self.current_node(), "colgroup") {
+                        self.stack_of_open_elements.pop();
+                        self.mode = InsertionMode::InTable;
+                    }
+                    return;
+                }
+                "col" => return,
+                "template" => {
+                    self.handle_in_head(token.clone());
+                    return;
+                }
+                _ => {}
+            },
+            HtmlToken::Eof => {
+                self.handle_in_body(token);
+                return;
+            }
+            _ => {}
+        }
+
+        if !is_html_element(&self.current_node(), "colgroup") {
+            return;
+        }
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTable;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intbody
+    fn handle_in_table_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "tr" => {
+                    self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InRow;
+                }
+                "th" | "td" => {
+                    self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                    self.insert_element("tr", Vec::new());
+                    self.mode = InsertionMode::InRow;
+                    self.process_token(token.clone());
+                }
+                "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" => {
+                    if self.close_table_body() {
+                        self.process_token(token.clone());
+                    }
+                }
+                _ => self.handle_in_table(token.clone()),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "tbody" | "tfoot" | "thead" => {
+                    if !self.has_element_in_scope(tag, Scope::Table) {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                        return;
+                    }
+                    self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::InTable;
+                }
+                "table" => {
+                    if self.close_table_body() {
+                        self.process_token(token.clone());
+                    }
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr" => {}
+                _ => self.handle_in_table(token.clone()),
+            },
+            _ => self.handle_in_table(token),
+        }
+    }
+
+    /// Closes the current tbody, thead or tfoot element and returns to the "in table" insertion
+    /// mode. Returns false if there is no such element to close.
+    fn close_table_body(&mut self) -> bool {
+        if !["tbody", "thead", "tfoot"]
+            .iter()
+            .any(|tag| self.has_element_in_scope(tag, Scope::Table))
+        {
+            return false;
+        }
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intr
+    fn handle_in_row(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "th" | "td" => {
+                    self.clear_stack_back_to(&["tr", "template", "html"]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InCell;
+                    self.active_formatting_elements
+                        .push(FormattingElement::Marker);
+                }
+                "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr" => {
+                    if self.close_row() {
+                        self.process_token(token.clone());
+                    }
+                }
+                _ => self.handle_in_table(token.clone()),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "tr" => {
+                    self.close_row();
+                }
+                "table" => {
+                    if self.close_row() {
+                        self.process_token(token.clone());
+                    }
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    if !self.has_element_in_scope(tag, Scope::Table) {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                        return;
+                    }
+                    if self.close_row() {
+                        self.process_token(token.clone());
+                    }
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" => {}
+                _ => self.handle_in_table(token.clone()),
+            },
+            _ => self.handle_in_table(token),
+        }
+    }
+
+    /// Closes the current tr element and returns to the "in table body" insertion mode. Returns
+    /// false if there is no tr to close.
+    fn close_row(&mut self) -> bool {
+        if !self.has_element_in_scope("tr", Scope::Table) {
+            return false;
+        }
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intd
+    fn handle_in_cell(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "td" | "th" => {
+                    if !self.has_element_in_scope(tag, Scope::Table) {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                        return;
+                    }
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(tag);
+                    self.clear_active_formatting_elements_up_to_last_marker();
+                    self.mode = InsertionMode::InRow;
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" => {}
+                "table" | "tbody" | "tfoot" | "thead" | "tr" => {
+                    if !self.has_element_in_scope(tag, Scope::Table) {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                        return;
+                    }
+                    self.close_cell();
+                    self.process_token(token.clone());
+                }
+                _ => self.handle_in_body(token.clone()),
+            },
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                if !self.has_element_in_scope("td", Scope::Table)
+                    && !self.has_element_in_scope("th", Scope::Table)
+                {
+                    return;
+                }
+                self.close_cell();
+                self.process_token(token);
+            }
+            _ => self.handle_in_body(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        while let Some(node) = self.stack_of_open_elements.pop() {
+            if is_html_element(&node, "td") || is_html_element(&node, "th") {
+                break;
+            }
+        }
+        self.clear_active_formatting_elements_up_to_last_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselect
+    fn handle_in_select(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => self.parse_error(ParseErrorKind::UnexpectedNullCharacter),
+            HtmlToken::Char(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => self.handle_in_body(token.clone()),
+                "option" => {
+                    if is_html_element(&self.current_node(), "option") {
+                        self.stack_of_open_elements.pop();
+                    }
+                    self.insert_element(tag, attributes.to_vec());
+                }
+                "optgroup" | "hr" => {
+                    if is_html_element(&self.current_node(), "option") {
+                        self.stack_of_open_elements.pop();
+                    }
+                    if is_html_element(&self.current_node(), "optgroup") {
+                        self.stack_of_open_elements.pop();
+                    }
+                    self.insert_element(tag, attributes.to_vec());
+                    if tag == "hr" {
+                        self.stack_of_open_elements.pop();
+                    }
+                }
+                "select" => {
+                    // A nested <select> closes the open one.
+                    self.parse_error(ParseErrorKind::UnexpectedStartTag);
+                    self.close_select();
+                }
+                "input" | "keygen" | "textarea" => {
+                    self.parse_error(ParseErrorKind::UnexpectedStartTag);
+                    if self.close_select() {
+                        self.process_token(token.clone());
+                    }
+                }
+                "script" | "template" => self.handle_in_head(token.clone()),
+                _ => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "optgroup" => {
+                    // </optgroup> also closes an option in the group.
+                    let len = self.stack_of_open_elements.len();
+                    if len >= 2
+                        && is_html_element(&self.stack_of_open_elements[len - 1], "option")
+                        && is_html_element(&self.stack_of_open_elements[len - 2], "optgroup")
+                    {
+                        self.stack_of_open_elements.pop();
+                    }
+                    if is_html_element(&self.current_node(), "optgroup") {
+                        self.stack_of_open_elements.pop();
+                    } else {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    }
+                }
+                "option" => {
+                    if is_html_element(&self.current_node(), "option") {
+                        self.stack_of_open_elements.pop();
+                    } else {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    }
+                }
+                "select" => {
+                    if !self.close_select() {
+                        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    }
+                }
+                "template" => self.handle_in_head(token.clone()),
+                _ => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+            },
+            HtmlToken::Eof => self.handle_in_body(token),
+        }
+    }
+
+    /// Pops elements until the <select> is closed and resets the insertion mode. Returns false
+    /// if there is no select to close, which happens only in the fragment case.
+    fn close_select(&mut self) -> bool {
+        if !self.has_element_in_scope("select", Scope::Select) {
+            return false;
+        }
+        self.pop_until("select");
+        self.reset_insertion_mode_appropriately();
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselectintable
+    fn handle_in_select_in_table(&mut self, token: HtmlToken) {
+        let is_table_tag = |tag: &str| {
+            matches!(
+                tag,
+                "caption" | "table" | "tbody" | "tfoot" | "thead" | "tr" | "td" | "th"
+            )
+        };
+        match token {
+            // Table markup closes the <select>. e.g. <table><tr><td><select><td>
+            HtmlToken::StartTag { ref tag, .. } if is_table_tag(tag) => {
+                self.parse_error(ParseErrorKind::UnexpectedStartTag);
+                self.pop_until("select");
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { ref tag } if is_table_tag(tag) => {
+                self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                if !self.has_element_in_scope(tag, Scope::Table) {
+                    return;
+                }
+                self.pop_until("select");
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+            _ => self.handle_in_select(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intemplate
+    fn handle_in_template(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(_) | HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => {
+                self.handle_in_body(token);
+            }
+            HtmlToken::StartTag { ref tag, .. } => {
+                // The first element decides how the rest of the contents are parsed, e.g. <tr>
+                // makes them table rows.
+                let mode = match tag.as_str() {
+                    "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                    | "style" | "template" | "title" => {
+                        self.handle_in_head(token.clone());
+                        return;
+                    }
+                    "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => InsertionMode::InTable,
+                    "col" => InsertionMode::InColumnGroup,
+                    "tr" => InsertionMode::InTableBody,
+                    "td" | "th" => InsertionMode::InRow,
+                    _ => InsertionMode::InBody,
+                };
+                self.template_insertion_modes.pop();
+                self.template_insertion_modes.push(mode);
+                self.mode = mode;
+                self.process_token(token.clone());
+            }
+            HtmlToken::EndTag { ref tag } if tag == "template" => {
+                self.handle_in_head(token.clone())
+            }
+            HtmlToken::EndTag { .. } => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+            HtmlToken::Eof => {
+                // Only the template contents of the fragment case are left.
+                if !self.has_template_on_stack() {
+                    return;
+                }
+                self.parse_error(ParseErrorKind::EofWithOpenElements);
+                self.pop_until("template");
+                self.clear_active_formatting_elements_up_to_last_marker();
+                self.template_insertion_modes.pop();
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+        }
+    }
+
+    /// Handles a </template> end tag in the "in head" insertion mode.
+    fn close_template_element(&mut self) {
+        if !self.has_template_on_stack() {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+            return;
+        }
+        self.generate_implied_end_tags_thoroughly();
+        if !is_html_element(&self.current_node(), "template") {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until("template");
+        self.clear_active_formatting_elements_up_to_last_marker();
+        self.template_insertion_modes.pop();
+        self.reset_insertion_mode_appropriately();
+    }
+
+    fn has_template_on_stack(&self) -> bool {
+        self.stack_of_open_elements
+            .iter()
+            .any(|node| is_html_element(node, "template"))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterbody
+    fn handle_after_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {
+                self.handle_in_body(token);
+                return;
+            }
+            HtmlToken::Comment(ref data) => {
+                // Comments after </body> become the last child of the html element.
+                let html = self.stack_of_open_elements.first().cloned();
+                self.insert_comment(data, html);
+                return;
+            }
+            HtmlToken::Doctype { .. } => {
+                self.parse_error(ParseErrorKind::UnexpectedDoctype);
+                return;
+            }
+            HtmlToken::Eof => return,
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => {
+                self.handle_in_body(token);
+                return;
+            }
+            HtmlToken::EndTag { ref tag } if tag == "html" => {
+                if self.context_element.is_none() {
+                    self.mode = InsertionMode::AfterAfterBody;
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        self.mode = InsertionMode::InBody;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inframeset
+    fn handle_in_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+            HtmlToken::StartTag {
+                ref tag,
+                ref attributes,
+                ..
+            } => match tag.as_str() {
+                "html" => self.handle_in_body(token.clone()),
+                "frameset" => {
+                    self.insert_element(tag, attributes.to_vec());
+                }
+                "frame" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                }
+                "noframes" => self.handle_in_head(token.clone()),
+                _ => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+            },
+            HtmlToken::EndTag { ref tag } if tag == "frameset" => {
+                // Only the root html element is left in the fragment case.
+                if is_html_element(&self.current_node(), "html") {
+                    self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                    return;
+                }
+                self.stack_of_open_elements.pop();
+                if self.context_element.is_none()
+                    && !is_html_element(&self.current_node(), "frameset")
+                {
+                    self.mode = InsertionMode::AfterFrameset;
+                }
+            }
+            HtmlToken::Eof => {
+                if !is_html_element(&self.current_node(), "html") {
+                    self.parse_error(ParseErrorKind::EofWithOpenElements);
+                }
+            }
+            HtmlToken::Char(_) => self.parse_error(ParseErrorKind::UnexpectedCharacter),
+            HtmlToken::EndTag { .. } => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterframeset
+    fn handle_after_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.handle_in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "noframes" => self.handle_in_head(token),
+            HtmlToken::EndTag { ref tag } if tag == "html" => {
+                self.mode = InsertionMode::AfterAfterFrameset;
+            }
+            HtmlToken::Eof => {}
+            HtmlToken::Char(_) => self.parse_error(ParseErrorKind::UnexpectedCharacter),
+            HtmlToken::StartTag { .. } => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+            HtmlToken::EndTag { .. } => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-body-insertion-mode
+    fn handle_after_after_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+                return;
+            }
+            HtmlToken::Doctype { .. } => {
+                self.handle_in_body(token);
+                return;
+            }
+            HtmlToken::Char(c) if is_whitespace(c) => {
+                self.handle_in_body(token);
+                return;
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => {
+                self.handle_in_body(token);
+                return;
+            }
+            HtmlToken::Eof => return,
+            _ => {}
+        }
+
+        self.mode = InsertionMode::InBody;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-frameset-insertion-mode
+    fn handle_after_after_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::Doctype { .. } => self.handle_in_body(token),
+            HtmlToken::Char(c) if is_whitespace(c) => self.handle_in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.handle_in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "noframes" => self.handle_in_head(token),
+            HtmlToken::Eof => {}
+            HtmlToken::Char(_) => self.parse_error(ParseErrorKind::UnexpectedCharacter),
+            HtmlToken::StartTag { .. } => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+            HtmlToken::EndTag { .. } => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+        }
+    }
+
+    fn create_element(&self, tag: &str, attributes: Vec<Attribute>) -> Node {
+        Node::new(NodeKind::Element(Element::new(tag, attributes)))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-an-html-element
+    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) -> Rc<RefCell<Node>> {
+        self.insert_foreign_element(tag, attributes, Namespace::Html)
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element
+    fn insert_foreign_element(
+        &mut self,
+        tag: &str,
+        attributes: Vec<Attribute>,
+        namespace: Namespace,
+    ) -> Rc<RefCell<Node>> {
+        let node = Rc::new(RefCell::new(Node::new(NodeKind::Element(
+            Element::new_with_namespace(tag, namespace, attributes),
+        ))));
+        self.insert_node(node.clone(), None);
+        self.stack_of_open_elements.push(node.clone());
+        node
+    }
+
+    /// Inserts `node` at the appropriate place for inserting a node.
+    fn insert_node(&mut self, node: Rc<RefCell<Node>>, override_target: Option<Rc<RefCell<Node>>>) {
+        let (parent, before) = self.appropriate_place_for_inserting_node(override_target);
+        insert_node_before(&parent, &node, before.as_ref());
+    }
+
+    /// Returns the parent node and the child before which a new node is inserted. The place is
+    /// computed from `override_target`, or from the current node if it's None.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+    fn appropriate_place_for_inserting_node(
+        &self,
+        override_target: Option<Rc<RefCell<Node>>>,
+    ) -> (Rc<RefCell<Node>>, Option<Rc<RefCell<Node>>>) {
+        let (parent, before) = self.insertion_location(override_target);
+        // Nodes inserted into a <template> go to its template contents.
+        let contents = match parent.borrow().kind {
+            NodeKind::Element(ref e) if e.namespace() == Namespace::Html => e.template_contents(),
+            _ => None,
+        };
+        match contents {
+            Some(contents) => (contents, None),
+            None => (parent, before),
+        }
+    }
+
+    /// Returns the place for inserting a node before it's adjusted for templates.
+    fn insertion_location(
+        &self,
+        override_target: Option<Rc<RefCell<Node>>>,
+    ) -> (Rc<RefCell<Node>>, Option<Rc<RefCell<Node>>>) {
+        let target = match override_target {
+            Some(target) => target,
+            None => self.current_node(),
+        };
+
+        if !self.foster_parenting
+            || !["table", "tbody", "tfoot", "thead", "tr"]
+                .iter()
+                .any(|tag| is_html_element(&target, tag))
+        {
+            return (target, None);
+        }
+
+        // Foster parenting: content misplaced in a table is inserted right before the table.
+        let last_template_index = self
+            .stack_of_open_elements
+            .iter()
+            .rposition(|n| is_html_element(n, "template"));
+        let last_table_index = self
+            .stack_of_open_elements
+            .iter()
+            .rposition(|n| is_html_element(n, "table"));
+        let last_table_index = match (last_template_index, last_table_index) {
+            // A table in a template is inside the template contents.
+            (Some(template), table) if table.map_or(true, |table| template > table) => {
+                return (self.stack_of_open_elements[template].clone(), None);
+            }
+            (_, Some(i)) => i,
+            (_, None) => return (self.stack_of_open_elements[0].clone(), None),
+        };
+        let last_table = self.stack_of_open_elements[last_table_index].clone();
+        let parent = last_table.borrow().parent().upgrade();
+        match parent {
+            Some(parent) => (parent, Some(last_table)),
+            None => (
+                self.stack_of_open_elements[last_table_index - 1].clone(),
+                None,
+            ),
+        }
+    }
+
+    /// Returns the current node, or the context element when only the root element is open in
+    /// the fragment case.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adjusted-current-node
+    fn adjusted_current_node(&self) -> Option<Rc<RefCell<Node>>> {
+        match self.context_element {
+            Some(ref context) if self.stack_of_open_elements.len() == 1 => Some(context.clone()),
+            _ => self.stack_of_open_elements.last().cloned(),
+        }
+    }
+
+    /// Returns the bottommost node of the stack of open elements, or the document if the stack
+    /// is empty.
+    fn current_node(&self) -> Rc<RefCell<Node>> {
+        match self.stack_of_open_elements.last() {
+            Some(node) => node.clone(),
+            None => self.window.borrow().document(),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-comment
+    fn insert_comment(&mut self, data: &str, parent: Option<Rc<RefCell<Node>>>) {
+        let node = Rc::new(RefCell::new(Node::new(NodeKind::Comment(String::from(
+            data,
+        )))));
+        match parent {
+            Some(p) => insert_node_before(&p, &node, None),
+            None => self.insert_node(node, None),
+        }
+    }
+
+    fn insert_doctype(
+        &mut self,
+        name: &Option<String>,
+        public_id: &Option<String>,
+        system_id: &Option<String>,
+        force_quirks: bool,
+    ) {
+        let doctype = DocumentType::new(
+            name.clone().unwrap_or_default(),
+            public_id.clone().unwrap_or_default(),
+            system_id.clone().unwrap_or_default(),
+        );
+        let document = self.window.borrow().document();
+        let node = Rc::new(RefCell::new(Node::new(NodeKind::DocumentType(doctype))));
+        insert_node_before(&document, &node, None);
+
+        let quirks_mode = quirks_mode_from_doctype(name, public_id, system_id, force_quirks);
+        self.set_quirks_mode(quirks_mode);
+    }
+
+    fn quirks_mode(&self) -> QuirksMode {
+        self.window.borrow().document().borrow().quirks_mode()
+    }
+
+    fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.window
+            .borrow()
+            .document()
+            .borrow_mut()
+            .set_quirks_mode(quirks_mode);
+    }
+
+    fn pop_until(&mut self, tag: &str) {
+        while let Some(node) = self.stack_of_open_elements.pop() {
+            if is_html_element(&node, tag) {
+                return;
+            }
+        }
+    }
+
+    fn remove_from_stack(&mut self, node: &Rc<RefCell<Node>>) {
+        self.stack_of_open_elements.retain(|n| !Rc::ptr_eq(n, node));
+    }
+
+    fn position_in_stack(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+        // Elements looked up are usually near the bottom of the stack.
+        self.stack_of_open_elements
+            .iter()
+            .rposition(|n| Rc::ptr_eq(n, node))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+    fn has_element_in_scope(&self, tag: &str, scope: Scope) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            if let NodeKind::Element(ref element) = node.borrow().kind {
+                if element.is_html(tag) {
+                    return true;
+                }
+                if scope.is_boundary(element) {
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    fn has_node_in_scope(&self, target: &Rc<RefCell<Node>>, scope: Scope) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            if Rc::ptr_eq(node, target) {
+                return true;
+            }
+            if let NodeKind::Element(ref element) = node.borrow().kind {
+                if scope.is_boundary(element) {
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+    fn generate_implied_end_tags(&mut self, exception: Option<&str>) {
+        while let Some(node) = self.stack_of_open_elements.last() {
+            let is_implied = match node.borrow().kind {
+                NodeKind::Element(ref e) if e.namespace() == Namespace::Html => {
+                    let tag = e.local_name();
+                    Some(tag) != exception
+                        && matches!(
+                            tag,
+                            "dd" | "dt"
+                                | "li"
+                                | "optgroup"
+                                | "option"
+                                | "p"
+                                | "rb"
+                                | "rp"
+                                | "rt"
+                                | "rtc"
+                        )
+                }
+                _ => false,
+            };
+            if !is_implied {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// Pops all elements that are closed implicitly, including table parts.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-all-implied-end-tags-thoroughly
+    fn generate_implied_end_tags_thoroughly(&mut self) {
+        while let Some(node) = self.stack_of_open_elements.last() {
+            let is_implied = match node.borrow().kind {
+                NodeKind::Element(ref e) if e.namespace() == Namespace::Html => matches!(
+                    e.local_name(),
+                    "caption"
+                        | "colgroup"
+                        | "dd"
+                        | "dt"
+                        | "li"
+                        | "optgroup"
+                        | "option"
+                        | "p"
+                        | "rb"
+                        | "rp"
+                        | "rt"
+                        | "rtc"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ),
+                _ => false,
+            };
+            if !is_implied {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        self.pop_until("p");
+    }
+
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_element_in_scope("p", Scope::Button) {
+            self.close_p_element();
+        }
+    }
+
+    /// Handles "any other end tag" in the "in body" insertion mode. The token is ignored if a
+    /// special element is found before the element to close.
+    fn close_element_for_end_tag(&mut self, tag: &str) {
+        for i in (0..self.stack_of_open_elements.len()).rev() {
+            let node = self.stack_of_open_elements[i].clone();
+            if is_html_element(&node, tag) {
+                self.generate_implied_end_tags(Some(tag));
+                self.stack_of_open_elements.truncate(i);
+                return;
+            }
+            if is_special(&node) {
+                self.parse_error(ParseErrorKind::UnexpectedEndTag);
+                return;
+            }
+        }
+    }
+
+    /// Pops elements until the current node is an HTML element named one of `tags`.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-context
+    fn clear_stack_back_to(&mut self, tags: &[&str]) {
+        while let Some(node) = self.stack_of_open_elements.last() {
+            if tags.iter().any(|tag| is_html_element(node, tag)) {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
+    fn reset_insertion_mode_appropriately(&mut self) {
+        for (i, node) in self.stack_of_open_elements.iter().enumerate().rev() {
+            let last = i == 0;
+            // In the fragment case, the root html element is handled as the context element.
+            let node = match self.context_element {
+                Some(ref context) if last => context,
+                _ => node,
+            };
+            let node = node.borrow();
+            let element = match node.kind {
+                NodeKind::Element(ref e) if e.namespace() == Namespace::Html => e,
+                _ => continue,
+            };
+
+            self.mode = match element.local_name() {
+                "select" => self.select_insertion_mode(i, last),
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                "template" => match self.template_insertion_modes.last() {
+                    Some(mode) => *mode,
+                    None => InsertionMode::InTemplate,
+                },
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "frameset" => InsertionMode::InFrameset,
+                "html" => {
+                    if self.head_element.is_none() {
+                        InsertionMode::BeforeHead
+                    } else {
+                        InsertionMode::AfterHead
+                    }
+                }
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+            return;
+        }
+
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// Returns the insertion mode for the <select> at `index` in the stack of open elements,
+    /// which depends on whether it's in a table.
+    fn select_insertion_mode(&self, index: usize, last: bool) -> InsertionMode {
+        if !last {
+            for ancestor in self.stack_of_open_elements[..index].iter().rev() {
+                if is_html_element(ancestor, "template") {
+                    break;
+                }
+                if is_html_element(ancestor, "table") {
+                    return InsertionMode::InSelectInTable;
+                }
+            }
+        }
+        InsertionMode::InSelect
+    }
+
+    fn position_in_active_formatting_elements(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|entry| match entry {
+                FormattingElement::Element(n) => Rc::ptr_eq(n, node),
+                FormattingElement::Marker => false,
+            })
+    }
+
+    fn remove_from_active_formatting_elements(&mut self, node: &Rc<RefCell<Node>>) {
+        if let Some(i) = self.position_in_active_formatting_elements(node) {
+            self.active_formatting_elements.remove(i);
+        }
+    }
+
+    /// Returns the last element named `tag` in the list of active formatting elements after
+    /// the last marker.
+    fn find_formatting_element_after_last_marker(&self, tag: &str) -> Option<Rc<RefCell<Node>>> {
+        for entry in self.active_formatting_elements.iter().rev() {
+            match entry {
+                FormattingElement::Marker => return None,
+                FormattingElement::Element(node) => {
+                    if is_html_element(node, tag) {
+                        return Some(node.clone());
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#push-onto-the-list-of-active-formatting-elements
+    fn push_active_formatting_element(&mut self, node: Rc<RefCell<Node>>) {
+        // Noah's Ark clause: keep at most three identical elements after the last marker.
+        let mut identical = Vec::new();
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match entry {
+                FormattingElement::Marker => break,
+                FormattingElement::Element(n) => {
+                    if is_same_formatting_element(n, &node) {
+                        identical.push(i);
+                    }
+                }
+            }
+        }
+        if identical.len() >= 3 {
+            if let Some(earliest) = identical.last() {
+                self.active_formatting_elements.remove(*earliest);
+            }
+        }
+
+        self.active_formatting_elements
+            .push(FormattingElement::Element(node));
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let is_open = |parser: &Self, entry: &FormattingElement| match entry {
+            FormattingElement::Marker => true,
+            FormattingElement::Element(node) => parser.position_in_stack(node).is_some(),
+        };
+
+        let mut i = match self.active_formatting_elements.last() {
+            Some(entry) => {
+                if is_open(self, entry) {
+                    return;
+                }
+                self.active_formatting_elements.len() - 1
+            }
+            None => return,
+        };
+
+        // Rewind to the entry right after the last marker or open element.
+        while i > 0 && !is_open(self, &self.active_formatting_elements[i - 1]) {
+            i -= 1;
+        }
+
+        for j in i..self.active_formatting_elements.len() {
+            let element = match &self.active_formatting_elements[j] {
+                FormattingElement::Element(node) => node.borrow().get_element(),
+                FormattingElement::Marker => None,
+            };
+            if let Some(element) = element {
+                let node = Rc::new(RefCell::new(Node::new(NodeKind::Element(element))));
+                self.insert_node(node.clone(), None);
+                self.stack_of_open_elements.push(node.clone());
+                self.active_formatting_elements[j] = FormattingElement::Element(node);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-list-of-active-formatting-elements-up-to-the-last-marker
+    fn clear_active_formatting_elements_up_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if let FormattingElement::Marker = entry {
+                return;
+            }
+        }
+    }
+
+    /// Fixes up misnested formatting elements such as <b><i></b></i>.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    fn run_adoption_agency_algorithm(&mut self, subject: &str) -> AdoptionAgencyResult {
+        let current = self.current_node();
+        if is_html_element(&current, subject)
+            && self
+                .position_in_active_formatting_elements(&current)
+                .is_none()
+        {
+            self.stack_of_open_elements.pop();
+            return AdoptionAgencyResult::Done;
+        }
+
+        for _ in 0..8 {
+            let formatting_element = match self.find_formatting_element_after_last_marker(subject) {
+                Some(node) => node,
+                None => return AdoptionAgencyResult::AnyOtherEndTag,
+            };
+
+            let formatting_element_index = match self.position_in_stack(&formatting_element) {
+                Some(i) => i,
+                None => {
+                    self.remove_from_active_formatting_elements(&formatting_element);
+                    return AdoptionAgencyResult::Done;
+                }
+            };
+
+            if !self.has_node_in_scope(&formatting_element, Scope::Default) {
+                return AdoptionAgencyResult::Done;
+            }
+
+            let furthest_block_index = (formatting_element_index + 1
+                ..self.stack_of_open_elements.len())
+                .find(|i| is_special(&self.stack_of_open_elements[*i]));
+            let furthest_block_index = match furthest_block_index {
+                Some(i) => i,
+                None => {
+                    self.stack_of_open_elements
+                        .truncate(formatting_element_index);
+                    self.remove_from_active_formatting_elements(&formatting_element);
+                    return AdoptionAgencyResult::Done;
+                }
+            };
+            let furthest_block = self.stack_of_open_elements[furthest_block_index].clone();
+
+            let common_ancestor = self.stack_of_open_elements[formatting_element_index - 1].clone();
+            let mut bookmark = self
+                .position_in_active_formatting_elements(&formatting_element)
+                .expect("formatting element should be in the list");
+
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block.clone();
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.stack_of_open_elements[node_index].clone();
+                if Rc::ptr_eq(&node, &formatting_element) {
+                    break;
+                }
+
+                let mut position = self.position_in_active_formatting_elements(&node);
+                if inner_loop_counter > 3 {
+                    if let Some(i) = position {
+                        self.active_formatting_elements.remove(i);
+                        if i < bookmark {
+                            bookmark -= 1;
+                        }
+                        position = None;
+                    }
+                }
+
+                let position = match position {
+                    Some(i) => i,
+                    None => {
+                        self.stack_of_open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+
+                let element = node
+                    .borrow()
+                    .get_element()
+                    .expect("formatting element should be an element");
+                let new_node = Rc::new(RefCell::new(Node::new(NodeKind::Element(element))));
+                self.active_formatting_elements[position] =
+                    FormattingElement::Element(new_node.clone());
+                self.stack_of_open_elements[node_index] = new_node.clone();
+
+                if Rc::ptr_eq(&last_node, &furthest_block) {
+                    bookmark = position + 1;
+                }
+
+                insert_node_before(&new_node, &last_node, None);
+                last_node = new_node;
+            }
+
+            detach_node(&last_node);
+            self.insert_node(last_node, Some(common_ancestor));
+
+            let element = formatting_element
+                .borrow()
+                .get_element()
+                .expect("formatting element should be an element");
+            let new_node = Rc::new(RefCell::new(Node::new(NodeKind::Element(element))));
+            loop {
+                let child = match furthest_block.borrow().first_child() {
+                    Some(child) => child,
+                    None => break,
+                };
+                insert_node_before(&new_node, &child, None);
+            }
+            insert_node_before(&furthest_block, &new_node, None);
+
+            if let Some(i) = self.position_in_active_formatting_elements(&formatting_element) {
+                self.active_formatting_elements.remove(i);
+                if i < bookmark {
+                    bookmark -= 1;
+                }
+            }
+            self.active_formatting_elements
+                .insert(bookmark, FormattingElement::Element(new_node.clone()));
+
+            self.remove_from_stack(&formatting_element);
+            let furthest_block_index = self
+                .position_in_stack(&furthest_block)
+                .expect("furthest block should be in the stack");
+            self.stack_of_open_elements
+                .insert(furthest_block_index + 1, new_node);
+        }
+
+        AdoptionAgencyResult::Done
+    }
+
+    fn create_char(&self, c: char) -> Node {
+        let mut s = String::new();
+        s.push(c);
+        Node::new(NodeKind::Text(s))
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let (parent, before) = self.appropriate_place_for_inserting_node(None);
+        if parent.borrow().kind() == NodeKind::Document {
+            return;
+        }
+
+        // Append to the text node right before the insertion point if there is one, so that the
+        // parser never creates adjacent or empty text nodes.
+        let prev = match before {
+            Some(ref before) => before.borrow().prev_sibling().upgrade(),
+            None => parent.borrow().last_child().upgrade(),
+        };
+        if let Some(prev) = prev {
+            if let NodeKind::Text(ref mut s) = prev.borrow_mut().kind {
+                s.push(c);
+                return;
+            }
+        }
+
+        let node = Rc::new(RefCell::new(self.create_char(c)));
+        insert_node_before(&parent, &node, before.as_ref());
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+impl Scope {
+    /// Returns true if `element` terminates the search of an element in this scope.
+    fn is_boundary(&self, element: &Element) -> bool {
+        let name = element.local_name();
+
+        // HTML elements are matched by kind so that walking a deep stack doesn't compare
+        // names. <applet> and <marquee> have no kind of their own.
+        let kind = element.kind();
+        // Every element except <optgroup> and <option> is a boundary of the select scope.
+        if *self == Scope::Select {
+            return !matches!(kind, ElementKind::Optgroup | ElementKind::Option);
+        }
+        if *self == Scope::Table {
+            return matches!(
+                kind,
+                ElementKind::Html | ElementKind::Table | ElementKind::Template
+            );
+        }
+
+        match element.namespace() {
+            Namespace::Html => {
+                let is_boundary = match kind {
+                    ElementKind::Caption
+                    | ElementKind::Html
+                    | ElementKind::Table
+                    | ElementKind::Td
+                    | ElementKind::Th
+                    | ElementKind::Object
+                    | ElementKind::Template => true,
+                    ElementKind::Unknown => matches!(name, "applet" | "marquee"),
+                    _ => false,
+                };
+                if is_boundary {
+                    return true;
+                }
+                match self {
+                    Scope::Default => false,
+                    Scope::ListItem => matches!(kind, ElementKind::Ol | ElementKind::Ul),
+                    Scope::Button => kind == ElementKind::Button,
+                    Scope::Table | Scope::Select => false,
+                }
+            }
+            Namespace::MathMl => {
+                matches!(name, "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml")
+            }
+            Namespace::Svg => matches!(name, "foreignObject" | "desc" | "title"),
+        }
+    }
+}
+
+static HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Adds the attributes in `attributes` to `node` unless it already has an attribute with the
+/// same name. This is used for a stray <html> or <body> tag.
+fn merge_attributes(node: &Rc<RefCell<Node>>, attributes: &[Attribute]) {
+    let mut added = Vec::new();
+    if let NodeKind::Element(ref mut element) = node.borrow_mut().kind {
+        for attr in attributes {
+            if element.add_attribute(attr.clone()) {
+                added.push(attr);
+            }
+        }
+    }
+    for attr in added {
+        queue_attribute_record(node, &attr.local_name(), attr.namespace(), None);
+    }
+    if attributes.iter().any(|a| a.name() == "id") {
+        index_ids(node);
+    }
+}
+
+/// Returns true if `node` is an element in the HTML namespace.
+fn is_html_element_node(node: &Rc<RefCell<Node>>) -> bool {
+    match node.borrow().kind {
+        NodeKind::Element(ref e) => e.namespace() == Namespace::Html,
+        _ => false,
+    }
+}
+
+/// Returns true if the local name of `node` matches `tag`, which the tokenizer lowercased.
+fn has_local_name_ignoring_case(node: &Rc<RefCell<Node>>, tag: &str) -> bool {
+    match node.borrow().kind {
+        NodeKind::Element(ref e) => e.local_name().eq_ignore_ascii_case(tag),
+        _ => false,
+    }
+}
+
+fn is_html_element(node: &Rc<RefCell<Node>>, tag: &str) -> bool {
+    match node.borrow().kind {
+        NodeKind::Element(ref e) => e.is_html(tag),
+        _ => false,
+    }
+}
+
+/// Returns true if character tokens are buffered by the "in table text" insertion mode when
+/// `node` is the current node.
+fn is_table_context(node: &Rc<RefCell<Node>>) -> bool {
+    ["table", "tbody", "template", "tfoot", "thead", "tr"]
+        .iter()
+        .any(|tag| is_html_element(node, tag))
+}
+
+fn is_hidden_input(attributes: &[Attribute]) -> bool {
+    attributes
+        .iter()
+        .any(|a| a.name() == "type" && a.value().eq_ignore_ascii_case("hidden"))
+}
+
+fn is_heading(node: &Rc<RefCell<Node>>) -> bool {
+    HEADINGS.iter().any(|h| is_html_element(node, h))
+}
+
+/// Returns true if two elements have the same tag name, namespace and attributes.
+fn is_same_formatting_element(a: &Rc<RefCell<Node>>, b: &Rc<RefCell<Node>>) -> bool {
+    *a.borrow() == *b.borrow()
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#special
+fn is_special(node: &Rc<RefCell<Node>>) -> bool {
+    let node = node.borrow();
+    let element = match node.kind {
+        NodeKind::Element(ref e) => e,
+        _ => return false,
+    };
+
+    let names: &[&str] = match element.namespace() {
+        Namespace::Html => &[
+            "address",
+            "applet",
+            "area",
+            "article",
+            "aside",
+            "base",
+            "basefont",
+            "bgsound",
+            "blockquote",
+            "body",
+            "br",
+            "button",
+            "caption",
+            "center",
+            "col",
+            "colgroup",
+            "dd",
+            "details",
+            "dir",
+            "div",
+            "dl",
+            "dt",
+            "embed",
+            "fieldset",
+            "figcaption",
+            "figure",
+            "footer",
+            "form",
+            "frame",
+            "frameset",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "head",
+            "header",
+            "hgroup",
+            "hr",
+            "html",
+            "iframe",
+            "img",
+            "input",
+            "keygen",
+            "li",
+            "link",
+            "listing",
+            "main",
+            "marquee",
+            "menu",
+            "meta",
+            "nav",
+            "noembed",
+            "noframes",
+            "noscript",
+            "object",
+            "ol",
+            "p",
+            "param",
+            "plaintext",
+            "pre",
+            "script",
+            "search",
+            "section",
+            "select",
+            "source",
+            "style",
+            "summary",
+            "table",
+            "tbody",
+            "td",
+            "template",
+            "textarea",
+            "tfoot",
+            "th",
+            "thead",
+            "title",
+            "tr",
+            "track",
+            "ul",
+            "wbr",
+            "xmp",
+        ],
+        Namespace::MathMl => &["mi", "mo", "mn", "ms", "mtext", "annotation-xml"],
+        Namespace::Svg => &["foreignObject", "desc", "title"],
+    };
+    names.contains(&element.local_name())
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+fn quirks_mode_from_doctype(
+    name: &Option<String>,
+    public_id: &Option<String>,
+    system_id: &Option<String>,
+    force_quirks: bool,
+) -> QuirksMode {
+    let public_id = public_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let system_id = system_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let public_starts_with = |prefixes: &[&str]| match &public_id {
+        Some(id) => prefixes
+            .iter()
+            .any(|p| id.starts_with(&p.to_ascii_lowercase())),
+        None => false,
+    };
+
+    if force_quirks
+        || name.as_deref() != Some("html")
+        || public_id.as_deref() == Some("-//w3o//dtd w3 html strict 3.0//en//")
+        || public_id.as_deref() == Some("-/w3c/dtd html 4.0 transitional/en")
+        || public_id.as_deref() == Some("html")
+        || system_id.as_deref()
+            == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        || public_starts_with(QUIRKY_PUBLIC_ID_PREFIXES)
+        || (system_id.is_none()
+            && public_starts_with(&[
+                "-//W3C//DTD HTML 4.01 Frameset//",
+                "-//W3C//DTD HTML 4.01 Transitional//",
+            ]))
+    {
+        return QuirksMode::Quirks;
+    }
+
+    if public_starts_with(&[
+        "-//W3C//DTD XHTML 1.0 Frameset//",
+        "-//W3C//DTD XHTML 1.0 Transitional//",
+    ]) || (system_id.is_some()
+        && public_starts_with(&[
+            "-//W3C//DTD HTML 4.01 Frameset//",
+            "-//W3C//DTD HTML 4.01 Transitional//",
+        ]))
+    {
+        return QuirksMode::LimitedQuirks;
+    }
+
+    QuirksMode::NoQuirks
+}
+
+static QUIRKY_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "+//Silmaril//dtd html Pro v0r11 19970101//",
+    "-//AS//DTD HTML 3.0 asWedit + extensions//",
+    "-//AdvaSoft Ltd//DTD HTML 3.0 asWedit + extensions//",
+    "-//IETF//DTD HTML 2.0 Level 1//",
+    "-//IETF//DTD HTML 2.0 Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict Level 1//",
+    "-//IETF//DTD HTML 2.0 Strict Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict//",
+    "-//IETF//DTD HTML 2.0//",
+    "-//IETF//DTD HTML 2.1E//",
+    "-//IETF//DTD HTML 3.0//",
+    "-//IETF//DTD HTML 3.2 Final//",
+    "-//IETF//DTD HTML 3.2//",
+    "-//IETF//DTD HTML 3//",
+    "-//IETF//DTD HTML Level 0//",
+    "-//IETF//DTD HTML Level 1//",
+    "-//IETF//DTD HTML Level 2//",
+    "-//IETF//DTD HTML Level 3//",
+    "-//IETF//DTD HTML Strict Level 0//",
+    "-//IETF//DTD HTML Strict Level 1//",
+    "-//IETF//DTD HTML Strict Level 2//",
+    "-//IETF//DTD HTML Strict Level 3//",
+    "-//IETF//DTD HTML Strict//",
+    "-//IETF//DTD HTML//",
+    "-//Metrius//DTD Metrius Presentational//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 2.0 Tables//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 3.0 Tables//",
+    "-//Netscape Comm. Corp.//DTD HTML//",
+    "-//Netscape Comm. Corp.//DTD Strict HTML//",
+    "-//O'Reilly and Associates//DTD HTML 2.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended 1.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended Relaxed 1.0//",
+    "-//SQ//DTD HTML 2.0 HoTMetaL + extensions//",
+    "-//SoftQuad Software//DTD HoTMetaL PRO 6.0::19990601::extensions to HTML 4.0//",
+    "-//SoftQuad//DTD HoTMetaL PRO 4.0::19971010::extensions to HTML 4.0//",
+    "-//Spyglass//DTD HTML 2.0 Extended//",
+    "-//Sun Microsystems Corp.//DTD HotJava HTML//",
+    "-//Sun Microsystems Corp.//DTD HotJava Strict HTML//",
+    "-//W3C//DTD HTML 3 1995-03-24//",
+    "-//W3C//DTD HTML 3.2 Draft//",
+    "-//W3C//DTD HTML 3.2 Final//",
+    "-//W3C//DTD HTML 3.2//",
+    "-//W3C//DTD HTML 3.2S Draft//",
+    "-//W3C//DTD HTML 4.0 Frameset//",
+    "-//W3C//DTD HTML 4.0 Transitional//",
+    "-//W3C//DTD HTML Experimental 19960712//",
+    "-//W3C//DTD HTML Experimental 970421//",
+    "-//W3C//DTD W3 HTML//",
+    "-//W3O//DTD W3 HTML 3.0//",
+    "-//WebTechs//DTD Mozilla HTML 2.0//",
+    "-//WebTechs//DTD Mozilla HTML//",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    InTemplate,
+    AfterBody,
+    InFrameset,
+    AfterFrameset,
+    AfterAfterBody,
+    AfterAfterFrameset,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::string::ToString;
+    use crate::renderer::dom::api::get_elements_by_tag_name;
+    use crate::renderer::dom::serializer::serialize_children;
+    use crate::renderer::dom::serializer::serialize_node;
+    use crate::renderer::dom::test_utils::text;
+    use crate::renderer::dom::traversal::inclusive_descendants;
+    use crate::renderer::html::attribute::AttributeNamespace;
+    use alloc::vec;
+
+    #[test]
+    fn test_empty() {
+        let html = "".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let expected = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+        assert_eq!(expected, window.borrow().document());
+    }
+
+    #[test]
+    fn test_body() {
+        let html = "<html><head></head><body></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Document))),
+            document
+        );
+
+        let html = document
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of document");
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "html",
+                Vec::new()
+            ))))),
+            html
+        );
+
+        let head = html
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of html");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "head",
+                Vec::new()
+            ))))),
+            head
+        );
+
+        let body = head
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of head");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "body",
+                Vec::new()
+            ))))),
+            body
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        let html = "<html><head></head><body>text</body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Document))),
+            document
+        );
+
+        let html = document
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of document");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "html",
+                Vec::new()
+            ))))),
+            html
+        );
+
+        let body = html
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of html")
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of head");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "body",
+                Vec::new()
+            ))))),
+            body
+        );
+
+        let text = body
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of body");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))),
+            text
+        );
+    }
+
+    #[test]
+    fn test_multiple_nodes() {
+        let html = "<html><head></head><body><p><a foo=bar>test</a></p></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of document")
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of html")
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of head");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "body",
+                Vec::new()
+            ))))),
+            body
+        );
+
+        let p = body
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of body");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "p",
+                Vec::new()
+            ))))),
+            p
+        );
+
+        let mut attr = Attribute::new();
+        attr.add_char('f', true);
+        attr.add_char('o', true);
+        attr.add_char('o', true);
+        attr.add_char('b', false);
+        attr.add_char('a', false);
+        attr.add_char('r', false);
+
+        let a = p
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of p");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                "a",
+                vec![attr]
+            ))))),
+            a
+        );
+
+        let text = a
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of a");
+
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Text("test".to_string())))),
+            text
+        );
+    }
+
+    #[test]
+    fn test_comment_and_doctype() {
+        let html = "<!DOCTYPE html><!--a--><html><body><!--b--></body></html><!--c-->".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        assert_eq!(
+            QuirksMode::NoQuirks,
+            window.borrow().document().borrow().quirks_mode()
+        );
+        let document = window.borrow().document();
+
+        let doctype = document
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of document");
+        assert_eq!(
+            NodeKind::DocumentType(DocumentType::new(
+                "html".to_string(),
+                "".to_string(),
+                "".to_string()
+            )),
+            doctype.borrow().kind()
+        );
+
+        let comment = doctype
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of doctype");
+        assert_eq!(NodeKind::Comment("a".to_string()), comment.borrow().kind());
+
+        let html = comment
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of comment");
+        let body = html
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("failed to get last child of html");
+        assert_eq!(
+            NodeKind::Element(Element::new("body", Vec::new())),
+            body.borrow().kind()
+        );
+        match body.borrow().first_child() {
+            Some(n) => assert!(matches!(n.borrow().kind(), NodeKind::Comment(ref s) if s == "b")),
+            None => panic!("failed to get first child of body"),
+        }
+
+        let last = document
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("failed to get last child of document");
+        assert!(matches!(last.borrow().kind(), NodeKind::Comment(ref s) if s == "c"));
+    }
+
+    #[test]
+    fn test_quirks_mode() {
+        let cases = [
+            ("<html></html>", QuirksMode::Quirks),
+            ("<!DOCTYPE html>", QuirksMode::NoQuirks),
+            ("<!DOCTYPE foo>", QuirksMode::Quirks),
+            (
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">",
+                QuirksMode::Quirks,
+            ),
+            (
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \"http://www.w3.org/TR/html4/loose.dtd\">",
+                QuirksMode::LimitedQuirks,
+            ),
+            (
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">",
+                QuirksMode::LimitedQuirks,
+            ),
+        ];
+
+        for (html, expected) in cases {
+            let t = HtmlTokenizer::new(html.to_string());
+            let window = HtmlParser::new(t).construct_tree();
+            assert_eq!(
+                expected,
+                window.borrow().document().borrow().quirks_mode(),
+                "{}",
+                html
+            );
+        }
+    }
+
+    #[test]
+    fn test_generic_elements() {
+        let html =
+            "<html><head><title>t</title><meta charset=utf-8></head><body><div><span>a</span><br><my-element>b</my-element></div></body></html>"
+                .to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let head = document
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of document")
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of html");
+        let title = head
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of head");
+        assert_eq!(Some(ElementKind::Title), title.borrow().element_kind());
+        let meta = title
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of title");
+        assert_eq!(Some(ElementKind::Meta), meta.borrow().element_kind());
+
+        let div = head
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of head")
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of body");
+        assert_eq!(Some(ElementKind::Div), div.borrow().element_kind());
+
+        let span = div
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of div");
+        assert_eq!(Some(ElementKind::Span), span.borrow().element_kind());
+
+        let br = span
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of span");
+        assert_eq!(Some(ElementKind::Br), br.borrow().element_kind());
+        assert!(br.borrow().first_child().is_none());
+
+        let custom = br
+            .borrow()
+            .next_sibling()
+            .expect("failed to get next sibling of br");
+        let element = custom
+            .borrow()
+            .get_element()
+            .expect("my-element should be an element");
+        assert_eq!(ElementKind::Unknown, element.kind());
+        assert_eq!("my-element", element.local_name());
+        assert_eq!(Namespace::Html, element.namespace());
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Text("b".to_string())))),
+            custom
+                .borrow()
+                .first_child()
+                .expect("failed to get first child of my-element")
+        );
+    }
+
+    #[test]
+    fn test_unmatched_end_tag() {
+        let html = "<body><div><span>a</i></div>b</body>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let body = window
+            .borrow()
+            .document()
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("failed to get last child of html");
+
+        let div = body
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of body");
+        assert_eq!(Some(ElementKind::Div), div.borrow().element_kind());
+        assert_eq!(
+            Rc::new(RefCell::new(Node::new(NodeKind::Text("b".to_string())))),
+            div.borrow()
+                .next_sibling()
+                .expect("failed to get next sibling of div")
+        );
+    }
+
+    fn parse_body(html: &str) -> Rc<RefCell<Node>> {
+        let t = HtmlTokenizer::new(html.to_string());
+        let window = HtmlParser::new(t).construct_tree();
+        let body = window
+            .borrow()
+            .document()
+            .borrow()
+            .first_child()
+            .expect("failed to get first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("failed to get last child of html");
+        body
+    }
+
+    fn children(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+        let mut children = Vec::new();
+        let mut child = node.borrow().first_child();
+        while let Some(c) = child {
+            child = c.borrow().next_sibling();
+            children.push(c);
+        }
+        children
+    }
+
+    #[test]
+    fn test_misnested_formatting_elements() {
+        let body = parse_body("<b><i>a</b>c</i>");
+        let children_of_body = children(&body);
+        assert_eq!(2, children_of_body.len());
+
+        let b = &children_of_body[0];
+        assert_eq!(Some(ElementKind::B), b.borrow().element_kind());
+        let i = b.borrow().first_child().expect("failed to get i in b");
+        assert_eq!(Some(ElementKind::I), i.borrow().element_kind());
+        assert!(i.borrow().next_sibling().is_none());
+
+        // The i element is reopened after </b>.
+        let i = &children_of_body[1];
+        assert_eq!(Some(ElementKind::I), i.borrow().element_kind());
+        assert_eq!(Some(text("c")), i.borrow().first_child());
+    }
+
+    #[test]
+    fn test_adoption_agency_with_block() {
+        let body = parse_body("<a>1<p>2</a>3</p>");
+        let children_of_body = children(&body);
+        assert_eq!(2, children_of_body.len());
+        assert_eq!(
+            Some(ElementKind::A),
+            children_of_body[0].borrow().element_kind()
+        );
+
+        let p = &children_of_body[1];
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+        let children_of_p = children(p);
+        assert_eq!(2, children_of_p.len());
+        assert_eq!(
+            Some(ElementKind::A),
+            children_of_p[0].borrow().element_kind()
+        );
+        assert_eq!(Some(text("2")), children_of_p[0].borrow().first_child());
+        assert_eq!(text("3"), children_of_p[1]);
+    }
+
+    #[test]
+    fn test_implied_end_tags() {
+        let body = parse_body("<p>a<div>b</div><ul><li>c<li>d</ul>");
+        let children_of_body = children(&body);
+        assert_eq!(3, children_of_body.len());
+        assert_eq!(
+            Some(ElementKind::P),
+            children_of_body[0].borrow().element_kind()
+        );
+        assert_eq!(
+            Some(ElementKind::Div),
+            children_of_body[1].borrow().element_kind()
+        );
+
+        let children_of_ul = children(&children_of_body[2]);
+        assert_eq!(2, children_of_ul.len());
+        for li in children_of_ul {
+            assert_eq!(Some(ElementKind::Li), li.borrow().element_kind());
+        }
+    }
+
+    #[test]
+    fn test_end_tag_without_start_tag() {
+        let body = parse_body("<body></p></div></h1>a");
+        let children_of_body = children(&body);
+        assert_eq!(2, children_of_body.len());
+        assert_eq!(
+            Some(ElementKind::P),
+            children_of_body[0].borrow().element_kind()
+        );
+        assert!(children_of_body[0].borrow().first_child().is_none());
+        assert_eq!(text("a"), children_of_body[1]);
+    }
+
+    #[test]
+    fn test_table() {
+        let body = parse_body("<table><tr><td>a<td>b</table>");
+        let table = body.borrow().first_child().expect("failed to get table");
+        assert_eq!(Some(ElementKind::Table), table.borrow().element_kind());
+
+        // A tbody element is implied by <tr>.
+        let tbody = table.borrow().first_child().expect("failed to get tbody");
+        assert_eq!(Some(ElementKind::Tbody), tbody.borrow().element_kind());
+        let tr = tbody.borrow().first_child().expect("failed to get tr");
+        assert_eq!(Some(ElementKind::Tr), tr.borrow().element_kind());
+
+        let cells = children(&tr);
+        assert_eq!(2, cells.len());
+        assert_eq!(Some(ElementKind::Td), cells[0].borrow().element_kind());
+        assert_eq!(Some(text("a")), cells[0].borrow().first_child());
+        assert_eq!(Some(ElementKind::Td), cells[1].borrow().element_kind());
+        assert_eq!(Some(text("b")), cells[1].borrow().first_child());
+    }
+
+    #[test]
+    fn test_table_caption_and_colgroup() {
+        let body = parse_body("<table><caption>c</caption><col><thead><tr><th>h</table>");
+        let table = body.borrow().first_child().expect("failed to get table");
+        let children_of_table = children(&table);
+        assert_eq!(3, children_of_table.len());
+        assert_eq!(
+            Some(ElementKind::Caption),
+            children_of_table[0].borrow().element_kind()
+        );
+        assert_eq!(
+            Some(ElementKind::Colgroup),
+            children_of_table[1].borrow().element_kind()
+        );
+        assert_eq!(
+            Some(ElementKind::Col),
+            children_of_table[1]
+                .borrow()
+                .first_child()
+                .expect("failed to get col")
+                .borrow()
+                .element_kind()
+        );
+        assert_eq!(
+            Some(ElementKind::Thead),
+            children_of_table[2].borrow().element_kind()
+        );
+    }
+
+    #[test]
+    fn test_foster_parenting() {
+        let body = parse_body("<table>a<b>b</b><tr><td>c</td></tr>d</table>");
+        let children_of_body = children(&body);
+        assert_eq!(4, children_of_body.len());
+
+        // Misplaced text and elements are moved before the table.
+        assert_eq!(text("a"), children_of_body[0]);
+        assert_eq!(
+            Some(ElementKind::B),
+            children_of_body[1].borrow().element_kind()
+        );
+        assert_eq!(text("d"), children_of_body[2]);
+        let table = &children_of_body[3];
+        assert_eq!(Some(ElementKind::Table), table.borrow().element_kind());
+        assert_eq!(
+            Some(ElementKind::Td),
+            table
+                .borrow()
+                .first_child()
+                .expect("failed to get tbody")
+                .borrow()
+                .first_child()
+                .expect("failed to get tr")
+                .borrow()
+                .first_child()
+                .expect("failed to get td")
+                .borrow()
+                .element_kind()
+        );
+    }
 
-            last_sibling
-                .unwrap()
-                .borrow_mut()
-                .set_next_sibling(Some(node.clone()));
-            node.borrow_mut().set_prev_sibling(Rc::downgrade(
-                &current
-                    .borrow()
-                    .first_child()
-                    .expect("failed to get first child"),
-            ))
-        } else {
-            current.borrow_mut().set_first_child(Some(node.clone()));
+    fn parse_fragment(html: &str, context: &str) -> Vec<Rc<RefCell<Node>>> {
+        let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            context,
+            Vec::new(),
+        )))));
+        let t = HtmlTokenizer::new(html.to_string());
+        HtmlParser::new(t).parse_fragment(&context)
+    }
+
+    #[test]
+    fn test_fragment() {
+        let nodes = parse_fragment("<p>a</p>b</body><div>", "div");
+        assert_eq!(3, nodes.len());
+        assert_eq!(Some(ElementKind::P), nodes[0].borrow().element_kind());
+        assert_eq!(Some(text("a")), nodes[0].borrow().first_child());
+        assert_eq!(text("b"), nodes[1]);
+        assert_eq!(Some(ElementKind::Div), nodes[2].borrow().element_kind());
+        for node in nodes {
+            assert!(node.borrow().parent().upgrade().is_none());
         }
+    }
 
-        current.borrow_mut().set_last_child(Rc::downgrade(&node));
-        node.borrow_mut().set_parent(Rc::downgrade(&current));
+    #[test]
+    fn test_fragment_in_table_context() {
+        let nodes = parse_fragment("<td>a<td>b", "tr");
+        assert_eq!(2, nodes.len());
+        assert_eq!(Some(ElementKind::Td), nodes[0].borrow().element_kind());
+        assert_eq!(Some(ElementKind::Td), nodes[1].borrow().element_kind());
 
-        self.stack_of_open_elements.push(node);
+        let nodes = parse_fragment("<tr><td>a", "tbody");
+        assert_eq!(1, nodes.len());
+        assert_eq!(Some(ElementKind::Tr), nodes[0].borrow().element_kind());
     }
 
-    fn pop_current_node(&mut self, element_kind: ElementKind) -> bool {
-        let current = match self.stack_of_open_elements.last() {
-            Some(node) => node,
-            None => return false,
+    #[test]
+    fn test_fragment_in_quirks_mode_document() {
+        let parse_in_body = |document_html: &str| {
+            let window =
+                HtmlParser::new(HtmlTokenizer::new(document_html.to_string())).construct_tree();
+            let body = get_elements_by_tag_name(&window.borrow().document(), "body")[0].clone();
+            let t = HtmlTokenizer::new("<p>a<table></table>".to_string());
+            let nodes = HtmlParser::new(t).parse_fragment(&body);
+            nodes.iter().map(serialize_node).collect::<Vec<String>>()
         };
 
-        if current.borrow().element_kind() == Some(element_kind) {
-            self.stack_of_open_elements.pop();
-            return true;
-        }
-        false
-    }
-
-    fn pop_until(&mut self, element_kind: ElementKind) {
-        assert!(
-            self.contain_in_stack(element_kind),
-            "stack does not have an element {:?}",
-            element_kind
+        // A <table> closes a <p> unless the context's document is in quirks mode.
+        assert_eq!(
+            vec!["<p>a</p>", "<table></table>"],
+            parse_in_body("<!DOCTYPE html><body>")
         );
-
-        loop {
-            let current = match self.stack_of_open_elements.pop() {
-                Some(node) => node,
-                None => return,
-            };
-
-            if current.borrow().element_kind() == Some(element_kind) {
-                return;
-            }
-        }
+        assert_eq!(vec!["<p>a<table></table></p>"], parse_in_body("<body>"));
     }
 
-    fn contain_in_stack(&mut self, element_kind: ElementKind) -> bool {
-        for i in 0..self.stack_of_open_elements.len() {
-            if self.stack_of_open_elements[i].borrow().element_kind() == Some(element_kind) {
-                return true;
-            }
-        }
+    fn parse_and_serialize(html: &str) -> String {
+        let t = HtmlTokenizer::new(html.to_string());
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        serialize_children(&document)
+    }
 
-        false
+    #[test]
+    fn test_rcdata_elements() {
+        assert_eq!(
+            "<html><head><title>&lt;b&gt;a&amp;b&lt;/b&gt;</title></head><body></body></html>",
+            parse_and_serialize("<title><b>a&amp;b</b></title>")
+        );
+        // The first newline in <textarea> is dropped.
+        assert_eq!(
+            "<html><head></head><body><textarea>&lt;p&gt;\nx</textarea>y</body></html>",
+            parse_and_serialize("<textarea>\n<p>\nx</textarea>y")
+        );
     }
 
-    fn create_char(&self, c: char) -> Node {
-        let mut s = String::new();
-        s.push(c);
-        Node::new(NodeKind::Text(s))
+    #[test]
+    fn test_raw_text_elements() {
+        assert_eq!(
+            "<html><head><style><p>&amp;</style></head><body><xmp><b></xmp><iframe></p></iframe></body></html>",
+            parse_and_serialize("<style><p>&amp;</style><xmp><b></xmp><iframe></p></iframe>")
+        );
+        assert_eq!(
+            "<html><head><noscript><link></noscript></head><body><p></p><plaintext></plaintext> &amp;</plaintext></body></html>",
+            parse_and_serialize("<noscript><link></noscript><p><plaintext></plaintext> &amp;")
+        );
     }
 
-    fn insert_char(&mut self, c: char) {
-        let current = match self.stack_of_open_elements.last() {
-            Some(node) => node.clone(),
-            None => return,
-        };
+    #[test]
+    fn test_streaming() {
+        let mut parser = HtmlParser::new(HtmlTokenizer::new_streaming());
+        parser.push_str("<p>a</p><p cla");
+        let document = parser.window().borrow().document();
+        assert_eq!(
+            "<html><head></head><body><p>a</p></body></html>",
+            serialize_children(&document)
+        );
 
-        if let NodeKind::Text(ref mut s) = current.borrow_mut().kind {
-            s.push(c);
-            return;
-        }
+        parser.push_bytes(b"ss=x>caf\xC3");
+        parser.push_bytes(b"\xA9 &am");
+        parser.push_str("p;<b");
+        // "<b" is dropped because the input ends in the tag.
+        let window = parser.construct_tree();
+        assert_eq!(
+            "<html><head></head><body><p>a</p><p class=\"x\">caf\u{e9} &amp;</p></body></html>",
+            serialize_children(&window.borrow().document())
+        );
+    }
 
-        if c == '\n' || c == ' ' {
-            return;
+    /// Checks that there are no adjacent or empty text nodes in the tree rooted at `root`.
+    fn assert_normalized(root: &Rc<RefCell<Node>>) {
+        for node in inclusive_descendants(root) {
+            if let NodeKind::Text(ref s) = node.borrow().kind {
+                assert!(!s.is_empty());
+                let next = node.borrow().next_sibling();
+                assert!(!next.is_some_and(|n| matches!(n.borrow().kind, NodeKind::Text(_))));
+            }
         }
+    }
 
-        let node = Rc::new(RefCell::new(self.create_char(c)));
+    #[test]
+    fn test_normalized_text() {
+        for html in [
+            "a<!-- b -->c",
+            "<table>a<tr>b</tr>c</table>d",
+            "<b>a<p>b</b>c",
+            "<a>a<table><a>b</table>c",
+        ] {
+            let t = HtmlTokenizer::new(html.to_string());
+            let window = HtmlParser::new(t).construct_tree();
+            assert_normalized(&window.borrow().document());
 
-        if current.borrow().first_child().is_some() {
-            current
-                .borrow()
-                .first_child()
-                .unwrap()
-                .borrow_mut()
-                .set_next_sibling(Some(node.clone()));
-            node.borrow_mut().set_prev_sibling(Rc::downgrade(
-                &current
-                    .borrow()
-                    .first_child()
-                    .expect("failed to get first child"),
-            ));
-        } else {
-            current.borrow_mut().set_first_child(Some(node.clone()));
+            // Text split across chunks is appended to the same text node.
+            let mut parser = HtmlParser::new(HtmlTokenizer::new_streaming());
+            for c in html.chars() {
+                parser.push_str(&c.to_string());
+            }
+            let window = parser.construct_tree();
+            assert_normalized(&window.borrow().document());
         }
-
-        current.borrow_mut().set_last_child(Rc::downgrade(&node));
-
-        node.borrow_mut().set_parent(Rc::downgrade(&current));
-
-        self.stack_of_open_elements.push(node);
     }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum InsertionMode {
-    Initial,
-    BeforeHtml,
-    BeforeHead,
-    InHead,
-    AfterHead,
-    InBody,
-    Text,
-    AfterBody,
-    AfterAfterBody,
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::alloc::string::ToString;
-    use alloc::vec;
+    #[test]
+    fn test_parse_errors() {
+        let t = HtmlTokenizer::new("<p>a\n</div></p></body><!DOCTYPE html><b".to_string());
+        let mut parser = HtmlParser::new(t);
+        parser.construct_tree();
+        let errors: Vec<(&str, usize, usize)> = parser
+            .errors()
+            .iter()
+            .map(|e| (e.kind.name(), e.position.line, e.position.column))
+            .collect();
+        assert_eq!(
+            vec![
+                ("missing-doctype", 1, 1),
+                ("unexpected-end-tag", 2, 1),
+                ("unexpected-doctype", 2, 18),
+                ("eof-in-tag", 2, 35),
+            ],
+            errors
+        );
+    }
 
     #[test]
-    fn test_empty() {
-        let html = "".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let expected = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
-        assert_eq!(expected, window.borrow().document());
+    fn test_malformed_input_does_not_panic() {
+        let inputs = [
+            "<",
+            "</",
+            "<a",
+            "<a b",
+            "<a b=",
+            "<a b='",
+            "</a b>",
+            "</>",
+            "<!",
+            "<!-",
+            "<!--",
+            "<!-->",
+            "<!---",
+            "<!DOCTYPE",
+            "<!DOCTYPE html PUBLIC",
+            "<!DOCTYPE html SYSTEM '",
+            "&",
+            "&#",
+            "&#x",
+            "&#x110000;",
+            "&#0;",
+            "<a\0b c\0=\0>",
+            "<p/ />",
+            "<title>",
+            "<textarea></textare",
+            "<script><!--<script></script>",
+            "<table><tr>a<td>",
+            "</table></tr></td>",
+            "<b><p></b></i></p>",
+            "<?php ?>",
+            "<![CDATA[x]]>",
+        ];
+        for input in inputs {
+            let t = HtmlTokenizer::new(input.to_string());
+            HtmlParser::new(t).construct_tree();
+        }
     }
 
     #[test]
-    fn test_body() {
-        let html = "<html><head></head><body></body></html>".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let document = window.borrow().document();
+    fn test_svg() {
+        let body = parse_body("<svg viewbox='0 0 1 1'><lineargradient/><circle r=1></circle><p>a");
+        let children_of_body = children(&body);
+        assert_eq!(2, children_of_body.len());
+
+        let svg = children_of_body[0]
+            .borrow()
+            .get_element()
+            .expect("not an element");
+        assert_eq!(Namespace::Svg, svg.namespace());
+        assert_eq!(ElementKind::Unknown, svg.kind());
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Document))),
-            document
+            Some("viewBox".to_string()),
+            svg.attributes().iter().next().map(|a| a.name())
         );
 
-        let html = document
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of document");
+        let names: Vec<(String, Namespace)> = children(&children_of_body[0])
+            .iter()
+            .map(|n| {
+                let e = n.borrow().get_element().expect("not an element");
+                (e.local_name().to_string(), e.namespace())
+            })
+            .collect();
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "html",
-                Vec::new()
-            ))))),
-            html
+            vec![
+                ("linearGradient".to_string(), Namespace::Svg),
+                ("circle".to_string(), Namespace::Svg)
+            ],
+            names
         );
 
-        let head = html
+        // <p> isn't allowed in SVG and closes it.
+        let p = children_of_body[1]
             .borrow()
-            .first_child()
-            .expect("failed to get first child of html");
+            .get_element()
+            .expect("not an element");
+        assert_eq!(Namespace::Html, p.namespace());
+        assert_eq!("p", p.local_name());
+    }
 
+    #[test]
+    fn test_foreign_attributes() {
+        let body = parse_body("<svg xmlns:xlink=x><a xlink:href=#a XLINK:show=new></a></svg>");
+        let svg = body.borrow().first_child().expect("no svg");
+        let a = svg.borrow().first_child().expect("no a");
+        let a = a.borrow().get_element().expect("not an element");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "head",
-                Vec::new()
-            ))))),
-            head
+            Some("#a".to_string()),
+            a.get_attribute_ns(Some(AttributeNamespace::XLink), "href")
         );
+        assert_eq!(Some("#a".to_string()), a.get_attribute("xlink:href"));
+        assert_eq!(None, a.get_attribute_ns(None, "href"));
+        assert!(a.has_attribute("xlink:show"));
 
-        let body = head
-            .borrow()
-            .next_sibling()
-            .expect("failed to get next sibling of head");
-
+        let svg = svg.borrow().get_element().expect("not an element");
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "body",
-                Vec::new()
-            ))))),
-            body
+            Some("x".to_string()),
+            svg.get_attribute_ns(Some(AttributeNamespace::Xmlns), "xlink")
         );
     }
 
     #[test]
-    fn test_text() {
-        let html = "<html><head></head><body>text</body></html>".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let document = window.borrow().document();
+    fn test_foreign_content() {
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Document))),
-            document
+            "<html><head></head><body><svg><foreignObject><p>a</p></foreignObject>b</svg>\
+             </body></html>",
+            parse_and_serialize("<svg><foreignobject><p>a</p></foreignobject>b</svg>")
         );
-
-        let html = document
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of document");
-
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "html",
-                Vec::new()
-            ))))),
-            html
+            "<html><head></head><body><svg><desc><b>a</b></desc><g></g></svg></body></html>",
+            parse_and_serialize("<svg><desc><b>a</b></desc><G></svg>")
         );
-
-        let body = html
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of html")
-            .borrow()
-            .next_sibling()
-            .expect("failed to get next sibling of head");
-
+        // CDATA sections are text in foreign content.
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "body",
-                Vec::new()
-            ))))),
-            body
+            "<html><head></head><body><svg><text>a&lt;b</text></svg><!--[CDATA[c]]-->\
+             </body></html>",
+            parse_and_serialize("<svg><text><![CDATA[a<b]]></text></svg><![CDATA[c]]>")
         );
-
-        let text = body
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of body");
-
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))),
-            text
+            "<html><head></head><body><math definitionURL=\"x\"><mi><b>a</b></mi>\
+             <annotation-xml encoding=\"TEXT/HTML\"><div></div></annotation-xml></math>\
+             </body></html>",
+            parse_and_serialize(
+                "<math definitionurl=x><mi><b>a</b></mi>\
+                 <annotation-xml encoding=TEXT/HTML><div></div></annotation-xml></math>"
+            )
+        );
+        // An end tag closes the matching foreign element.
+        assert_eq!(
+            "<html><head></head><body><div><svg><g><rect></rect></g></svg>a</div></body></html>",
+            parse_and_serialize("<div><svg><g><rect></g></svg>a</div>")
         );
     }
 
     #[test]
-    fn test_multiple_nodes() {
-        let html = "<html><head></head><body><p><a foo=bar>test</a></p></body></html>".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let document = window.borrow().document();
+    fn test_fragment_in_svg_context() {
+        let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(
+            Element::new_with_namespace("svg", Namespace::Svg, Vec::new()),
+        ))));
+        let t = HtmlTokenizer::new("<path/><![CDATA[a]]>".to_string());
+        let nodes = HtmlParser::new(t).parse_fragment(&context);
+        assert_eq!(2, nodes.len());
+        let path = nodes[0].borrow().get_element().expect("not an element");
+        assert_eq!(Namespace::Svg, path.namespace());
+        assert_eq!(text("a"), nodes[1]);
+    }
 
-        let body = document
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of document")
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of html")
-            .borrow()
-            .next_sibling()
-            .expect("failed to get next sibling of head");
+    #[test]
+    fn test_fragment_in_rcdata_context() {
+        let nodes = parse_fragment("<b>&amp;</title>", "title");
+        assert_eq!(1, nodes.len());
+        let kind = nodes[0].borrow().kind();
+        match kind {
+            NodeKind::Text(s) => assert_eq!("<b>&</title>", s),
+            _ => panic!("expected a text node but got {:?}", kind),
+        }
+    }
 
+    #[test]
+    fn test_template_contents() {
+        let body = parse_body("<body><template><p>a</p></template><p>b</p>");
+        let template = body.borrow().first_child().expect("failed to get template");
+        // The contents aren't children of the template so that they aren't rendered.
+        assert!(template.borrow().first_child().is_none());
+        let contents = template
+            .borrow()
+            .get_element()
+            .and_then(|e| e.template_contents())
+            .expect("failed to get template contents");
+        assert_eq!(NodeKind::DocumentFragment, contents.borrow().kind());
+        let p = contents.borrow().first_child().expect("failed to get p");
+        assert_eq!(Some(text("a")), p.borrow().first_child());
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "body",
-                Vec::new()
-            ))))),
-            body
+            Some(ElementKind::P),
+            template
+                .borrow()
+                .next_sibling()
+                .unwrap()
+                .borrow()
+                .element_kind()
         );
+    }
 
-        let p = body
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of body");
-
+    #[test]
+    fn test_template() {
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "p",
-                Vec::new()
-            ))))),
-            p
+            "<html><head><template><meta></template></head><body></body></html>",
+            parse_and_serialize("<template><meta></template>")
         );
-
-        let mut attr = Attribute::new();
-        attr.add_char('f', true);
-        attr.add_char('o', true);
-        attr.add_char('o', true);
-        attr.add_char('b', false);
-        attr.add_char('a', false);
-        attr.add_char('r', false);
-
-        let a = p
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of p");
-
+        // Table rows in a template aren't wrapped in <table>.
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
-                "a",
-                vec![attr]
-            ))))),
-            a
+            "<html><head></head><body><template><tr><td>a</td></tr></template></body></html>",
+            parse_and_serialize("<body><template><tr><td>a</template>")
         );
-
-        let text = a
-            .borrow()
-            .first_child()
-            .expect("failed to get first child of a");
-
         assert_eq!(
-            Rc::new(RefCell::new(Node::new(NodeKind::Text("test".to_string())))),
-            text
+            "<html><head></head><body><table><template><col></template></table></body></html>",
+            parse_and_serialize("<body><table><template><col>")
+        );
+        // Text in a table in a template is foster parented in the template contents.
+        assert_eq!(
+            "<html><head></head><body><template>a<table></table></template></body></html>",
+            parse_and_serialize("<body><template><table>a</table></template>")
         );
     }
+
+    #[test]
+    fn test_fragment_in_template_context() {
+        let nodes = parse_fragment("<td>a</td><td>b", "template");
+        assert_eq!(2, nodes.len());
+        assert_eq!(Some(ElementKind::Td), nodes[1].borrow().element_kind());
+    }
 }
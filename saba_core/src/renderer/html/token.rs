@@ -1,5 +1,17 @@
+use crate::encoding::decoder::Decoder;
+use crate::encoding::windows_1252_code_point;
+use crate::encoding::Encoding;
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::encoding_sniffer::sniff_encoding;
+use crate::renderer::html::encoding_sniffer::PRESCAN_LENGTH;
+use crate::renderer::html::entities;
+use crate::renderer::html::parse_error::ParseError;
+use crate::renderer::html::parse_error::ParseErrorKind;
+use crate::renderer::html::parse_error::Position;
+use crate::renderer::html::parse_error::Span;
+use alloc::collections::VecDeque;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -8,19 +20,279 @@ pub struct HtmlTokenizer {
     pos: usize,
     reconsume: bool,
     latest_token: Option<HtmlToken>,
+    /// The input that is not tokenized yet, from the character before the current one.
     input: Vec<char>,
+    /// The number of characters drained from the front of `input`. An index in `input` plus
+    /// this is the index in the whole input, which positions are computed from.
+    offset: usize,
     buf: String,
+    return_state: State,
+    char_ref_code: u32,
+    pending_tokens: VecDeque<HtmlToken>,
+    last_start_tag: String,
+    /// The indices in the whole input of the first character of each line, from the line of
+    /// the earliest character whose position may still be reported.
+    line_starts: Vec<usize>,
+    /// The number of lines drained from the front of `line_starts`.
+    drained_lines: usize,
+    errors: Vec<ParseError>,
+    /// False while more input can be pushed with `push_bytes()` or `push_str()`.
+    closed: bool,
+    /// Set when the input ends before the current character can be handled, e.g. "&am".
+    needs_more_input: bool,
+    /// Where the current character started to be handled. The tokenizer goes back here when
+    /// `needs_more_input` is set, and handles the character again once more input is pushed.
+    step: Step,
+    /// The index in the whole input of the first character of the token that the input
+    /// pushed so far ended in the middle of.
+    token_start: Option<usize>,
+    /// True if "<![CDATA[" starts a CDATA section rather than a bogus comment.
+    cdata_allowed: bool,
+    /// The bytes pushed before the encoding is determined.
+    undecoded: Vec<u8>,
+    /// The encoding given by the transport layer, e.g. the charset parameter of Content-Type.
+    transport_encoding: Option<Encoding>,
+    /// Created once the encoding of the bytes is determined.
+    decoder: Option<Decoder>,
+}
+
+/// The part of the tokenizer state that handling a character changes before it can tell that
+/// more input is needed. The rest, e.g. the token being built, is kept as it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Step {
+    pos: usize,
+    reconsume: bool,
+    errors_len: usize,
 }
 
 impl HtmlTokenizer {
     pub fn new(html: String) -> Self {
+        let input: Vec<char> = html.chars().collect();
+        let mut line_starts = vec![0];
+        for (i, c) in input.iter().enumerate() {
+            if *c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+
         Self {
             state: State::Data,
             pos: 0,
             reconsume: false,
             latest_token: None,
-            input: html.chars().collect(),
+            input,
+            offset: 0,
             buf: String::new(),
+            return_state: State::Data,
+            char_ref_code: 0,
+            pending_tokens: VecDeque::new(),
+            last_start_tag: String::new(),
+            line_starts,
+            drained_lines: 0,
+            errors: Vec::new(),
+            closed: true,
+            needs_more_input: false,
+            step: Step::default(),
+            token_start: None,
+            cdata_allowed: false,
+            undecoded: Vec::new(),
+            transport_encoding: None,
+            decoder: None,
+        }
+    }
+
+    /// Creates a tokenizer for an encoded document. The encoding is determined from the byte
+    /// order mark, `transport_encoding`, or a `<meta>` declaration in that order.
+    pub fn from_bytes(bytes: &[u8], transport_encoding: Option<Encoding>) -> Self {
+        let mut t = Self::new_streaming();
+        if let Some(encoding) = transport_encoding {
+            t.set_transport_encoding(encoding);
+        }
+        t.push_bytes(bytes);
+        t.close();
+        t
+    }
+
+    /// Creates a tokenizer whose input is pushed chunk by chunk. Tokens are returned as soon as
+    /// they are complete, and `next()` returns None until more input is pushed or `close()` is
+    /// called.
+    pub fn new_streaming() -> Self {
+        let mut t = Self::new(String::new());
+        t.closed = false;
+        t
+    }
+
+    /// Sets the encoding given by the transport layer. This must be called before any bytes
+    /// are pushed.
+    pub fn set_transport_encoding(&mut self, encoding: Encoding) {
+        self.transport_encoding = Some(encoding);
+    }
+
+    /// Returns the encoding of the bytes pushed, or None if it isn't determined yet.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.decoder.as_ref().map(|d| d.encoding())
+    }
+
+    /// Appends a chunk of encoded input. A character may be split across chunks, and invalid
+    /// bytes are replaced with U+FFFD. The bytes are held until enough of them are pushed to
+    /// determine the encoding.
+    pub fn push_bytes(&mut self, chunk: &[u8]) {
+        if let Some(decoder) = self.decoder.as_mut() {
+            let s = decoder.decode(chunk);
+            self.append_input(&s);
+            return;
+        }
+
+        self.undecoded.extend_from_slice(chunk);
+        // With a transport encoding, only the byte order mark needs to be checked.
+        let needed = match self.transport_encoding {
+            Some(_) => 3,
+            None => PRESCAN_LENGTH,
+        };
+        if self.undecoded.len() >= needed {
+            self.start_decoding();
+        }
+    }
+
+    fn start_decoding(&mut self) {
+        let bytes = core::mem::take(&mut self.undecoded);
+        let mut decoder = Decoder::new(sniff_encoding(&bytes, self.transport_encoding));
+        let s = decoder.decode(&bytes);
+        self.decoder = Some(decoder);
+        self.append_input(&s);
+    }
+
+    /// Appends a chunk of input.
+    pub fn push_str(&mut self, chunk: &str) {
+        // Bytes held to determine the encoding come before the chunk.
+        if self.decoder.is_none() && !self.undecoded.is_empty() {
+            self.start_decoding();
+        }
+        self.append_input(chunk);
+    }
+
+    fn append_input(&mut self, chunk: &str) {
+        self.drain_consumed_input();
+        for c in chunk.chars() {
+            self.input.push(c);
+            if c == '\n' {
+                self.line_starts.push(self.offset + self.input.len());
+            }
+        }
+    }
+
+    /// Drops the input before the current character, which is never looked at again, so that
+    /// a long stream doesn't stay in memory.
+    fn drain_consumed_input(&mut self) {
+        // The current character may be reconsumed.
+        let consumed = self.pos.saturating_sub(1);
+        // Draining only once half of the input is consumed keeps the cost of moving the rest
+        // linear in the length of the whole input.
+        if consumed == 0 || consumed < self.input.len() / 2 {
+            return;
+        }
+        self.input.drain(..consumed);
+        self.pos -= consumed;
+        self.offset += consumed;
+
+        // Keep the line of the start of the partial token, whose span is reported later.
+        let first = self.token_start.unwrap_or(self.offset);
+        let lines = self.line_starts.partition_point(|start| *start <= first) - 1;
+        self.line_starts.drain(..lines);
+        self.drained_lines += lines;
+    }
+
+    /// Marks the end of the input.
+    pub fn close(&mut self) {
+        if self.decoder.is_none() && !self.undecoded.is_empty() {
+            self.start_decoding();
+        }
+        if let Some(decoder) = self.decoder.as_mut() {
+            let s = decoder.finish();
+            self.append_input(&s);
+        }
+        self.closed = true;
+    }
+
+    /// Sets whether CDATA sections are allowed. The tree builder allows them when the adjusted
+    /// current node is an SVG or MathML element.
+    pub fn set_cdata_allowed(&mut self, allowed: bool) {
+        self.cdata_allowed = allowed;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Returns the next token with the range of the input it was created from.
+    pub fn next_with_span(&mut self) -> Option<SpannedToken> {
+        let start = self
+            .token_start
+            .unwrap_or(self.offset + self.next_input_index());
+        let start = self.position_at(start);
+        let token = self.next()?;
+        let end = self.position_at(self.offset + self.next_input_index());
+        Some(SpannedToken {
+            token,
+            span: Span::new(start, end),
+        })
+    }
+
+    /// Returns the parse errors found so far.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Returns the parse errors found so far and clears them.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        core::mem::take(&mut self.errors)
+    }
+
+    /// Returns the position of the character at `index` in the whole input.
+    fn position_at(&self, index: usize) -> Position {
+        let line = self.line_starts.partition_point(|start| *start <= index);
+        Position::new(
+            self.drained_lines + line,
+            index - self.line_starts[line - 1] + 1,
+        )
+    }
+
+    /// Returns the index of the character that is consumed next.
+    fn next_input_index(&self) -> usize {
+        let index = if self.reconsume {
+            self.pos - 1
+        } else {
+            self.pos
+        };
+        index.min(self.input.len())
+    }
+
+    /// Records a parse error at the current input character.
+    fn parse_error(&mut self, kind: ParseErrorKind) {
+        let index = self.pos.saturating_sub(1).min(self.input.len());
+        let position = self.position_at(self.offset + index);
+        self.errors.push(ParseError::new(kind, position));
+    }
+
+    /// Switches the tokenizer state. The tree builder uses this to tokenize the contents of
+    /// elements such as <title> and <style> as text.
+    pub fn switch_to(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// Sets the name of the last start tag, which decides which end tag closes the text in the
+    /// RCDATA, RAWTEXT and script data states.
+    pub fn set_last_start_tag(&mut self, tag: &str) {
+        self.last_start_tag = String::from(tag);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-end-tag-token
+    fn is_appropriate_end_tag(&self) -> bool {
+        match self.latest_token {
+            Some(HtmlToken::EndTag { ref tag }) => {
+                !self.last_start_tag.is_empty() && *tag == self.last_start_tag
+            }
+            _ => false,
         }
     }
 
@@ -28,8 +300,13 @@ impl HtmlTokenizer {
         self.pos > self.input.len()
     }
 
+    /// Returns '\0' once the input is exhausted. Callers distinguish it from a real NULL
+    /// character with `is_eof()`.
     fn consume_next_input(&mut self) -> char {
-        let c = self.input[self.pos];
+        if self.pos >= self.input.len() && !self.closed {
+            self.needs_more_input = true;
+        }
+        let c = self.input.get(self.pos).copied().unwrap_or('\0');
         self.pos += 1;
         c
     }
@@ -48,40 +325,66 @@ impl HtmlTokenizer {
 
     fn reconsume_input(&mut self) -> char {
         self.reconsume = false;
-        self.input[self.pos - 1]
+        self.input.get(self.pos - 1).copied().unwrap_or('\0')
     }
 
     fn append_tag_name(&mut self, c: char) {
-        assert!(self.latest_token.is_some());
-
-        if let Some(t) = self.latest_token.as_mut() {
-            match t {
-                HtmlToken::StartTag {
-                    ref mut tag,
-                    self_closing: _,
-                    attributes: _,
-                }
-                | HtmlToken::EndTag { ref mut tag } => {
-                    tag.push(c);
-                }
-                _ => panic!("`latest_token` should be either StartTag or EndTag."),
+        if let Some(
+            HtmlToken::StartTag {
+                ref mut tag,
+                self_closing: _,
+                attributes: _,
             }
+            | HtmlToken::EndTag { ref mut tag },
+        ) = self.latest_token.as_mut()
+        {
+            tag.push(c);
         }
     }
 
     fn take_latest_token(&mut self) -> Option<HtmlToken> {
-        assert!(self.latest_token.is_some());
-
-        let t = self.latest_token.as_ref().cloned();
-        self.latest_token = None;
-        assert!(self.latest_token.is_none());
+        let mut t = self.latest_token.take();
+
+        if let Some(HtmlToken::StartTag {
+            ref tag,
+            ref mut attributes,
+            ..
+        }) = t
+        {
+            // Only the first of the attributes with the same name is kept.
+            let mut i = 1;
+            while i < attributes.len() {
+                if attributes[..i]
+                    .iter()
+                    .any(|a| a.name() == attributes[i].name())
+                {
+                    attributes.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+            self.last_start_tag = tag.clone();
+        }
 
         t
     }
 
-    fn start_new_attribute(&mut self) {
-        assert!(self.latest_token.is_some());
+    /// Reports the attribute whose name was just consumed if the tag already has an attribute
+    /// with the same name. The attribute itself is dropped when the tag is emitted.
+    fn check_duplicate_attribute(&mut self) {
+        let is_duplicate = match self.latest_token {
+            Some(HtmlToken::StartTag { ref attributes, .. }) => match attributes.split_last() {
+                Some((last, rest)) => rest.iter().any(|a| a.name() == last.name()),
+                None => false,
+            },
+            _ => false,
+        };
+        if is_duplicate {
+            self.parse_error(ParseErrorKind::DuplicateAttribute);
+        }
+    }
 
+    fn start_new_attribute(&mut self) {
         if let Some(t) = self.latest_token.as_mut() {
             match t {
                 HtmlToken::StartTag {
@@ -91,14 +394,14 @@ impl HtmlTokenizer {
                 } => {
                     attributes.push(Attribute::new());
                 }
-                _ => panic!("`latest_token` should be StartTag."),
+                // Attributes and the self-closing flag on an end tag are dropped.
+                HtmlToken::EndTag { .. } => self.parse_error(ParseErrorKind::EndTagWithAttributes),
+                _ => {}
             }
         }
     }
 
     fn append_attribute(&mut self, c: char, is_name: bool) {
-        assert!(self.latest_token.is_some());
-
         if let Some(t) = self.latest_token.as_mut() {
             match t {
                 HtmlToken::StartTag {
@@ -106,19 +409,18 @@ impl HtmlTokenizer {
                     self_closing: _,
                     ref mut attributes,
                 } => {
-                    let len = attributes.len();
-                    assert!(len > 0);
-
-                    attributes[len - 1].add_char(c, is_name);
+                    if let Some(attribute) = attributes.last_mut() {
+                        attribute.add_char(c, is_name);
+                    }
                 }
-                _ => panic!("`latest_token` should be StartTag."),
+                // Attributes and the self-closing flag on an end tag are dropped.
+                HtmlToken::EndTag { .. } => {}
+                _ => {}
             }
         }
     }
 
     fn set_self_closing_flag(&mut self) {
-        assert!(self.latest_token.is_some());
-
         if let Some(t) = self.latest_token.as_mut() {
             match t {
                 HtmlToken::StartTag {
@@ -128,9 +430,216 @@ impl HtmlTokenizer {
                 } => {
                     *self_closing = true;
                 }
-                _ => panic!("`latest_token` should be StartTag."),
+                // Attributes and the self-closing flag on an end tag are dropped.
+                HtmlToken::EndTag { .. } => {
+                    self.parse_error(ParseErrorKind::EndTagWithTrailingSolidus)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Consumes `s` if the input starting from the current character matches it.
+    fn consume_if_next_chars_are(&mut self, s: &str, ascii_case_insensitive: bool) -> bool {
+        let start = self.pos - 1;
+        let len = s.chars().count();
+        if start + len > self.input.len() {
+            // The rest of the input may still match once more input is pushed.
+            let available = &self.input[start..];
+            if !self.closed
+                && available.iter().zip(s.chars()).all(|(a, b)| {
+                    if ascii_case_insensitive {
+                        a.eq_ignore_ascii_case(&b)
+                    } else {
+                        *a == b
+                    }
+                })
+            {
+                self.needs_more_input = true;
+            }
+            return false;
+        }
+
+        let matched = self.input[start..start + len]
+            .iter()
+            .zip(s.chars())
+            .all(|(a, b)| {
+                if ascii_case_insensitive {
+                    a.eq_ignore_ascii_case(&b)
+                } else {
+                    *a == b
+                }
+            });
+        if matched {
+            self.pos = start + len;
+        }
+        matched
+    }
+
+    fn create_comment(&mut self) {
+        self.latest_token = Some(HtmlToken::Comment(String::new()));
+    }
+
+    fn append_comment(&mut self, s: &str) {
+        if let Some(HtmlToken::Comment(ref mut data)) = self.latest_token.as_mut() {
+            data.push_str(s);
+        }
+    }
+
+    fn create_doctype(&mut self) {
+        self.latest_token = Some(HtmlToken::Doctype {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        });
+    }
+
+    fn append_doctype_name(&mut self, c: char) {
+        if let Some(HtmlToken::Doctype { ref mut name, .. }) = self.latest_token.as_mut() {
+            name.get_or_insert_with(String::new).push(c);
+        }
+    }
+
+    fn start_doctype_identifier(&mut self, is_public: bool) {
+        if let Some(HtmlToken::Doctype {
+            ref mut public_id,
+            ref mut system_id,
+            ..
+        }) = self.latest_token.as_mut()
+        {
+            if is_public {
+                *public_id = Some(String::new());
+            } else {
+                *system_id = Some(String::new());
+            }
+        }
+    }
+
+    fn append_doctype_identifier(&mut self, c: char, is_public: bool) {
+        if let Some(HtmlToken::Doctype {
+            ref mut public_id,
+            ref mut system_id,
+            ..
+        }) = self.latest_token.as_mut()
+        {
+            let id = if is_public { public_id } else { system_id };
+            id.get_or_insert_with(String::new).push(c);
+        }
+    }
+
+    fn set_force_quirks_flag(&mut self) {
+        if let Some(HtmlToken::Doctype {
+            ref mut force_quirks,
+            ..
+        }) = self.latest_token.as_mut()
+        {
+            *force_quirks = true;
+        }
+    }
+
+    /// Emits the current comment or DOCTYPE token followed by an end-of-file token.
+    fn emit_latest_token_and_eof(&mut self) -> Option<HtmlToken> {
+        match self.state {
+            State::BogusComment | State::BogusDoctype => {}
+            State::CommentStart
+            | State::CommentStartDash
+            | State::Comment
+            | State::CommentLessThanSign
+            | State::CommentLessThanSignBang
+            | State::CommentLessThanSignBangDash
+            | State::CommentLessThanSignBangDashDash
+            | State::CommentEndDash
+            | State::CommentEnd
+            | State::CommentEndBang => self.parse_error(ParseErrorKind::EofInComment),
+            _ => self.parse_error(ParseErrorKind::EofInDoctype),
+        }
+        self.pending_tokens.push_back(HtmlToken::Eof);
+        self.take_latest_token()
+    }
+
+    fn is_consumed_as_part_of_attribute(&self) -> bool {
+        matches!(
+            self.return_state,
+            State::AttributeValueDoubleQuoted
+                | State::AttributeValueSingleQuoted
+                | State::AttributeValueUnquoted
+        )
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#flush-code-points-consumed-as-a-character-reference
+    fn flush_code_points_consumed_as_character_reference(&mut self) {
+        let buf = core::mem::take(&mut self.buf);
+        for c in buf.chars() {
+            if self.is_consumed_as_part_of_attribute() {
+                self.append_attribute(c, /*is_name*/ false);
+            } else {
+                self.pending_tokens.push_back(HtmlToken::Char(c));
+            }
+        }
+    }
+
+    /// Returns the length of the longest named character reference starting at the current
+    /// input character, and the characters it refers to.
+    fn match_named_character_reference(&mut self) -> Option<(usize, &'static str)> {
+        let start = self.pos - 1;
+        let mut name = String::new();
+        let mut result = None;
+
+        for i in start..self.input.len() {
+            name.push(self.input[i]);
+            if !entities::has_prefix(&name) {
+                return result;
+            }
+            if let Some(chars) = entities::lookup(&name) {
+                result = Some((i + 1 - start, chars));
+            }
+        }
+
+        // A longer reference may follow in the next chunk.
+        if !self.closed {
+            self.needs_more_input = true;
+        }
+        result
+    }
+
+    fn append_char_ref_code(&mut self, digit: u32, radix: u32) {
+        // Clamp the code so that a long run of digits can't overflow. Anything above
+        // 0x10FFFF is replaced with U+FFFD anyway.
+        self.char_ref_code = (self.char_ref_code * radix + digit).min(0x110000);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+    fn numeric_character_reference_end(&mut self) {
+        let error = match self.char_ref_code {
+            0 => Some(ParseErrorKind::NullCharacterReference),
+            c if c > 0x10FFFF => Some(ParseErrorKind::CharacterReferenceOutsideUnicodeRange),
+            0xD800..=0xDFFF => Some(ParseErrorKind::SurrogateCharacterReference),
+            0xFDD0..=0xFDEF => Some(ParseErrorKind::NoncharacterCharacterReference),
+            c if c & 0xFFFE == 0xFFFE => Some(ParseErrorKind::NoncharacterCharacterReference),
+            0x0D | 0x80..=0x9F => Some(ParseErrorKind::ControlCharacterReference),
+            c if c < 0x20 && !matches!(c, 0x09 | 0x0A | 0x0C) => {
+                Some(ParseErrorKind::ControlCharacterReference)
             }
+            0x7F => Some(ParseErrorKind::ControlCharacterReference),
+            _ => None,
+        };
+        if let Some(kind) = error {
+            self.parse_error(kind);
         }
+
+        let code = match self.char_ref_code {
+            0 | 0xD800..=0xDFFF => 0xFFFD,
+            c if c > 0x10FFFF => 0xFFFD,
+            c @ 0x80..=0x9F => windows_1252_code_point(c as u8) as u32,
+            c => c,
+        };
+
+        self.buf.clear();
+        self.buf
+            .push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+        self.flush_code_points_consumed_as_character_reference();
+        self.state = self.return_state;
     }
 }
 
@@ -145,10 +654,17 @@ pub enum HtmlToken {
         tag: String,
     },
     Char(char),
+    Comment(String),
+    Doctype {
+        name: Option<String>,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        force_quirks: bool,
+    },
     Eof,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
     Data,
     TagOpen,
@@ -163,29 +679,163 @@ pub enum State {
     AttributeValueUnquoted,
     AfterAttributeValueQuoted,
     SelfClosingStartTag,
+    RcData,
+    RcDataLessThanSign,
+    RcDataEndTagOpen,
+    RcDataEndTagName,
+    RawText,
+    RawTextLessThanSign,
+    RawTextEndTagOpen,
+    RawTextEndTagName,
     ScriptData,
     ScriptDataLessThanSign,
     ScriptDataEndTagOpen,
     ScriptDataEndTagName,
-    TemporaryBuffer,
+    ScriptDataEscapeStart,
+    ScriptDataEscapeStartDash,
+    ScriptDataEscaped,
+    ScriptDataEscapedDash,
+    ScriptDataEscapedDashDash,
+    ScriptDataEscapedLessThanSign,
+    ScriptDataEscapedEndTagOpen,
+    ScriptDataEscapedEndTagName,
+    ScriptDataDoubleEscapeStart,
+    ScriptDataDoubleEscaped,
+    ScriptDataDoubleEscapedDash,
+    ScriptDataDoubleEscapedDashDash,
+    ScriptDataDoubleEscapedLessThanSign,
+    ScriptDataDoubleEscapeEnd,
+    PlainText,
+    CharacterReference,
+    NamedCharacterReference,
+    AmbiguousAmpersand,
+    NumericCharacterReference,
+    HexadecimalCharacterReferenceStart,
+    DecimalCharacterReferenceStart,
+    HexadecimalCharacterReference,
+    DecimalCharacterReference,
+    MarkupDeclarationOpen,
+    BogusComment,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentLessThanSign,
+    CommentLessThanSignBang,
+    CommentLessThanSignBangDash,
+    CommentLessThanSignBangDashDash,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypePublicKeyword,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierDoubleQuoted,
+    DoctypePublicIdentifierSingleQuoted,
+    AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
+    AfterDoctypeSystemKeyword,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierDoubleQuoted,
+    DoctypeSystemIdentifierSingleQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    CdataSection,
+    CdataSectionBracket,
+    CdataSectionEnd,
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | ' ')
+}
+
+/// Returns the state that tokenizes the text of an element such as <title> or <script> for
+/// one of the states handling "<" or an end tag in the text.
+fn text_state_of(state: State) -> State {
+    match state {
+        State::RcDataLessThanSign | State::RcDataEndTagOpen | State::RcDataEndTagName => {
+            State::RcData
+        }
+        State::RawTextLessThanSign | State::RawTextEndTagOpen | State::RawTextEndTagName => {
+            State::RawText
+        }
+        State::ScriptDataEscapedEndTagOpen | State::ScriptDataEscapedEndTagName => {
+            State::ScriptDataEscaped
+        }
+        _ => State::ScriptData,
+    }
+}
+
+/// A token and the range of the input it was created from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedToken {
+    pub token: HtmlToken,
+    pub span: Span,
 }
 
 impl Iterator for HtmlTokenizer {
     type Item = HtmlToken;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.input.len() {
+        if let Some(t) = self.pending_tokens.pop_front() {
+            return Some(t);
+        }
+
+        let start = self.next_input_index();
+        let token = self.next_token();
+        if self.needs_more_input {
+            // Go back to the character that couldn't be handled, but keep the token built so
+            // far, e.g. for "<di", so that it isn't tokenized again once more input is pushed.
+            self.needs_more_input = false;
+            self.pos = self.step.pos;
+            self.reconsume = self.step.reconsume;
+            self.errors.truncate(self.step.errors_len);
+            if self.token_start.is_none() && self.next_input_index() != start {
+                self.token_start = Some(self.offset + start);
+            }
+            return None;
+        }
+        if token.is_some() {
+            self.token_start = None;
+        }
+        token
+    }
+}
+
+impl HtmlTokenizer {
+    fn next_token(&mut self) -> Option<HtmlToken> {
+        if self.pos >= self.input.len() && !self.reconsume && self.token_start.is_none() {
             return None;
         }
 
         loop {
+            if let Some(t) = self.pending_tokens.pop_front() {
+                return Some(t);
+            }
+
+            self.step = Step {
+                pos: self.pos,
+                reconsume: self.reconsume,
+                errors_len: self.errors.len(),
+            };
             let c = match self.reconsume {
                 true => self.reconsume_input(),
                 false => self.consume_next_input(),
             };
+            if self.needs_more_input {
+                return None;
+            }
 
             match self.state {
                 State::Data => {
+                    if c == '&' {
+                        self.return_state = State::Data;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
+
                     if c == '<' {
                         self.state = State::TagOpen;
                         continue;
@@ -195,9 +845,18 @@ impl Iterator for HtmlTokenizer {
                         return Some(HtmlToken::Eof);
                     }
 
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                    }
+
                     return Some(HtmlToken::Char(c));
                 }
                 State::TagOpen => {
+                    if c == '!' {
+                        self.state = State::MarkupDeclarationOpen;
+                        continue;
+                    }
+
                     if c == '/' {
                         self.state = State::EndTagOpen;
                         continue;
@@ -210,16 +869,31 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '?' {
+                        self.parse_error(ParseErrorKind::UnexpectedQuestionMarkInsteadOfTagName);
+                        self.reconsume = true;
+                        self.state = State::BogusComment;
+                        self.create_comment();
+                        continue;
+                    }
+
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        self.parse_error(ParseErrorKind::EofBeforeTagName);
+                        self.pending_tokens.push_back(HtmlToken::Eof);
+                        return Some(HtmlToken::Char('<'));
                     }
 
+                    self.parse_error(ParseErrorKind::InvalidFirstCharacterOfTagName);
                     self.reconsume = true;
                     self.state = State::Data;
+                    return Some(HtmlToken::Char('<'));
                 }
                 State::EndTagOpen => {
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        self.parse_error(ParseErrorKind::EofBeforeTagName);
+                        self.pending_tokens.push_back(HtmlToken::Char('/'));
+                        self.pending_tokens.push_back(HtmlToken::Eof);
+                        return Some(HtmlToken::Char('<'));
                     }
 
                     if c.is_ascii_alphabetic() {
@@ -229,11 +903,19 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::MissingEndTagName);
+                        self.state = State::Data;
+                        continue;
+                    }
+
+                    self.parse_error(ParseErrorKind::InvalidFirstCharacterOfTagName);
                     self.reconsume = true;
-                    self.state = State::Data;
+                    self.state = State::BogusComment;
+                    self.create_comment();
                 }
                 State::TagName => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -254,30 +936,51 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInTag);
                         return Some(HtmlToken::Eof);
                     }
 
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_tag_name('\u{FFFD}');
+                        continue;
+                    }
+
                     self.append_tag_name(c);
                 }
                 State::BeforeAttributeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
                     if c == '/' || c == '>' || self.is_eof() {
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
                     }
 
+                    if c == '=' {
+                        self.parse_error(ParseErrorKind::UnexpectedEqualsSignBeforeAttributeName);
+                        self.start_new_attribute();
+                        self.append_attribute(c, /*is_name*/ true);
+                        self.state = State::AttributeName;
+                        continue;
+                    }
+
                     self.reconsume = true;
                     self.state = State::AttributeName;
                     self.start_new_attribute();
                 }
                 State::AttributeName => {
-                    if c == ' ' || c == '/' || c == '>' || self.is_eof() {
+                    if is_whitespace(c) || c == '/' || c == '>' || self.is_eof() {
+                        self.check_duplicate_attribute();
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
                     }
 
                     if c == '=' {
+                        self.check_duplicate_attribute();
                         self.state = State::BeforeAttributeValue;
                         continue;
                     }
@@ -287,10 +990,20 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_attribute('\u{FFFD}', /*is_name*/ true);
+                        continue;
+                    }
+
+                    if c == '"' || c == '\'' || c == '<' {
+                        self.parse_error(ParseErrorKind::UnexpectedCharacterInAttributeName);
+                    }
+
                     self.append_attribute(c, /*is_name*/ true);
                 }
                 State::AfterAttributeName => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         continue;
                     }
 
@@ -310,6 +1023,7 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInTag);
                         return Some(HtmlToken::Eof);
                     }
 
@@ -319,7 +1033,7 @@ impl Iterator for HtmlTokenizer {
                 }
 
                 State::BeforeAttributeValue => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         continue;
                     }
 
@@ -333,55 +1047,85 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::MissingAttributeValue);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
                     self.reconsume = true;
                     self.state = State::AttributeValueUnquoted;
                 }
-                State::AttributeValueDoubleQuoted => {
-                    if c == '"' {
+                State::AttributeValueDoubleQuoted | State::AttributeValueSingleQuoted => {
+                    let quote = match self.state {
+                        State::AttributeValueDoubleQuoted => '"',
+                        _ => '\'',
+                    };
+
+                    if c == quote {
                         self.state = State::AfterAttributeValueQuoted;
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
-
-                    self.append_attribute(c, /*is_name*/ false);
-                }
-
-                State::AttributeValueSingleQuoted => {
-                    if c == '\'' {
-                        self.state = State::AfterAttributeValueQuoted;
+                    if c == '&' {
+                        self.return_state = self.state;
+                        self.state = State::CharacterReference;
                         continue;
                     }
 
                     if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInTag);
                         return Some(HtmlToken::Eof);
                     }
 
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_attribute('\u{FFFD}', /*is_name*/ false);
+                        continue;
+                    }
+
                     self.append_attribute(c, /*is_name*/ false);
                 }
 
                 State::AttributeValueUnquoted => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
 
+                    if c == '&' {
+                        self.return_state = State::AttributeValueUnquoted;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
+
                     if c == '>' {
                         self.state = State::Data;
                         return self.take_latest_token();
                     }
 
                     if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInTag);
                         return Some(HtmlToken::Eof);
                     }
 
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_attribute('\u{FFFD}', /*is_name*/ false);
+                        continue;
+                    }
+
+                    if matches!(c, '"' | '\'' | '<' | '=' | '`') {
+                        self.parse_error(
+                            ParseErrorKind::UnexpectedCharacterInUnquotedAttributeValue,
+                        );
+                    }
+
                     self.append_attribute(c, /*is_name*/ false);
                 }
 
                 State::AfterAttributeValueQuoted => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -397,11 +1141,14 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInTag);
                         return Some(HtmlToken::Eof);
                     }
 
+                    // e.g. <p a='b'c='d'> has two attributes.
+                    self.parse_error(ParseErrorKind::MissingWhitespaceBetweenAttributes);
                     self.reconsume = true;
-                    self.state = State::BeforeAttributeValue;
+                    self.state = State::BeforeAttributeName;
                 }
 
                 State::SelfClosingStartTag => {
@@ -412,13 +1159,28 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInTag);
                         return Some(HtmlToken::Eof);
                     }
+
+                    self.parse_error(ParseErrorKind::UnexpectedSolidusInTag);
+                    self.reconsume = true;
+                    self.state = State::BeforeAttributeName;
                 }
 
-                State::ScriptData => {
-                    if c == '<' {
-                        self.state = State::ScriptDataLessThanSign;
+                State::RcData | State::RawText | State::ScriptData | State::PlainText => {
+                    if c == '&' && self.state == State::RcData {
+                        self.return_state = State::RcData;
+                        self.state = State::CharacterReference;
+                        continue;
+                    }
+
+                    if c == '<' && self.state != State::PlainText {
+                        self.state = match self.state {
+                            State::RcData => State::RcDataLessThanSign,
+                            State::RawText => State::RawTextLessThanSign,
+                            _ => State::ScriptDataLessThanSign,
+                        };
                         continue;
                     }
 
@@ -426,9 +1188,30 @@ impl Iterator for HtmlTokenizer {
                         return Some(HtmlToken::Eof);
                     }
 
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
+                    }
+
                     return Some(HtmlToken::Char(c));
                 }
 
+                State::RcDataLessThanSign | State::RawTextLessThanSign => {
+                    let text_state = text_state_of(self.state);
+                    if c == '/' {
+                        self.buf.clear();
+                        self.state = match text_state {
+                            State::RcData => State::RcDataEndTagOpen,
+                            _ => State::RawTextEndTagOpen,
+                        };
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = text_state;
+                    return Some(HtmlToken::Char('<'));
+                }
+
                 State::ScriptDataLessThanSign => {
                     if c == '/' {
                         self.buf.clear();
@@ -436,58 +1219,934 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '!' {
+                        self.state = State::ScriptDataEscapeStart;
+                        self.pending_tokens.push_back(HtmlToken::Char('!'));
+                        return Some(HtmlToken::Char('<'));
+                    }
+
                     self.reconsume = true;
                     self.state = State::ScriptData;
                     return Some(HtmlToken::Char('<'));
                 }
 
-                State::ScriptDataEndTagOpen => {
+                State::RcDataEndTagOpen
+                | State::RawTextEndTagOpen
+                | State::ScriptDataEndTagOpen
+                | State::ScriptDataEscapedEndTagOpen => {
+                    let text_state = text_state_of(self.state);
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
-                        self.state = State::ScriptDataEndTagName;
+                        self.state = match text_state {
+                            State::RcData => State::RcDataEndTagName,
+                            State::RawText => State::RawTextEndTagName,
+                            State::ScriptData => State::ScriptDataEndTagName,
+                            _ => State::ScriptDataEscapedEndTagName,
+                        };
                         self.create_tag(false);
                         continue;
                     }
 
                     self.reconsume = true;
-                    self.state = State::ScriptData;
+                    self.state = text_state;
+                    self.pending_tokens.push_back(HtmlToken::Char('/'));
                     return Some(HtmlToken::Char('<'));
                 }
 
-                State::ScriptDataEndTagName => {
-                    if c == '>' {
-                        self.state = State::ScriptData;
-                        return self.take_latest_token();
+                State::RcDataEndTagName
+                | State::RawTextEndTagName
+                | State::ScriptDataEndTagName
+                | State::ScriptDataEscapedEndTagName => {
+                    if self.is_appropriate_end_tag() {
+                        if is_whitespace(c) {
+                            self.state = State::BeforeAttributeName;
+                            continue;
+                        }
+
+                        if c == '/' {
+                            self.state = State::SelfClosingStartTag;
+                            continue;
+                        }
+
+                        if c == '>' {
+                            self.state = State::Data;
+                            return self.take_latest_token();
+                        }
                     }
 
                     if c.is_ascii_alphabetic() {
-                        self.buf.push(c);
                         self.append_tag_name(c.to_ascii_lowercase());
+                        self.buf.push(c);
                         continue;
                     }
 
-                    self.state = State::TemporaryBuffer;
-                    self.buf = String::from("</") + &self.buf;
-                    self.buf.push(c);
-                    continue;
-                }
+                    // Not an end tag of the current element, so the characters are text.
+                    self.latest_token = None;
+                    self.pending_tokens.push_back(HtmlToken::Char('/'));
+                    for c in self.buf.chars() {
+                        self.pending_tokens.push_back(HtmlToken::Char(c));
+                    }
+                    self.reconsume = true;
+                    self.state = text_state_of(self.state);
+                    return Some(HtmlToken::Char('<'));
+                }
+
+                State::ScriptDataEscapeStart | State::ScriptDataEscapeStartDash => {
+                    if c == '-' {
+                        self.state = match self.state {
+                            State::ScriptDataEscapeStart => State::ScriptDataEscapeStartDash,
+                            _ => State::ScriptDataEscapedDashDash,
+                        };
+                        return Some(HtmlToken::Char('-'));
+                    }
 
-                State::TemporaryBuffer => {
                     self.reconsume = true;
+                    self.state = State::ScriptData;
+                }
 
-                    if self.buf.chars().count() == 0 {
+                State::ScriptDataEscaped
+                | State::ScriptDataEscapedDash
+                | State::ScriptDataEscapedDashDash
+                | State::ScriptDataDoubleEscaped
+                | State::ScriptDataDoubleEscapedDash
+                | State::ScriptDataDoubleEscapedDashDash => {
+                    let double_escaped = matches!(
+                        self.state,
+                        State::ScriptDataDoubleEscaped
+                            | State::ScriptDataDoubleEscapedDash
+                            | State::ScriptDataDoubleEscapedDashDash
+                    );
+                    let (escaped, dash, dash_dash, less_than_sign) = if double_escaped {
+                        (
+                            State::ScriptDataDoubleEscaped,
+                            State::ScriptDataDoubleEscapedDash,
+                            State::ScriptDataDoubleEscapedDashDash,
+                            State::ScriptDataDoubleEscapedLessThanSign,
+                        )
+                    } else {
+                        (
+                            State::ScriptDataEscaped,
+                            State::ScriptDataEscapedDash,
+                            State::ScriptDataEscapedDashDash,
+                            State::ScriptDataEscapedLessThanSign,
+                        )
+                    };
+
+                    if c == '-' {
+                        self.state = if self.state == escaped {
+                            dash
+                        } else {
+                            dash_dash
+                        };
+                        return Some(HtmlToken::Char('-'));
+                    }
+
+                    if c == '<' {
+                        self.state = less_than_sign;
+                        if double_escaped {
+                            return Some(HtmlToken::Char('<'));
+                        }
+                        continue;
+                    }
+
+                    if c == '>' && self.state == dash_dash {
                         self.state = State::ScriptData;
+                        return Some(HtmlToken::Char('>'));
+                    }
+
+                    if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInScriptHtmlCommentLikeText);
+                        return Some(HtmlToken::Eof);
+                    }
+
+                    self.state = escaped;
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
+                    }
+                    return Some(HtmlToken::Char(c));
+                }
+
+                State::ScriptDataEscapedLessThanSign => {
+                    if c == '/' {
+                        self.buf.clear();
+                        self.state = State::ScriptDataEscapedEndTagOpen;
+                        continue;
+                    }
+
+                    if c.is_ascii_alphabetic() {
+                        self.buf.clear();
+                        self.reconsume = true;
+                        self.state = State::ScriptDataDoubleEscapeStart;
+                        return Some(HtmlToken::Char('<'));
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::ScriptDataEscaped;
+                    return Some(HtmlToken::Char('<'));
+                }
+
+                State::ScriptDataDoubleEscapeStart | State::ScriptDataDoubleEscapeEnd => {
+                    // <script> in an escaped script starts a double escaped section, which
+                    // ends at </script>.
+                    let (matched_state, unmatched_state) =
+                        if self.state == State::ScriptDataDoubleEscapeStart {
+                            (State::ScriptDataDoubleEscaped, State::ScriptDataEscaped)
+                        } else {
+                            (State::ScriptDataEscaped, State::ScriptDataDoubleEscaped)
+                        };
+
+                    if is_whitespace(c) || c == '/' || c == '>' {
+                        self.state = if self.buf == "script" {
+                            matched_state
+                        } else {
+                            unmatched_state
+                        };
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c.is_ascii_alphabetic() {
+                        self.buf.push(c.to_ascii_lowercase());
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    self.reconsume = true;
+                    self.state = unmatched_state;
+                }
+
+                State::ScriptDataDoubleEscapedLessThanSign => {
+                    if c == '/' {
+                        self.buf.clear();
+                        self.state = State::ScriptDataDoubleEscapeEnd;
+                        return Some(HtmlToken::Char('/'));
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::ScriptDataDoubleEscaped;
+                }
+
+                State::CharacterReference => {
+                    self.buf = String::from("&");
+
+                    if c.is_ascii_alphanumeric() {
+                        self.reconsume = true;
+                        self.state = State::NamedCharacterReference;
+                        continue;
+                    }
+
+                    if c == '#' {
+                        self.buf.push(c);
+                        self.state = State::NumericCharacterReference;
                         continue;
                     }
 
-                    let c = self
-                        .buf
-                        .chars()
-                        .nth(0)
-                        .expect("self.buf should have at least one character.");
-                    self.buf.remove(0);
+                    self.flush_code_points_consumed_as_character_reference();
+                    self.reconsume = true;
+                    self.state = self.return_state;
+                }
+
+                State::NamedCharacterReference => {
+                    let m = self.match_named_character_reference();
+                    if self.needs_more_input {
+                        return None;
+                    }
+                    let (len, chars) = match m {
+                        Some(m) => m,
+                        None => {
+                            self.flush_code_points_consumed_as_character_reference();
+                            self.reconsume = true;
+                            self.state = State::AmbiguousAmpersand;
+                            continue;
+                        }
+                    };
+
+                    let start = self.pos - 1;
+                    let name: String = self.input[start..start + len].iter().collect();
+                    self.pos = start + len;
+
+                    // For historical reasons, a reference without a semicolon in an attribute
+                    // value is kept as is if it's followed by "=" or an alphanumeric character.
+                    // e.g. <a href="?a=1&copy=2">
+                    let next_is_alphanumeric_or_equals = match self.input.get(self.pos) {
+                        Some(n) => *n == '=' || n.is_ascii_alphanumeric(),
+                        None if !self.closed => {
+                            self.needs_more_input = true;
+                            return None;
+                        }
+                        None => false,
+                    };
+                    if self.is_consumed_as_part_of_attribute()
+                        && !name.ends_with(';')
+                        && next_is_alphanumeric_or_equals
+                    {
+                        self.buf.push_str(&name);
+                    } else {
+                        if !name.ends_with(';') {
+                            self.parse_error(
+                                ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                            );
+                        }
+                        self.buf = String::from(chars);
+                    }
+
+                    self.flush_code_points_consumed_as_character_reference();
+                    self.state = self.return_state;
+                }
+
+                State::AmbiguousAmpersand => {
+                    if c.is_ascii_alphanumeric() {
+                        if self.is_consumed_as_part_of_attribute() {
+                            self.append_attribute(c, /*is_name*/ false);
+                            continue;
+                        }
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c == ';' {
+                        self.parse_error(ParseErrorKind::UnknownNamedCharacterReference);
+                    }
+
+                    self.reconsume = true;
+                    self.state = self.return_state;
+                }
+
+                State::NumericCharacterReference => {
+                    self.char_ref_code = 0;
+
+                    if c == 'x' || c == 'X' {
+                        self.buf.push(c);
+                        self.state = State::HexadecimalCharacterReferenceStart;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::DecimalCharacterReferenceStart;
+                }
+
+                State::HexadecimalCharacterReferenceStart => {
+                    if c.is_ascii_hexdigit() {
+                        self.reconsume = true;
+                        self.state = State::HexadecimalCharacterReference;
+                        continue;
+                    }
+
+                    self.parse_error(ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference);
+                    self.flush_code_points_consumed_as_character_reference();
+                    self.reconsume = true;
+                    self.state = self.return_state;
+                }
+
+                State::DecimalCharacterReferenceStart => {
+                    if c.is_ascii_digit() {
+                        self.reconsume = true;
+                        self.state = State::DecimalCharacterReference;
+                        continue;
+                    }
+
+                    self.parse_error(ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference);
+                    self.flush_code_points_consumed_as_character_reference();
+                    self.reconsume = true;
+                    self.state = self.return_state;
+                }
+
+                State::HexadecimalCharacterReference => {
+                    if let Some(digit) = c.to_digit(16) {
+                        self.append_char_ref_code(digit, 16);
+                        continue;
+                    }
+
+                    if c != ';' {
+                        self.parse_error(ParseErrorKind::MissingSemicolonAfterCharacterReference);
+                        self.reconsume = true;
+                    }
+                    self.numeric_character_reference_end();
+                }
+
+                State::DecimalCharacterReference => {
+                    if let Some(digit) = c.to_digit(10) {
+                        self.append_char_ref_code(digit, 10);
+                        continue;
+                    }
+
+                    if c != ';' {
+                        self.parse_error(ParseErrorKind::MissingSemicolonAfterCharacterReference);
+                        self.reconsume = true;
+                    }
+                    self.numeric_character_reference_end();
+                }
+
+                State::MarkupDeclarationOpen => {
+                    if self.consume_if_next_chars_are("--", false) {
+                        self.state = State::CommentStart;
+                        self.create_comment();
+                        continue;
+                    }
+
+                    if self.consume_if_next_chars_are("DOCTYPE", true) {
+                        self.state = State::Doctype;
+                        continue;
+                    }
+
+                    if self.consume_if_next_chars_are("[CDATA[", false) {
+                        if self.cdata_allowed {
+                            self.state = State::CdataSection;
+                            continue;
+                        }
+                        self.parse_error(ParseErrorKind::CdataInHtmlContent);
+                        self.state = State::BogusComment;
+                        self.create_comment();
+                        self.append_comment("[CDATA[");
+                        continue;
+                    }
+
+                    // The input pushed so far may end in the middle of one of them.
+                    if self.needs_more_input {
+                        return None;
+                    }
+
+                    self.parse_error(ParseErrorKind::IncorrectlyOpenedComment);
+                    self.reconsume = true;
+                    self.state = State::BogusComment;
+                    self.create_comment();
+                }
+
+                State::BogusComment => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_comment("\u{FFFD}");
+                        continue;
+                    }
+
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+
+                State::CommentStart => {
+                    if c == '-' {
+                        self.state = State::CommentStartDash;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+
+                State::CommentStartDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+
+                State::Comment => {
+                    if c == '<' {
+                        self.append_comment("<");
+                        self.state = State::CommentLessThanSign;
+                        continue;
+                    }
+
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_comment("\u{FFFD}");
+                        continue;
+                    }
+
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+
+                State::CommentLessThanSign => {
+                    if c == '!' {
+                        self.append_comment("!");
+                        self.state = State::CommentLessThanSignBang;
+                        continue;
+                    }
+
+                    if c == '<' {
+                        self.append_comment("<");
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+
+                State::CommentLessThanSignBang => {
+                    if c == '-' {
+                        self.state = State::CommentLessThanSignBangDash;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+
+                State::CommentLessThanSignBangDash => {
+                    if c == '-' {
+                        self.state = State::CommentLessThanSignBangDashDash;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::CommentEndDash;
+                }
+
+                State::CommentLessThanSignBangDashDash => {
+                    // A nested comment ("<!--") is a parse error but is otherwise handled in the
+                    // same way as "-->".
+                    if c != '>' && !self.is_eof() {
+                        self.parse_error(ParseErrorKind::NestedComment);
+                    }
+                    self.reconsume = true;
+                    self.state = State::CommentEnd;
+                }
+
+                State::CommentEndDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+
+                State::CommentEnd => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '!' {
+                        self.state = State::CommentEndBang;
+                        continue;
+                    }
+
+                    if c == '-' {
+                        self.append_comment("-");
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.append_comment("--");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+
+                State::CommentEndBang => {
+                    if c == '-' {
+                        self.append_comment("--!");
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::IncorrectlyClosedComment);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.append_comment("--!");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+
+                State::Doctype => {
+                    if is_whitespace(c) {
+                        self.state = State::BeforeDoctypeName;
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        self.create_doctype();
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.parse_error(ParseErrorKind::MissingWhitespaceBeforeDoctypeName);
+                    self.reconsume = true;
+                    self.state = State::BeforeDoctypeName;
+                }
+
+                State::BeforeDoctypeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::MissingDoctypeName);
+                        self.create_doctype();
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        self.create_doctype();
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.create_doctype();
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_doctype_name('\u{FFFD}');
+                    } else {
+                        self.append_doctype_name(c.to_ascii_lowercase());
+                    }
+                    self.state = State::DoctypeName;
+                }
+
+                State::DoctypeName => {
+                    if is_whitespace(c) {
+                        self.state = State::AfterDoctypeName;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_doctype_name('\u{FFFD}');
+                        continue;
+                    }
+
+                    self.append_doctype_name(c.to_ascii_lowercase());
+                }
+
+                State::AfterDoctypeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    if self.consume_if_next_chars_are("PUBLIC", true) {
+                        self.state = State::AfterDoctypePublicKeyword;
+                        continue;
+                    }
+
+                    if self.consume_if_next_chars_are("SYSTEM", true) {
+                        self.state = State::AfterDoctypeSystemKeyword;
+                        continue;
+                    }
+
+                    if self.needs_more_input {
+                        return None;
+                    }
+
+                    self.parse_error(ParseErrorKind::InvalidCharacterSequenceAfterDoctypeName);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+
+                State::AfterDoctypePublicKeyword | State::BeforeDoctypePublicIdentifier => {
+                    if is_whitespace(c) {
+                        if self.state == State::AfterDoctypePublicKeyword {
+                            self.state = State::BeforeDoctypePublicIdentifier;
+                        }
+                        continue;
+                    }
+
+                    if c == '"' || c == '\'' {
+                        if self.state == State::AfterDoctypePublicKeyword {
+                            self.parse_error(
+                                ParseErrorKind::MissingWhitespaceAfterDoctypePublicKeyword,
+                            );
+                        }
+                        self.start_doctype_identifier(/*is_public*/ true);
+                        self.state = if c == '"' {
+                            State::DoctypePublicIdentifierDoubleQuoted
+                        } else {
+                            State::DoctypePublicIdentifierSingleQuoted
+                        };
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::MissingDoctypePublicIdentifier);
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.parse_error(ParseErrorKind::MissingQuoteBeforeDoctypePublicIdentifier);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+
+                State::DoctypePublicIdentifierDoubleQuoted
+                | State::DoctypePublicIdentifierSingleQuoted
+                | State::DoctypeSystemIdentifierDoubleQuoted
+                | State::DoctypeSystemIdentifierSingleQuoted => {
+                    let (quote, is_public, next_state) = match self.state {
+                        State::DoctypePublicIdentifierDoubleQuoted => {
+                            ('"', true, State::AfterDoctypePublicIdentifier)
+                        }
+                        State::DoctypePublicIdentifierSingleQuoted => {
+                            ('\'', true, State::AfterDoctypePublicIdentifier)
+                        }
+                        State::DoctypeSystemIdentifierDoubleQuoted => {
+                            ('"', false, State::AfterDoctypeSystemIdentifier)
+                        }
+                        _ => ('\'', false, State::AfterDoctypeSystemIdentifier),
+                    };
+
+                    if c == quote {
+                        self.state = next_state;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(if is_public {
+                            ParseErrorKind::AbruptDoctypePublicIdentifier
+                        } else {
+                            ParseErrorKind::AbruptDoctypeSystemIdentifier
+                        });
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_doctype_identifier('\u{FFFD}', is_public);
+                        continue;
+                    }
+
+                    self.append_doctype_identifier(c, is_public);
+                }
+
+                State::AfterDoctypePublicIdentifier
+                | State::BetweenDoctypePublicAndSystemIdentifiers => {
+                    if is_whitespace(c) {
+                        self.state = State::BetweenDoctypePublicAndSystemIdentifiers;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '"' || c == '\'' {
+                        if self.state == State::AfterDoctypePublicIdentifier {
+                            self.parse_error(
+                                ParseErrorKind::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+                            );
+                        }
+                        self.start_doctype_identifier(/*is_public*/ false);
+                        self.state = if c == '"' {
+                            State::DoctypeSystemIdentifierDoubleQuoted
+                        } else {
+                            State::DoctypeSystemIdentifierSingleQuoted
+                        };
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.parse_error(ParseErrorKind::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+
+                State::AfterDoctypeSystemKeyword | State::BeforeDoctypeSystemIdentifier => {
+                    if is_whitespace(c) {
+                        if self.state == State::AfterDoctypeSystemKeyword {
+                            self.state = State::BeforeDoctypeSystemIdentifier;
+                        }
+                        continue;
+                    }
+
+                    if c == '"' || c == '\'' {
+                        if self.state == State::AfterDoctypeSystemKeyword {
+                            self.parse_error(
+                                ParseErrorKind::MissingWhitespaceAfterDoctypeSystemKeyword,
+                            );
+                        }
+                        self.start_doctype_identifier(/*is_public*/ false);
+                        self.state = if c == '"' {
+                            State::DoctypeSystemIdentifierDoubleQuoted
+                        } else {
+                            State::DoctypeSystemIdentifierSingleQuoted
+                        };
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(ParseErrorKind::MissingDoctypeSystemIdentifier);
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    self.parse_error(ParseErrorKind::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+
+                State::AfterDoctypeSystemIdentifier => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        self.set_force_quirks_flag();
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    // Unlike the other DOCTYPE states, this doesn't set the force-quirks flag.
+                    self.parse_error(
+                        ParseErrorKind::UnexpectedCharacterAfterDoctypeSystemIdentifier,
+                    );
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+
+                State::BogusDoctype => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_latest_token_and_eof();
+                    }
+
+                    if c == '\0' {
+                        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+                    }
+                }
+                State::CdataSection => {
+                    if c == ']' {
+                        self.state = State::CdataSectionBracket;
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        self.parse_error(ParseErrorKind::EofInCdata);
+                        return Some(HtmlToken::Eof);
+                    }
+
                     return Some(HtmlToken::Char(c));
                 }
+                State::CdataSectionBracket => {
+                    if c == ']' {
+                        self.state = State::CdataSectionEnd;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::CdataSection;
+                    return Some(HtmlToken::Char(']'));
+                }
+                State::CdataSectionEnd => {
+                    if c == ']' {
+                        return Some(HtmlToken::Char(']'));
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::CdataSection;
+                    self.pending_tokens.push_back(HtmlToken::Char(']'));
+                    return Some(HtmlToken::Char(']'));
+                }
             }
         }
     }
@@ -605,4 +2264,323 @@ mod tests {
             assert_eq!(Some(e), tokenizer.next());
         }
     }
+
+    #[test]
+    fn test_rcdata() {
+        let mut tokenizer = HtmlTokenizer::new("<b>&amp;</bar></title>x".to_string());
+        tokenizer.switch_to(State::RcData);
+        tokenizer.set_last_start_tag("title");
+        let mut expected: Vec<HtmlToken> = "<b>&</bar>".chars().map(HtmlToken::Char).collect();
+        expected.push(HtmlToken::EndTag {
+            tag: "title".to_string(),
+        });
+        expected.push(HtmlToken::Char('x'));
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_cdata_section() {
+        let html = "<![CDATA[a]b]]c]]]>d";
+        let mut tokenizer = HtmlTokenizer::new(html.to_string());
+        tokenizer.set_cdata_allowed(true);
+        assert_eq!("a]b]]c]d", collect_chars(tokenizer));
+
+        // CDATA sections are bogus comments in HTML content.
+        let mut tokenizer = HtmlTokenizer::new(html.to_string());
+        assert_eq!(
+            Some(HtmlToken::Comment("[CDATA[a]b]]c]]]".to_string())),
+            tokenizer.next()
+        );
+        assert_eq!(
+            ParseErrorKind::CdataInHtmlContent,
+            tokenizer.errors()[0].kind
+        );
+
+        let mut tokenizer = HtmlTokenizer::new("<![CDATA[a]".to_string());
+        tokenizer.set_cdata_allowed(true);
+        assert_eq!(Some(HtmlToken::Char('a')), tokenizer.next());
+        assert_eq!(Some(HtmlToken::Char(']')), tokenizer.next());
+        assert_eq!(Some(HtmlToken::Eof), tokenizer.next());
+        assert_eq!(ParseErrorKind::EofInCdata, tokenizer.errors()[0].kind);
+    }
+
+    #[test]
+    fn test_rawtext_and_plaintext() {
+        let mut tokenizer = HtmlTokenizer::new("a&amp;<!--</style>".to_string());
+        tokenizer.switch_to(State::RawText);
+        tokenizer.set_last_start_tag("style");
+        assert_eq!("a&amp;<!--", collect_chars(tokenizer));
+
+        let mut tokenizer = HtmlTokenizer::new("</plaintext>&amp;".to_string());
+        tokenizer.switch_to(State::PlainText);
+        assert_eq!("</plaintext>&amp;", collect_chars(tokenizer));
+    }
+
+    #[test]
+    fn test_spans() {
+        let html = "<p a=b>\nx</p>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let spans = [
+            Span::new(Position::new(1, 1), Position::new(1, 8)),
+            Span::new(Position::new(1, 8), Position::new(2, 1)),
+            Span::new(Position::new(2, 1), Position::new(2, 2)),
+            Span::new(Position::new(2, 2), Position::new(2, 6)),
+        ];
+        for span in spans {
+            assert_eq!(Some(span), tokenizer.next_with_span().map(|t| t.span));
+        }
+        assert_eq!(None, tokenizer.next_with_span());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let html = "<p a=1 a=2 b='c'd>\n</p x><div".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        match tokenizer.next() {
+            Some(HtmlToken::StartTag { attributes, .. }) => {
+                let attributes: Vec<(String, String)> =
+                    attributes.iter().map(|a| (a.name(), a.value())).collect();
+                assert_eq!(
+                    vec![
+                        ("a".to_string(), "1".to_string()),
+                        ("b".to_string(), "c".to_string()),
+                        ("d".to_string(), "".to_string()),
+                    ],
+                    attributes
+                );
+            }
+            t => panic!("expected a start tag but got {:?}", t),
+        }
+        while tokenizer.next().is_some() {}
+
+        let errors: Vec<(&str, usize, usize)> = tokenizer
+            .errors()
+            .iter()
+            .map(|e| (e.kind.name(), e.position.line, e.position.column))
+            .collect();
+        assert_eq!(
+            vec![
+                ("duplicate-attribute", 1, 9),
+                ("missing-whitespace-between-attributes", 1, 17),
+                ("end-tag-with-attributes", 2, 5),
+                ("eof-in-tag", 2, 11),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        let html = "<!DOCTYPE html><p class='a&amp;b'>caf\u{e9} &notin; &#x41;</p><!-- c -->";
+        let expected: Vec<HtmlToken> = HtmlTokenizer::new(html.to_string()).collect();
+
+        // The result doesn't depend on where the input is split, even in the middle of a
+        // multi-byte character.
+        let bytes = html.as_bytes();
+        for i in 0..=bytes.len() {
+            let mut tokenizer = HtmlTokenizer::new_streaming();
+            let mut tokens = Vec::new();
+            tokenizer.push_bytes(&bytes[..i]);
+            tokens.extend(tokenizer.by_ref());
+            tokenizer.push_bytes(&bytes[i..]);
+            tokens.extend(tokenizer.by_ref());
+            tokenizer.close();
+            tokens.extend(tokenizer.by_ref());
+            assert_eq!(expected, tokens, "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn test_streaming_one_char_at_a_time() {
+        let html = "<!DOCTYPE html PUBLIC \"x\">\n<p title='a&copy=b &amp c'>&notin;&#x41;</p>\n\
+                    <!-- a\nb --><!x><![CDATA[y]]>";
+        let mut tokenizer = HtmlTokenizer::new(html.to_string());
+        let expected: Vec<SpannedToken> =
+            core::iter::from_fn(|| tokenizer.next_with_span()).collect();
+        let expected_errors = tokenizer.take_errors();
+
+        // A token split across chunks is resumed rather than tokenized again, and the result
+        // is the same, including the spans and the errors.
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        let mut tokens = Vec::new();
+        for c in html.chars() {
+            tokenizer.push_str(c.encode_utf8(&mut [0; 4]));
+            tokens.extend(core::iter::from_fn(|| tokenizer.next_with_span()));
+        }
+        tokenizer.close();
+        tokens.extend(core::iter::from_fn(|| tokenizer.next_with_span()));
+        assert_eq!(expected, tokens);
+        assert_eq!(expected_errors, tokenizer.take_errors());
+    }
+
+    #[test]
+    fn test_streaming_drains_consumed_input() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.push_str("<!--");
+        for _ in 0..1000 {
+            tokenizer.push_str("a\n");
+            assert_eq!(None, tokenizer.next());
+        }
+        // Only the end of the input is kept while the comment is tokenized.
+        assert!(tokenizer.input.len() < 8);
+
+        tokenizer.push_str("-->");
+        let token = tokenizer.next_with_span().expect("failed to get a comment");
+        assert_eq!(HtmlToken::Comment("a\n".repeat(1000)), token.token);
+        assert_eq!(
+            Span::new(Position::new(1, 1), Position::new(1001, 4)),
+            token.span
+        );
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let bytes = b"<meta charset=shift_jis><p>\x93\xFA\x96\x7B</p>";
+        let tokenizer = HtmlTokenizer::from_bytes(bytes, None);
+        assert_eq!(Some(Encoding::ShiftJis), tokenizer.encoding());
+        assert_eq!("\u{65E5}\u{672C}", collect_chars(tokenizer));
+
+        // The transport encoding wins over <meta>.
+        let bytes = b"<meta charset=shift_jis><p>\xC6\xFC\xCB\xDC</p>";
+        let tokenizer = HtmlTokenizer::from_bytes(bytes, Some(Encoding::EucJp));
+        assert_eq!(Some(Encoding::EucJp), tokenizer.encoding());
+        assert_eq!("\u{65E5}\u{672C}", collect_chars(tokenizer));
+
+        // Without any declaration, bytes that aren't UTF-8 are decoded as windows-1252.
+        let tokenizer = HtmlTokenizer::from_bytes(b"caf\xE9 \x80", None);
+        assert_eq!(Some(Encoding::Windows1252), tokenizer.encoding());
+        assert_eq!("caf\u{E9} \u{20AC}", collect_chars(tokenizer));
+    }
+
+    #[test]
+    fn test_streaming_with_transport_encoding() {
+        let bytes = b"<p title='\x1B$BF|\x1B(B'>\x1B$BK\\8l\x1B(B</p>";
+        let expected: Vec<HtmlToken> =
+            HtmlTokenizer::from_bytes(bytes, Some(Encoding::Iso2022Jp)).collect();
+
+        for i in 0..=bytes.len() {
+            let mut tokenizer = HtmlTokenizer::new_streaming();
+            tokenizer.set_transport_encoding(Encoding::Iso2022Jp);
+            let mut tokens = Vec::new();
+            tokenizer.push_bytes(&bytes[..i]);
+            tokens.extend(tokenizer.by_ref());
+            tokenizer.push_bytes(&bytes[i..]);
+            tokens.extend(tokenizer.by_ref());
+            tokenizer.close();
+            tokens.extend(tokenizer.by_ref());
+            assert_eq!(expected, tokens, "split at {}", i);
+        }
+        assert_eq!(HtmlToken::Char('\u{672C}'), expected[1],);
+    }
+
+    fn collect_chars(tokenizer: HtmlTokenizer) -> String {
+        let mut s = String::new();
+        for token in tokenizer {
+            if let HtmlToken::Char(c) = token {
+                s.push(c);
+            }
+        }
+        s
+    }
+
+    #[test]
+    fn test_named_character_reference() {
+        let html = "a&amp;b&lt;&gt;&quot;&NotEqualTilde;".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        assert_eq!("a&b<>\"\u{2242}\u{338}", collect_chars(tokenizer));
+    }
+
+    #[test]
+    fn test_named_character_reference_without_semicolon() {
+        let html = "&amp &notit; &notin; &ampx &foo; &".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        assert_eq!("& \u{ac}it; \u{2209} &x &foo; &", collect_chars(tokenizer));
+    }
+
+    #[test]
+    fn test_numeric_character_reference() {
+        let html = "&#x3042;&#12354;&#X41&#65;&#0;&#x110000;&#xD800;&#x80;&#;&#x;".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            "\u{3042}\u{3042}AA\u{fffd}\u{fffd}\u{fffd}\u{20ac}&#;&#x;",
+            collect_chars(tokenizer)
+        );
+    }
+
+    #[test]
+    fn test_character_reference_in_attribute() {
+        let html = "<a title=\"&lt;&#65;\" href='?a=1&copy=2&amp;b' x=&amp;y></a>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [("title", "<A"), ("href", "?a=1&copy=2&b"), ("x", "&y")];
+
+        match tokenizer.next() {
+            Some(HtmlToken::StartTag { attributes, .. }) => {
+                assert_eq!(expected.len(), attributes.len());
+                for (attr, (name, value)) in attributes.iter().zip(expected) {
+                    assert_eq!(name, attr.name());
+                    assert_eq!(value, attr.value());
+                }
+            }
+            t => panic!("unexpected token: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_comment() {
+        let html = "<!-- a -- b --><!---><!--x--!><?php echo ?></ bogus>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Comment(" a -- b ".to_string()),
+            HtmlToken::Comment("".to_string()),
+            HtmlToken::Comment("x".to_string()),
+            HtmlToken::Comment("?php echo ?".to_string()),
+            HtmlToken::Comment(" bogus".to_string()),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_comment_eof() {
+        let html = "<!-- abc".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Comment(" abc".to_string())),
+            tokenizer.next()
+        );
+        assert_eq!(Some(HtmlToken::Eof), tokenizer.next());
+    }
+
+    #[test]
+    fn test_doctype() {
+        let html = "<!DOCTYPE html><!doctype HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'http://www.w3.org/TR/html4/strict.dtd'><!DOCTYPE>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Doctype {
+                name: Some("html".to_string()),
+                public_id: None,
+                system_id: None,
+                force_quirks: false,
+            },
+            HtmlToken::Doctype {
+                name: Some("html".to_string()),
+                public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+                system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+                force_quirks: false,
+            },
+            HtmlToken::Doctype {
+                name: None,
+                public_id: None,
+                system_id: None,
+                force_quirks: true,
+            },
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
 }
@@ -274,16 +274,14 @@ pub enum DisplayType {
 
 impl DisplayType {
     fn deault(node: &Rc<RefCell<Node>>) -> Self {
-        match &node.borrow().kind() {
-            NodeKind::Document => DisplayType::Block,
-            NodeKind::Element(e) => {
-                if e.is_block_element() {
-                    DisplayType::Block
-                } else {
-                    DisplayType::Inline
-                }
-            }
-            NodeKind::Text(_) => DisplayType::Inline,
+        let node = node.borrow();
+        if node.is_hidden_by_default() {
+            return DisplayType::DisplayNone;
+        }
+        match node.kind {
+            NodeKind::Document | NodeKind::DocumentFragment => DisplayType::Block,
+            NodeKind::Element(ref e) if e.is_block_element() => DisplayType::Block,
+            _ => DisplayType::Inline,
         }
     }
 
@@ -8,6 +8,8 @@ use crate::renderer::css::cssom::ComponentValue;
 use crate::renderer::css::cssom::Declaration;
 use crate::renderer::css::cssom::Selector;
 use crate::renderer::css::cssom::StyleSheet;
+use crate::renderer::dom::api::matches_selector;
+use crate::renderer::dom::inner_text::collapse_whitespace;
 use crate::renderer::dom::node::Node;
 use crate::renderer::dom::node::NodeKind;
 use crate::renderer::layout::computed_style::Color;
@@ -57,6 +59,10 @@ impl LayoutObject {
         self.kind
     }
 
+    pub fn node(&self) -> Rc<RefCell<Node>> {
+        self.node.clone()
+    }
+
     pub fn node_kind(&self) -> NodeKind {
         self.node.borrow().kind().clone()
     }
@@ -94,34 +100,7 @@ impl LayoutObject {
     }
 
     pub fn is_node_selected(&self, selector: &Selector) -> bool {
-        match &self.node_kind() {
-            NodeKind::Element(e) => match selector {
-                Selector::TypeSelector(type_name) => {
-                    if e.kind().to_string() == *type_name {
-                        return true;
-                    }
-                    false
-                }
-                Selector::ClassSelector(class_name) => {
-                    for attr in &e.attributes() {
-                        if attr.name() == "class" && attr.value() == *class_name {
-                            return true;
-                        }
-                    }
-                    false
-                }
-                Selector::IdSelector(id_name) => {
-                    for attr in &e.attributes() {
-                        if attr.name() == "id" && attr.value() == *id_name {
-                            return true;
-                        }
-                    }
-                    false
-                }
-                Selector::UnknownSelector => false,
-            },
-            _ => false,
-        }
+        matches_selector(&self.node, selector)
     }
 
     pub fn cascading_style(&mut self, declarations: Vec<Declaration>) {
@@ -187,7 +166,9 @@ impl LayoutObject {
 
     pub fn update_kind(&mut self) {
         match self.node_kind() {
-            NodeKind::Document => panic!("should not create a layout object for a Document node"),
+            NodeKind::Document | NodeKind::DocumentFragment => {
+                panic!("should not create a layout object for a Document or DocumentFragment node")
+            }
             NodeKind::Element(_) => {
                 let display = self.style.display();
                 match display {
@@ -199,6 +180,9 @@ impl LayoutObject {
                 }
             }
             NodeKind::Text(_) => self.kind = LayoutObjectKind::Text,
+            NodeKind::DocumentType(_) | NodeKind::Comment(_) => {
+                panic!("should not create a layout object for a DocumentType or Comment node")
+            }
         }
     }
 
@@ -336,7 +320,7 @@ impl LayoutObject {
                         FontSize::XXLarge => 3,
                     };
 
-                    let plain_text = t.replace("\n", "").split(' ').collect::<Vec<_>>().join(" ");
+                    let plain_text = collapse_whitespace(&t);
                     let lines = split_text(plain_text, CHAR_WIDTH * ratio);
                     let mut i = 0;
                     for line in lines {
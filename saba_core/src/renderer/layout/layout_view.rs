@@ -2,14 +2,19 @@ use crate::constants::CONTENT_AREA_WIDTH;
 use crate::display_item::DisplayItem;
 use crate::renderer::css::cssom::StyleSheet;
 use crate::renderer::dom::api::get_target_element_node;
+use crate::renderer::dom::inner_text::ElementEnd;
+use crate::renderer::dom::inner_text::InnerTextBuilder;
 use crate::renderer::dom::node::ElementKind;
 use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::NodeKind;
+use crate::renderer::dom::traversal::following_siblings;
 use crate::renderer::layout::layout_object::create_layout_object;
 use crate::renderer::layout::layout_object::LayoutObject;
 use crate::renderer::layout::layout_object::LayoutObjectKind;
 use crate::renderer::layout::layout_object::LayoutPoint;
 use crate::renderer::layout::layout_object::LayoutSize;
 use alloc::rc::Rc;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 
@@ -116,6 +121,45 @@ impl LayoutView {
         Self::paint_node(&self.root, &mut display_items);
         display_items
     }
+
+    /// Returns the rendered text of the page. Unlike `inner_text` for a DOM node, elements
+    /// hidden by the style sheets are skipped and blocks are decided by the computed styles.
+    pub fn inner_text(&self) -> String {
+        enum Step {
+            Enter(Rc<RefCell<LayoutObject>>),
+            Leave(ElementEnd),
+        }
+
+        let mut builder = InnerTextBuilder::new();
+        let mut stack: Vec<Step> = self.root.iter().cloned().map(Step::Enter).collect();
+        while let Some(step) = stack.pop() {
+            let obj = match step {
+                Step::Enter(obj) => obj,
+                Step::Leave(end) => {
+                    builder.leave_element(end);
+                    continue;
+                }
+            };
+            match obj.borrow().node_kind() {
+                NodeKind::Text(text) => builder.push_text(&text),
+                NodeKind::Element(element) => {
+                    let is_block = obj.borrow().kind() == LayoutObjectKind::Block;
+                    let end = builder.enter_element(&element, is_block);
+                    stack.push(Step::Leave(end));
+                }
+                _ => {}
+            }
+
+            let mut children = Vec::new();
+            let mut child = obj.borrow().first_child();
+            while let Some(c) = child {
+                child = c.borrow().next_sibling();
+                children.push(Step::Enter(c));
+            }
+            stack.extend(children.into_iter().rev());
+        }
+        builder.finish()
+    }
 }
 
 fn build_layout_tree(
@@ -123,73 +167,67 @@ fn build_layout_tree(
     parent_obj: &Option<Rc<RefCell<LayoutObject>>>,
     cssom: &StyleSheet,
 ) -> Option<Rc<RefCell<LayoutObject>>> {
-    let mut target_node = node.clone();
-    let mut layout_obj = create_layout_object(node, parent_obj, cssom);
-
-    while layout_obj.is_none() {
-        if let Some(n) = target_node {
-            target_node = n.borrow().next_sibling().clone();
-            layout_obj = create_layout_object(&target_node, parent_obj, cssom);
-        } else {
-            return layout_obj;
-        }
+    let mut pending = build_siblings(node, parent_obj, cssom);
+    let root = pending.first().cloned();
+
+    // Build the children of each layout object with a stack instead of recursion, so that a
+    // deeply nested DOM tree doesn't overflow the stack.
+    while let Some(obj) = pending.pop() {
+        let first_child = obj.borrow().node().borrow().first_child();
+        let children = build_siblings(&first_child, &Some(obj.clone()), cssom);
+        obj.borrow_mut().set_first_child(children.first().cloned());
+        pending.extend(children.into_iter().rev());
     }
 
-    if let Some(n) = target_node {
-        let original_first_child = n.borrow().first_child().clone();
-        let original_next_sibling = n.borrow().next_sibling();
-        let mut first_child = build_layout_tree(&original_first_child, &layout_obj, cssom);
-        let mut next_sibling = build_layout_tree(&original_next_sibling, parent_obj, cssom);
-
-        if first_child.is_none() && original_first_child.is_some() {
-            let mut original_dom_node = original_first_child
-                .expect("next sibling should exist")
-                .borrow()
-                .next_sibling();
-
-            loop {
-                first_child = build_layout_tree(&original_dom_node, &layout_obj, cssom);
-                if first_child.is_none() && original_dom_node.is_some() {
-                    original_dom_node = original_dom_node
-                        .expect("next sibling should exist")
-                        .borrow()
-                        .next_sibling();
-                    continue;
-                }
+    root
+}
 
-                break;
-            }
+/// Creates the layout objects for `node` and its following siblings and links them together.
+/// Nodes that don't create a layout object are skipped with their descendants.
+fn build_siblings(
+    node: &Option<Rc<RefCell<Node>>>,
+    parent_obj: &Option<Rc<RefCell<LayoutObject>>>,
+    cssom: &StyleSheet,
+) -> Vec<Rc<RefCell<LayoutObject>>> {
+    let node = match node {
+        Some(n) => n,
+        None => return Vec::new(),
+    };
+
+    let siblings = core::iter::once(node.clone()).chain(following_siblings(node));
+    let created: Vec<Rc<RefCell<LayoutObject>>> = siblings
+        .filter_map(|n| create_layout_object(&Some(n), parent_obj, cssom))
+        .collect();
+
+    // Whitespace-only text, such as indentation between tags, collapses away next to a block
+    // and at the start and the end of a block. Whitespace between inline boxes is kept.
+    let in_block = match parent_obj {
+        Some(p) => p.borrow().kind() == LayoutObjectKind::Block,
+        None => true,
+    };
+    let is_block = |obj: Option<&Rc<RefCell<LayoutObject>>>| match obj {
+        Some(obj) => obj.borrow().kind() == LayoutObjectKind::Block,
+        None => in_block,
+    };
+    let mut objects: Vec<Rc<RefCell<LayoutObject>>> = Vec::new();
+    for (i, obj) in created.iter().enumerate() {
+        let previous = i.checked_sub(1).and_then(|i| created.get(i));
+        if is_whitespace_text(obj) && (is_block(previous) || is_block(created.get(i + 1))) {
+            continue;
         }
-
-        if next_sibling.is_none() && n.borrow().next_sibling().is_some() {
-            let mut original_dom_node = original_next_sibling
-                .expect("first child should exist")
-                .borrow()
-                .next_sibling();
-
-            loop {
-                next_sibling = build_layout_tree(&original_dom_node, &None, cssom);
-                if next_sibling.is_none() && original_dom_node.is_some() {
-                    original_dom_node = original_dom_node
-                        .expect("next sibling should exist")
-                        .borrow()
-                        .next_sibling();
-                    continue;
-                }
-
-                break;
-            }
+        if let Some(previous) = objects.last() {
+            previous.borrow_mut().set_next_sibling(Some(obj.clone()));
         }
-
-        let obj = match layout_obj {
-            Some(ref obj) => obj,
-            None => panic!("render object should exist here"),
-        };
-        obj.borrow_mut().set_first_child(first_child);
-        obj.borrow_mut().set_next_sibling(next_sibling);
+        objects.push(obj.clone());
     }
+    objects
+}
 
-    layout_obj
+fn is_whitespace_text(obj: &Rc<RefCell<LayoutObject>>) -> bool {
+    match obj.borrow().node().borrow().kind {
+        NodeKind::Text(ref text) => text.chars().all(|c| c.is_ascii_whitespace()),
+        _ => false,
+    }
 }
 
 #[cfg(test)]
@@ -219,8 +257,14 @@ mod test {
 
     #[test]
     fn test_empty() {
+        // An empty document still has implied html, head and body elements.
         let layout_view = create_layout_view("".to_string());
-        assert_eq!(None, layout_view.root());
+        let root = layout_view.root().expect("root should exist");
+        assert_eq!(
+            NodeKind::Element(Element::new("body", Vec::new())),
+            root.borrow().node_kind()
+        );
+        assert!(root.borrow().first_child().is_none());
     }
 
     #[test]
@@ -344,4 +388,37 @@ mod test {
             .next_sibling()
             .is_none());
     }
+
+    #[test]
+    fn test_whitespace_between_inline_boxes() {
+        let html = "<html><body>\n  <p><b>a</b> <i>b</i></p>\n</body></html>".to_string();
+        let layout_view = create_layout_view(html);
+
+        let root = layout_view.root().expect("root should exist");
+        let p = root.borrow().first_child().expect("p should exist");
+        assert!(p.borrow().next_sibling().is_none());
+        assert_eq!("a b", layout_view.inner_text());
+
+        let b = p.borrow().first_child().expect("b should exist");
+        let space = b.borrow().next_sibling().expect("space should exist");
+        assert_eq!(LayoutObjectKind::Text, space.borrow().kind());
+        assert_eq!(NodeKind::Text(" ".to_string()), space.borrow().node_kind());
+    }
+
+    #[test]
+    fn test_inner_text() {
+        let html = r#"<html>
+<head><style>.hidden { display: none; }</style></head>
+<body>
+  <p>a   <a>b</a>
+  c</p>
+  <p class="hidden">hidden</p>
+  <div>d<br>e</div>
+</body>
+</html>"#
+            .to_string();
+        let layout_view = create_layout_view(html);
+
+        assert_eq!("a b c\n\nd\ne", layout_view.inner_text());
+    }
 }
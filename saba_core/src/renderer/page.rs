@@ -6,6 +6,7 @@ use crate::renderer::css::cssom::StyleSheet;
 use crate::renderer::css::token::CssTokenizer;
 use crate::renderer::dom::api::get_style_content;
 use crate::renderer::dom::node::Window;
+use crate::renderer::html::encoding_sniffer::extract_encoding;
 use crate::renderer::html::parser::HtmlParser;
 use crate::renderer::html::token::HtmlTokenizer;
 use crate::renderer::layout::layout_view::LayoutView;
@@ -36,15 +37,19 @@ impl Page {
     }
 
     pub fn receive_response(&mut self, response: HttpResponse) -> String {
-        self.create_frame(response.body());
+        self.create_frame(&response);
 
         self.set_layout_view();
 
         self.paint_tree();
     }
 
-    fn create_frame(&mut self, html: String) {
-        let html_tokenizer = HtmlTokenizer::new(html);
+    fn create_frame(&mut self, response: &HttpResponse) {
+        let transport_encoding = response
+            .header_value("Content-Type")
+            .ok()
+            .and_then(|content_type| extract_encoding(&content_type));
+        let html_tokenizer = HtmlTokenizer::from_bytes(&response.body_bytes(), transport_encoding);
         let frame = HtmlParser::new(html_tokenizer).construct_tree();
         let dom = frame.borrow().document();
 
@@ -2,27 +2,23 @@ use crate::renderer::dom::node::Node;
 use alloc::format;
 use alloc::rc::Rc;
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::cell::RefCell;
 
 pub fn convert_dom_to_string(root: &Option<Rc<RefCell<Node>>>) -> String {
     let mut result = String::from("\n");
-    convert_dom_to_string_internal(root, 0, &mut result);
-    result
-}
-
-fn convert_dom_to_string_internal(
-    node: &Option<Rc<RefCell<Node>>>,
-    depth: usize,
-    result: &mut String,
-) {
-    match node {
-        Some(n) => {
-            result.push_str(&" ".repeat(depth));
-            result.push_str(&format!("{:?}", n.borrow().kind()));
-            result.push_str("\n");
-            convert_dom_to_string_internal(&n.borrow().first_child(), depth + 1, result);
-            convert_dom_to_string_internal(&n.borrow().next_sibling(), depth, result);
+    // The nodes to print with their depth. The siblings of `root` are printed too.
+    let mut stack: Vec<(Rc<RefCell<Node>>, usize)> = root.iter().map(|n| (n.clone(), 0)).collect();
+    while let Some((n, depth)) = stack.pop() {
+        result.push_str(&" ".repeat(depth));
+        result.push_str(&format!("{:?}", n.borrow().kind()));
+        result.push_str("\n");
+        if let Some(sibling) = n.borrow().next_sibling() {
+            stack.push((sibling, depth));
+        }
+        if let Some(child) = n.borrow().first_child() {
+            stack.push((child, depth + 1));
         }
-        None => {}
     }
+    result
 }
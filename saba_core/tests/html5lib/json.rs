@@ -0,0 +1,213 @@
+//! A minimal JSON parser for the html5lib-tests tokenizer fixtures. saba_core has no
+//! dependencies, so the harness doesn't pull in serde just for reading fixtures.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members are kept in the source order.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        input: input.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.input.len() {
+        return Err(format!("unexpected trailing input at {}", parser.pos));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    input: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or("unexpected end of input")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        let c = self.next()?;
+        if c != expected {
+            return Err(format!(
+                "expected {:?} but got {:?} at {}",
+                expected, c, self.pos
+            ));
+        }
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => Ok(Json::String(self.parse_string()?)),
+            Some('t') => self.parse_keyword("true", Json::Bool(true)),
+            Some('f') => self.parse_keyword("false", Json::Bool(false)),
+            Some('n') => self.parse_keyword("null", Json::Null),
+            Some(_) => self.parse_number(),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        for c in keyword.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            self.pos += 1;
+        }
+        let s: String = self.input[start..self.pos].iter().collect();
+        s.parse()
+            .map(Json::Number)
+            .map_err(|_| format!("invalid number {:?} at {}", s, start))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    '"' => s.push('"'),
+                    '\\' => s.push('\\'),
+                    '/' => s.push('/'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let high = self.parse_hex4()?;
+                        if (0xD800..0xDC00).contains(&high)
+                            && self.input[self.pos..].starts_with(&['\\', 'u'])
+                        {
+                            self.pos += 2;
+                            let low = self.parse_hex4()?;
+                            let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                            s.push(char::from_u32(c).unwrap_or('\u{FFFD}'));
+                        } else {
+                            // Lone surrogates can't be represented in a Rust string.
+                            s.push(char::from_u32(high).unwrap_or('\u{FFFD}'));
+                        }
+                    }
+                    c => return Err(format!("invalid escape {:?} at {}", c, self.pos)),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let c = self.next()?;
+            let digit = c
+                .to_digit(16)
+                .ok_or_else(|| format!("invalid hex digit {:?} at {}", c, self.pos))?;
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(Json::Array(values)),
+                c => return Err(format!("expected ',' or ']' but got {:?}", c)),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            members.push((key, value));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(Json::Object(members)),
+                c => return Err(format!("expected ',' or '}}' but got {:?}", c)),
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Conformance tests against the html5lib-tests fixtures in `fixtures/`.
+//!
+//! Each test prints a report of pass/fail counts. Run
+//! `cargo test --test html5lib -- --nocapture` to see it. A run fails if a fixture test fails
+//! without being listed in the expected failures of its runner, or passes while listed.
+
+mod json;
+mod tokenizer;
+mod tree_construction;
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Returns the fixture files in `fixtures/<dir>` with the extension `ext`, sorted by name.
+pub fn fixture_files(dir: &str, ext: &str) -> Vec<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/html5lib/fixtures")
+        .join(dir);
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e))
+        .map(|entry| entry.expect("failed to read a directory entry").path())
+        .filter(|path| path.extension().is_some_and(|e| e == ext))
+        .collect();
+    files.sort();
+    files
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counts {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+/// Pass/fail counts of a fixture set, grouped by fixture file and by another key such as the
+/// insertion modes used by the tree builder.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub total: Counts,
+    pub crashed: Vec<String>,
+    pub failures: Vec<String>,
+    /// The names of the failed tests, including the ones that crashed.
+    pub failed_tests: BTreeSet<String>,
+    by_file: BTreeMap<String, Counts>,
+    by_group: BTreeMap<String, Counts>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl Report {
+    pub fn record(&mut self, file: &str, groups: &[String], outcome: Outcome) {
+        self.total.add(outcome);
+        self.by_file
+            .entry(file.to_string())
+            .or_default()
+            .add(outcome);
+        for group in groups {
+            self.by_group.entry(group.clone()).or_default().add(outcome);
+        }
+    }
+
+    pub fn print(&self, title: &str, group_title: &str) {
+        println!("== {} ==", title);
+        for (file, c) in &self.by_file {
+            println!("  {:<32} {}", file, c);
+        }
+        if !self.by_group.is_empty() {
+            println!("  -- by {} --", group_title);
+            for (group, c) in &self.by_group {
+                println!("  {:<32} {}", group, c);
+            }
+        }
+        println!("  {:<32} {}", "total", self.total);
+        for failure in &self.failures {
+            println!("FAIL {}", failure);
+        }
+        for crash in &self.crashed {
+            println!("CRASH {}", crash);
+        }
+    }
+
+    /// Panics if a test crashed, if a test failed without being in `expected_failures`, or if
+    /// a test in `expected_failures` passed, so that the list is kept up to date.
+    pub fn assert_expected_failures(&self, expected_failures: &[&str]) {
+        assert!(
+            self.crashed.is_empty(),
+            "{} tests panicked:\n{}",
+            self.crashed.len(),
+            self.crashed.join("\n")
+        );
+
+        let expected: BTreeSet<String> = expected_failures.iter().map(|s| s.to_string()).collect();
+        let unexpected_failures: Vec<&String> = self.failed_tests.difference(&expected).collect();
+        let unexpected_passes: Vec<&String> = expected.difference(&self.failed_tests).collect();
+        assert!(
+            unexpected_failures.is_empty() && unexpected_passes.is_empty(),
+            "unexpected failures: {:?}\nexpected failures that passed: {:?}",
+            unexpected_failures,
+            unexpected_passes
+        );
+    }
+}
+
+impl Counts {
+    fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Passed => self.passed += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::Skipped => self.skipped += 1,
+        }
+    }
+}
+
+impl core::fmt::Display for Counts {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "passed {:>4}  failed {:>4}  skipped {:>4}",
+            self.passed, self.failed, self.skipped
+        )
+    }
+}
@@ -0,0 +1,298 @@
+//! Runs the tokenizer fixtures (`fixtures/tokenizer/*.test`).
+//! https://github.com/html5lib/html5lib-tests/tree/master/tokenizer
+
+use crate::fixture_files;
+use crate::json;
+use crate::json::Json;
+use crate::Outcome;
+use crate::Report;
+use saba_core::renderer::html::token::HtmlToken;
+use saba_core::renderer::html::token::HtmlTokenizer;
+use saba_core::renderer::html::token::State;
+use std::fs;
+use std::panic;
+
+/// Tests that are known to fail, named like "test1.test: Correct Doctype lowercase".
+const EXPECTED_FAILURES: &[&str] = &[];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Doctype {
+        name: Option<String>,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        correctness: bool,
+    },
+    StartTag {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    EndTag {
+        name: String,
+    },
+    Comment(String),
+    Character(String),
+}
+
+/// Returns the tokens and the names of the parse errors.
+fn tokenize(input: &str, state: State, last_start_tag: Option<&str>) -> (Vec<Token>, Vec<String>) {
+    let mut tokenizer = HtmlTokenizer::new(input.to_string());
+    tokenizer.switch_to(state);
+    if let Some(tag) = last_start_tag {
+        tokenizer.set_last_start_tag(tag);
+    }
+
+    let mut tokens = Vec::new();
+    for token in tokenizer.by_ref() {
+        let token = match token {
+            HtmlToken::Doctype {
+                name,
+                public_id,
+                system_id,
+                force_quirks,
+            } => Token::Doctype {
+                name,
+                public_id,
+                system_id,
+                correctness: !force_quirks,
+            },
+            HtmlToken::StartTag {
+                tag,
+                self_closing,
+                attributes,
+            } => {
+                let mut attributes: Vec<(String, String)> =
+                    attributes.iter().map(|a| (a.name(), a.value())).collect();
+                attributes.sort();
+                Token::StartTag {
+                    name: tag,
+                    attributes,
+                    self_closing,
+                }
+            }
+            HtmlToken::EndTag { tag } => Token::EndTag { name: tag },
+            HtmlToken::Comment(data) => Token::Comment(data),
+            HtmlToken::Char(c) => {
+                // Adjacent character tokens are compared as one string.
+                if let Some(Token::Character(s)) = tokens.last_mut() {
+                    s.push(c);
+                    continue;
+                }
+                Token::Character(c.to_string())
+            }
+            HtmlToken::Eof => break,
+        };
+        tokens.push(token);
+    }
+    let errors = tokenizer
+        .errors()
+        .iter()
+        .map(|e| e.kind.name().to_string())
+        .collect();
+    (tokens, errors)
+}
+
+fn expected_tokens(output: &Json, double_escaped: bool) -> Result<Vec<Token>, String> {
+    let string = |value: &Json| -> Result<String, String> {
+        let s = value.as_str().ok_or("expected a string")?;
+        Ok(if double_escaped {
+            unescape(s)
+        } else {
+            s.to_string()
+        })
+    };
+    let optional_string = |value: &Json| -> Result<Option<String>, String> {
+        match value {
+            Json::Null => Ok(None),
+            v => string(v).map(Some),
+        }
+    };
+
+    let mut tokens = Vec::new();
+    for token in output.as_array().ok_or("output should be an array")? {
+        let fields = token.as_array().ok_or("a token should be an array")?;
+        let kind = fields
+            .first()
+            .and_then(Json::as_str)
+            .ok_or("a token should start with its type")?;
+        let token = match kind {
+            "DOCTYPE" => Token::Doctype {
+                name: optional_string(&fields[1])?,
+                public_id: optional_string(&fields[2])?,
+                system_id: optional_string(&fields[3])?,
+                correctness: fields[4].as_bool().ok_or("expected a bool")?,
+            },
+            "StartTag" => {
+                let mut attributes = Vec::new();
+                if let Json::Object(members) = &fields[2] {
+                    for (name, value) in members {
+                        let name = if double_escaped {
+                            unescape(name)
+                        } else {
+                            name.clone()
+                        };
+                        attributes.push((name, string(value)?));
+                    }
+                }
+                attributes.sort();
+                Token::StartTag {
+                    name: string(&fields[1])?,
+                    attributes,
+                    self_closing: fields.get(3).and_then(Json::as_bool).unwrap_or(false),
+                }
+            }
+            "EndTag" => Token::EndTag {
+                name: string(&fields[1])?,
+            },
+            "Comment" => Token::Comment(string(&fields[1])?),
+            "Character" => {
+                let data = string(&fields[1])?;
+                if let Some(Token::Character(s)) = tokens.last_mut() {
+                    s.push_str(&data);
+                    continue;
+                }
+                Token::Character(data)
+            }
+            _ => return Err(format!("unknown token type {}", kind)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Returns the tokenizer state for a name in `initialStates`.
+fn initial_state(name: &str) -> Option<State> {
+    match name {
+        "Data state" => Some(State::Data),
+        "PLAINTEXT state" => Some(State::PlainText),
+        "RCDATA state" => Some(State::RcData),
+        "RAWTEXT state" => Some(State::RawText),
+        "Script data state" => Some(State::ScriptData),
+        _ => None,
+    }
+}
+
+/// Decodes the "\uXXXX" escapes used by tests with the `doubleEscaped` flag.
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek() == Some(&'u') {
+            chars.next();
+            let hex: String = chars.by_ref().take(4).collect();
+            let code = u32::from_str_radix(&hex, 16).unwrap_or(0xFFFD);
+            result.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[test]
+fn html5lib_tokenizer() {
+    let mut report = Report::default();
+
+    for path in fixture_files("tokenizer", "test") {
+        let file = path
+            .file_name()
+            .expect("fixture should have a file name")
+            .to_string_lossy()
+            .to_string();
+        let source = fs::read_to_string(&path).expect("failed to read a fixture");
+        let fixture =
+            json::parse(&source).unwrap_or_else(|e| panic!("failed to parse {}: {}", file, e));
+        let tests = fixture.get("tests").and_then(Json::as_array).unwrap_or(&[]);
+
+        for test in tests {
+            let description = test
+                .get("description")
+                .and_then(Json::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let name = format!("{}: {}", file, description);
+
+            // A test without initialStates starts in the data state. A test with several initial
+            // states passes only if it passes in all of them.
+            let initial_states: Option<Vec<State>> = match test.get("initialStates") {
+                Some(states) => states
+                    .as_array()
+                    .unwrap_or(&[])
+                    .iter()
+                    .map(|s| s.as_str().and_then(initial_state))
+                    .collect(),
+                None => Some(vec![State::Data]),
+            };
+            let initial_states = match initial_states {
+                Some(states) => states,
+                None => {
+                    report.record(&file, &[], Outcome::Skipped);
+                    continue;
+                }
+            };
+            let last_start_tag = test
+                .get("lastStartTag")
+                .and_then(Json::as_str)
+                .map(str::to_string);
+
+            let double_escaped = test
+                .get("doubleEscaped")
+                .and_then(Json::as_bool)
+                .unwrap_or(false);
+            let input = test.get("input").and_then(Json::as_str).unwrap_or_default();
+            let input = if double_escaped {
+                unescape(input)
+            } else {
+                input.to_string()
+            };
+            let expected = test
+                .get("output")
+                .ok_or_else(|| "missing output".to_string())
+                .and_then(|output| expected_tokens(output, double_escaped))
+                .unwrap_or_else(|e| panic!("{}: {}: {}", file, description, e));
+            // Only the names of errors are compared because positions are counted slightly
+            // differently, e.g. html5lib-tests reports some errors after the current character.
+            let expected_errors: Vec<String> = test
+                .get("errors")
+                .and_then(Json::as_array)
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(|e| e.get("code").and_then(Json::as_str))
+                .map(str::to_string)
+                .collect();
+
+            let mut outcome = Outcome::Passed;
+            for state in initial_states {
+                let result =
+                    panic::catch_unwind(|| tokenize(&input, state, last_start_tag.as_deref()));
+                let (actual, actual_errors) = match result {
+                    Ok(result) => result,
+                    Err(_) => {
+                        report
+                            .crashed
+                            .push(format!("{}: {} ({:?})", file, description, state));
+                        outcome = Outcome::Failed;
+                        break;
+                    }
+                };
+
+                if actual != expected || actual_errors != expected_errors {
+                    report.failures.push(format!(
+                        "{}: {} ({:?})\n  input:    {:?}\n  expected: {:?} {:?}\n  actual:   {:?} {:?}",
+                        file, description, state, input, expected, expected_errors, actual, actual_errors
+                    ));
+                    outcome = Outcome::Failed;
+                    break;
+                }
+            }
+            if outcome == Outcome::Failed {
+                report.failed_tests.insert(name);
+            }
+            report.record(&file, &[], outcome);
+        }
+    }
+
+    report.print("html5lib tokenizer tests", "");
+    report.assert_expected_failures(EXPECTED_FAILURES);
+}
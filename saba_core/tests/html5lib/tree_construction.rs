@@ -0,0 +1,242 @@
+//! Runs the tree construction fixtures (`fixtures/tree-construction/*.dat`).
+//! https://github.com/html5lib/html5lib-tests/tree/master/tree-construction
+
+use crate::fixture_files;
+use crate::Outcome;
+use crate::Report;
+use core::cell::RefCell;
+use saba_core::renderer::dom::node::Element;
+use saba_core::renderer::dom::node::Namespace;
+use saba_core::renderer::dom::node::Node;
+use saba_core::renderer::dom::node::NodeKind;
+use saba_core::renderer::html::attribute::AttributeNamespace;
+use saba_core::renderer::html::parser::HtmlParser;
+use saba_core::renderer::html::token::HtmlTokenizer;
+use std::fs;
+use std::panic;
+use std::rc::Rc;
+
+/// Tests that are known to fail, named like "tests1.dat #3".
+const EXPECTED_FAILURES: &[&str] = &[];
+
+#[derive(Debug, Default)]
+struct TestCase {
+    data: String,
+    document: String,
+    fragment_context: Option<String>,
+    script_on: bool,
+}
+
+/// Splits a .dat file into test cases. Each test starts with a "#data" line, and sections
+/// other than #data, #document and #document-fragment are ignored.
+fn parse_dat(source: &str) -> Vec<TestCase> {
+    let mut tests = Vec::new();
+    let mut section = "";
+    let mut data: Vec<&str> = Vec::new();
+    let mut document: Vec<&str> = Vec::new();
+    let mut test = TestCase::default();
+
+    let finish = |test: &mut TestCase, data: &mut Vec<&str>, document: &mut Vec<&str>| {
+        // The blank line separating tests isn't part of the expected tree.
+        if document.last() == Some(&"") {
+            document.pop();
+        }
+        test.data = data.join("\n");
+        test.document = document.join("\n");
+        data.clear();
+        document.clear();
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        if line == "#data" && (i == 0 || section == "#document") {
+            if i != 0 {
+                finish(&mut test, &mut data, &mut document);
+                tests.push(core::mem::take(&mut test));
+            }
+            section = "#data";
+            continue;
+        }
+
+        if matches!(
+            line,
+            "#errors" | "#new-errors" | "#document" | "#document-fragment" | "#script-off"
+        ) {
+            section = line;
+            continue;
+        }
+        if line == "#script-on" {
+            test.script_on = true;
+            section = line;
+            continue;
+        }
+
+        match section {
+            "#data" => data.push(line),
+            "#document" => document.push(line),
+            "#document-fragment" => test.fragment_context = Some(line.to_string()),
+            _ => {}
+        }
+    }
+
+    if !data.is_empty() || !document.is_empty() {
+        finish(&mut test, &mut data, &mut document);
+        tests.push(test);
+    }
+    tests
+}
+
+/// Serializes the children of `node` in the format used by the #document section.
+fn serialize_children(node: &Rc<RefCell<Node>>, depth: usize, lines: &mut Vec<String>) {
+    let mut child = node.borrow().first_child();
+    while let Some(c) = child {
+        serialize_node(&c, depth, lines);
+        child = c.borrow().next_sibling();
+    }
+}
+
+fn serialize_node(node: &Rc<RefCell<Node>>, depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    match node.borrow().kind {
+        NodeKind::Document | NodeKind::DocumentFragment => {}
+        NodeKind::DocumentType(ref doctype) => {
+            if doctype.public_id().is_empty() && doctype.system_id().is_empty() {
+                lines.push(format!("| {}<!DOCTYPE {}>", indent, doctype.name()));
+            } else {
+                lines.push(format!(
+                    "| {}<!DOCTYPE {} \"{}\" \"{}\">",
+                    indent,
+                    doctype.name(),
+                    doctype.public_id(),
+                    doctype.system_id()
+                ));
+            }
+        }
+        NodeKind::Element(ref element) => {
+            let prefix = match element.namespace() {
+                Namespace::Html => "",
+                Namespace::Svg => "svg ",
+                Namespace::MathMl => "math ",
+            };
+            lines.push(format!("| {}<{}{}>", indent, prefix, element.local_name()));
+
+            let mut attributes: Vec<(String, String)> = element
+                .attributes()
+                .iter()
+                .map(|a| {
+                    // Namespaced attributes are written like "xlink href".
+                    let name = match a.namespace() {
+                        Some(AttributeNamespace::XLink) => format!("xlink {}", a.local_name()),
+                        Some(AttributeNamespace::Xml) => format!("xml {}", a.local_name()),
+                        Some(AttributeNamespace::Xmlns) => format!("xmlns {}", a.local_name()),
+                        None => a.name(),
+                    };
+                    (name, a.value())
+                })
+                .collect();
+            attributes.sort();
+            for (name, value) in attributes {
+                lines.push(format!("| {}  {}=\"{}\"", indent, name, value));
+            }
+            if let Some(contents) = element.template_contents() {
+                lines.push(format!("| {}  content", indent));
+                serialize_children(&contents, depth + 2, lines);
+            }
+        }
+        NodeKind::Text(ref text) => lines.push(format!("| {}\"{}\"", indent, text)),
+        NodeKind::Comment(ref data) => lines.push(format!("| {}<!-- {} -->", indent, data)),
+    }
+    serialize_children(node, depth + 1, lines);
+}
+
+/// Parses `data` as a document and returns the serialized tree and the insertion modes used.
+fn parse_document(data: &str) -> (String, Vec<String>) {
+    let mut parser = HtmlParser::new(HtmlTokenizer::new(data.to_string()));
+    let window = parser.construct_tree();
+    let mut lines = Vec::new();
+    serialize_children(&window.borrow().document(), 0, &mut lines);
+    let modes = parser
+        .used_insertion_modes()
+        .iter()
+        .map(|mode| format!("{:?}", mode))
+        .collect();
+    (lines.join("\n"), modes)
+}
+
+/// Parses `data` in the context of an element described by `context`, e.g. "td" or "svg path",
+/// and returns the serialized nodes and the insertion modes used.
+fn parse_fragment(data: &str, context: &str) -> (String, Vec<String>) {
+    let (namespace, local_name) = match context.split_once(' ') {
+        Some(("svg", name)) => (Namespace::Svg, name),
+        Some(("math", name)) => (Namespace::MathMl, name),
+        _ => (Namespace::Html, context),
+    };
+    let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(
+        Element::new_with_namespace(local_name, namespace, Vec::new()),
+    ))));
+
+    let mut parser = HtmlParser::new(HtmlTokenizer::new(data.to_string()));
+    let mut lines = Vec::new();
+    for node in parser.parse_fragment(&context) {
+        serialize_node(&node, 0, &mut lines);
+    }
+    let modes = parser
+        .used_insertion_modes()
+        .iter()
+        .map(|mode| format!("{:?}", mode))
+        .collect();
+    (lines.join("\n"), modes)
+}
+
+#[test]
+fn html5lib_tree_construction() {
+    let mut report = Report::default();
+
+    for path in fixture_files("tree-construction", "dat") {
+        let file = path
+            .file_name()
+            .expect("fixture should have a file name")
+            .to_string_lossy()
+            .to_string();
+        let source = fs::read_to_string(&path).expect("failed to read a fixture");
+
+        for (i, test) in parse_dat(&source).iter().enumerate() {
+            let name = format!("{} #{}", file, i + 1);
+
+            // Scripting isn't supported.
+            if test.script_on {
+                report.record(&file, &[], Outcome::Skipped);
+                continue;
+            }
+
+            let data = test.data.clone();
+            let context = test.fragment_context.clone();
+            let result = panic::catch_unwind(|| match context {
+                Some(ref context) => parse_fragment(&data, context),
+                None => parse_document(&data),
+            });
+            let (actual, modes) = match result {
+                Ok(result) => result,
+                Err(_) => {
+                    report.crashed.push(format!("{}: {:?}", name, test.data));
+                    report.failed_tests.insert(name);
+                    report.record(&file, &[], Outcome::Failed);
+                    continue;
+                }
+            };
+
+            if actual == test.document {
+                report.record(&file, &modes, Outcome::Passed);
+            } else {
+                report.failures.push(format!(
+                    "{}\n#data\n{}\n#expected\n{}\n#actual\n{}",
+                    name, test.data, test.document, actual
+                ));
+                report.failed_tests.insert(name);
+                report.record(&file, &modes, Outcome::Failed);
+            }
+        }
+    }
+
+    report.print("html5lib tree construction tests", "insertion mode");
+    report.assert_expected_failures(EXPECTED_FAILURES);
+}